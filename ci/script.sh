@@ -8,8 +8,11 @@ main() {
     # cross build --target $TARGET --release
     cd rlay-client && cargo build -p rlay-client --features backend_neo4j && cd ..
     cargo build -p rlay-backend
+    cargo build -p rlay-backend-embedded
+    cargo build -p rlay-backend-memory
     cargo build -p rlay-backend-neo4j
     cargo build -p rlay-backend-redisgraph
+    cargo build -p rlay-backend-sql
 
     if [ ! -z $DISABLE_TESTS ]; then
         return
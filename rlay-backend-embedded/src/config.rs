@@ -0,0 +1,34 @@
+use failure::Error;
+use std::path::Path;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmbeddedBackendConfig {
+    /// Path of the database directory. Relative paths are resolved against the `data_path` of
+    /// rlay-client.
+    #[serde(default = "default_path")]
+    pub path: String,
+}
+
+fn default_path() -> String {
+    "embedded".to_owned()
+}
+
+impl Default for EmbeddedBackendConfig {
+    fn default() -> Self {
+        Self {
+            path: default_path(),
+        }
+    }
+}
+
+impl EmbeddedBackendConfig {
+    /// Open the database inside the provided data directory.
+    ///
+    /// Only one handle to the database may be opened per process, so it should be opened once and
+    /// be passed around via the `SyncState`.
+    pub fn open_db<P: AsRef<Path>>(&self, data_path: P) -> Result<sled::Db, Error> {
+        let db_path = data_path.as_ref().join(&self.path);
+        trace!("Opening embedded database at \"{}\"", db_path.display());
+        Ok(sled::open(db_path)?)
+    }
+}
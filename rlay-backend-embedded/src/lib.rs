@@ -0,0 +1,370 @@
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+extern crate static_assertions as sa;
+
+pub mod config;
+
+use async_trait::async_trait;
+use cid::{Cid, ToCid};
+use failure::{err_msg, format_err, Error};
+use futures::future::{self, BoxFuture, FutureExt};
+use rlay_backend::metrics;
+use rlay_backend::relationships::{entity_relationships, resolve_entity_with};
+use rlay_backend::rpc::*;
+use rlay_backend::{BackendFromConfigAndSyncState, GetEntity, ResolveEntity};
+use rlay_ontology::prelude::*;
+use rustc_hex::{FromHex, ToHex};
+use serde_json::Value;
+use sled::Transactional;
+use std::collections::{HashMap, HashSet};
+
+use crate::config::EmbeddedBackendConfig;
+
+/// Map with CID of resolved entity as key, and Vec of all contained entities within the resolved
+/// entity as values.
+type ResolvedEntities = HashMap<String, Vec<Entity>>;
+
+/// Separator between the parts of index keys.
+///
+/// Hex-encoded CIDs, entity kinds and field names never contain it, so keys can be split on it
+/// and prefix scans can't match unrelated keys.
+const KEY_SEPARATOR: &str = "/";
+
+/// Value of index entries, for which only the key is relevant.
+const EMPTY_VALUE: &[u8] = &[];
+
+sa::assert_impl_all!(EmbeddedBackend: Send, Sync);
+/// Backend that persists entities in an embedded key-value store.
+///
+/// Entities are stored (in their web3 JSON format) keyed by their hex-encoded CID. Additional
+/// trees serve as secondary indexes:
+/// - `kinds`: `<kind>/<cid>`
+/// - `outgoing`: `<cid>/<field>/<referenced cid>`
+/// - `incoming`: `<referenced cid>/<field>/<cid>`
+#[derive(Clone)]
+pub struct EmbeddedBackend {
+    pub config: EmbeddedBackendConfig,
+    db: sled::Db,
+    entities: sled::Tree,
+    kinds: sled::Tree,
+    outgoing: sled::Tree,
+    incoming: sled::Tree,
+}
+
+#[derive(Clone)]
+pub struct SyncState {
+    pub db: Option<sled::Db>,
+}
+
+/// A entity that has been prepared to be written to the database.
+struct EntityEntry {
+    cid: Cid,
+    formatted_cid: String,
+    kind_name: String,
+    payload: Vec<u8>,
+    relationships: Vec<(String, String)>,
+}
+
+fn index_key(parts: &[&str]) -> String {
+    parts.join(KEY_SEPARATOR)
+}
+
+/// Prefix of all keys in a index whose first part is `part`.
+fn index_prefix(part: &str) -> String {
+    format!("{}{}", part, KEY_SEPARATOR)
+}
+
+/// Last part of a index key.
+fn index_key_last_part(key: &[u8]) -> Result<String, Error> {
+    let key = std::str::from_utf8(key)?;
+    key.rsplit(KEY_SEPARATOR)
+        .next()
+        .map(|n| n.to_owned())
+        .ok_or_else(|| format_err!("Malformed index key \"{}\"", key))
+}
+
+impl EmbeddedBackend {
+    pub fn from_config(config: EmbeddedBackendConfig, db: sled::Db) -> Result<Self, Error> {
+        Ok(Self {
+            entities: db.open_tree("entities")?,
+            kinds: db.open_tree("kinds")?,
+            outgoing: db.open_tree("outgoing")?,
+            incoming: db.open_tree("incoming")?,
+            config,
+            db,
+        })
+    }
+
+    /// Persist all writes that are only buffered in memory.
+    pub fn flush(&self) -> Result<(), Error> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn get_entity(&self, cid: &str) -> Result<Option<Entity>, Error> {
+        let _timer = metrics::start_query_timer("embedded", metrics::query::GET_ENTITY);
+        self.read_entity(cid)
+    }
+
+    /// Read an entity from the database, without recording it as a query of its own.
+    fn read_entity(&self, cid: &str) -> Result<Option<Entity>, Error> {
+        let payload = match self.entities.get(cid)? {
+            Some(payload) => payload,
+            None => return Ok(None),
+        };
+        let web3_entity: FormatWeb3<Entity> = serde_json::from_slice(&payload)?;
+        Ok(Some(web3_entity.0))
+    }
+
+    pub fn get_entities(&self, cids: Vec<String>) -> Result<Vec<Entity>, Error> {
+        let _timer = metrics::start_query_timer("embedded", metrics::query::GET_ENTITIES);
+        let mut seen_cids = HashSet::new();
+        let mut entities = Vec::new();
+        for cid in cids {
+            if !seen_cids.insert(cid.clone()) {
+                continue;
+            }
+            if let Some(entity) = self.read_entity(&cid)? {
+                entities.push(entity);
+            }
+        }
+        trace!("get_entities retrieved {} entities", entities.len());
+
+        Ok(entities)
+    }
+
+    fn store_entities(&self, entities: &[Entity]) -> Result<Vec<Cid>, Error> {
+        let _timer = metrics::start_query_timer("embedded", metrics::query::STORE_ENTITIES);
+        let entries = entities
+            .iter()
+            .map(|entity| -> Result<EntityEntry, Error> {
+                let cid = entity
+                    .to_cid()
+                    .map_err(|_| err_msg("Unable to calculate CID of entity"))?;
+                let kind_name: &str = entity.kind().into();
+                Ok(EntityEntry {
+                    formatted_cid: format!("0x{}", cid.to_bytes().to_hex()),
+                    cid,
+                    kind_name: kind_name.to_owned(),
+                    payload: serde_json::to_vec(&FormatWeb3(entity.clone()))?,
+                    relationships: entity_relationships(entity),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        (&self.entities, &self.kinds, &self.outgoing, &self.incoming)
+            .transaction(|(entities_tx, kinds_tx, outgoing_tx, incoming_tx)| {
+                for entry in &entries {
+                    let cid: &str = &entry.formatted_cid;
+                    let kind_key = index_key(&[entry.kind_name.as_str(), cid]);
+                    entities_tx.insert(cid.as_bytes(), entry.payload.as_slice())?;
+                    kinds_tx.insert(kind_key.as_bytes(), EMPTY_VALUE)?;
+                    for (field, related_cid) in &entry.relationships {
+                        let (field, related_cid) = (field.as_str(), related_cid.as_str());
+                        outgoing_tx.insert(
+                            index_key(&[cid, field, related_cid]).as_bytes(),
+                            EMPTY_VALUE,
+                        )?;
+                        incoming_tx.insert(
+                            index_key(&[related_cid, field, cid]).as_bytes(),
+                            EMPTY_VALUE,
+                        )?;
+                    }
+                }
+                Ok(())
+            })
+            .map_err(|err| format_err!("Unable to store entities: {:?}", err))?;
+
+        Ok(entries.into_iter().map(|entry| entry.cid).collect())
+    }
+
+    fn list_cids(&self, entity_kind: Option<&str>) -> Result<Vec<String>, Error> {
+        if let Some(kind) = entity_kind {
+            validate_entity_kind(kind)?;
+        }
+        let _timer = metrics::start_query_timer("embedded", metrics::query::QUERY_ENTITIES);
+        match entity_kind {
+            None => self
+                .entities
+                .iter()
+                .map(|entry| -> Result<String, Error> {
+                    let (key, _) = entry?;
+                    Ok(String::from_utf8(key.to_vec())?)
+                })
+                .collect(),
+            Some(kind) => self
+                .kinds
+                .scan_prefix(index_prefix(kind))
+                .map(|entry| -> Result<String, Error> {
+                    let (key, _) = entry?;
+                    index_key_last_part(&key)
+                })
+                .collect(),
+        }
+    }
+
+    /// CIDs of all entities that reference the entity with the provided CID via `field`.
+    fn referencing_cids(&self, cid: &str, field: &str) -> Result<Vec<String>, Error> {
+        self.incoming
+            .scan_prefix(index_prefix(&index_key(&[cid, field])))
+            .map(|entry| -> Result<String, Error> {
+                let (key, _) = entry?;
+                index_key_last_part(&key)
+            })
+            .collect()
+    }
+
+    pub fn resolve_entities(&self, cids: Vec<String>) -> Result<ResolvedEntities, Error> {
+        let _timer = metrics::start_query_timer("embedded", metrics::query::RESOLVE_ENTITIES);
+        let mut resolved_entities = ResolvedEntities::new();
+        for cid in cids {
+            let entities = resolve_entity_with(
+                &cid,
+                |related_cid| self.read_entity(related_cid),
+                |subject_cid| self.referencing_cids(subject_cid, "subject"),
+            )?;
+            if let Some(entities) = entities {
+                resolved_entities.insert(cid, entities);
+            }
+        }
+        trace!(
+            "resolve_entities retrieved {} entities",
+            resolved_entities.len()
+        );
+
+        Ok(resolved_entities)
+    }
+
+    /// Run `f` with a clone of the backend on the blocking thread pool, as the database may block
+    /// on disk I/O.
+    fn run_blocking<T, F>(&self, f: F) -> BoxFuture<'static, Result<T, Error>>
+    where
+        T: Send + 'static,
+        F: FnOnce(Self) -> Result<T, Error> + Send + 'static,
+    {
+        let backend = self.clone();
+        tokio::task::spawn_blocking(move || f(backend))
+            .map(|res| match res {
+                Ok(res) => res,
+                Err(err) => Err(err.into()),
+            })
+            .boxed()
+    }
+}
+
+impl BackendFromConfigAndSyncState for EmbeddedBackend {
+    type C = EmbeddedBackendConfig;
+    type S = SyncState;
+    type R = future::Ready<Result<Self, Error>>;
+
+    fn from_config_and_syncstate(config: Self::C, sync_state: Self::S) -> Self::R {
+        let backend = sync_state
+            .db
+            .ok_or_else(|| err_msg("No database has been opened for the embedded backend"))
+            .and_then(|db| Self::from_config(config, db));
+        future::ready(backend)
+    }
+}
+
+#[async_trait]
+impl GetEntity for EmbeddedBackend {
+    async fn get_entity(&self, cid: &[u8]) -> Result<Option<Entity>, Error> {
+        let cid = format!("0x{}", cid.to_hex());
+        self.run_blocking(move |backend| backend.get_entity(&cid))
+            .await
+    }
+}
+
+#[async_trait]
+impl ResolveEntity for EmbeddedBackend {
+    async fn resolve_entity(&self, cid: &[u8]) -> Result<HashMap<Vec<u8>, Vec<Entity>>, Error> {
+        let cid = format!("0x{}", cid.to_hex());
+        let resolved_entities = self
+            .run_blocking(move |backend| backend.resolve_entities(vec![cid]))
+            .await?;
+        resolved_entities
+            .into_iter()
+            .map(|(old_key, value)| {
+                let key = old_key
+                    .trim_start_matches("0x")
+                    .from_hex()
+                    .map_err(|_| format_err!("Invalid CID {}", old_key))?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+}
+
+impl BackendRpcMethodGetEntity for EmbeddedBackend {
+    fn get_entity(&mut self, cid: &str) -> BoxFuture<Result<Option<Entity>, Error>> {
+        let cid = cid.to_owned();
+        self.run_blocking(move |backend| backend.get_entity(&cid))
+    }
+}
+
+impl BackendRpcMethodGetEntities for EmbeddedBackend {
+    fn get_entities(&mut self, cids: Vec<String>) -> BoxFuture<Result<Vec<Entity>, Error>> {
+        self.run_blocking(move |backend| backend.get_entities(cids))
+    }
+}
+
+impl BackendRpcMethodStoreEntity for EmbeddedBackend {
+    fn store_entity(
+        &mut self,
+        entity: &Entity,
+        _options_object: &Value,
+    ) -> BoxFuture<Result<Cid, Error>> {
+        let entity = entity.to_owned();
+        self.run_blocking(move |backend| {
+            backend
+                .store_entities(&[entity])
+                .map(|mut cids| cids.remove(0))
+        })
+    }
+}
+
+impl BackendRpcMethodStoreEntities for EmbeddedBackend {
+    fn store_entities(
+        &mut self,
+        entities: &Vec<Entity>,
+        _options_object: &Value,
+    ) -> BoxFuture<Result<Vec<Cid>, Error>> {
+        let entities = entities.to_owned();
+        self.run_blocking(move |backend| backend.store_entities(&entities))
+    }
+}
+
+impl BackendRpcMethodListCids for EmbeddedBackend {
+    fn list_cids(&mut self, entity_kind: Option<&str>) -> BoxFuture<Result<Vec<String>, Error>> {
+        let entity_kind = entity_kind.map(ToOwned::to_owned);
+        self.run_blocking(move |backend| backend.list_cids(entity_kind.as_deref()))
+    }
+}
+
+impl BackendRpcMethodResolveEntity for EmbeddedBackend {
+    fn resolve_entity(
+        &mut self,
+        cid: &str,
+    ) -> BoxFuture<Result<HashMap<String, Vec<Entity>>, Error>> {
+        let cid = cid.to_owned();
+        self.run_blocking(move |backend| backend.resolve_entities(vec![cid]))
+    }
+}
+
+impl BackendRpcMethodResolveEntities for EmbeddedBackend {
+    fn resolve_entities(
+        &mut self,
+        cids: Vec<String>,
+    ) -> BoxFuture<Result<HashMap<String, Vec<Entity>>, Error>> {
+        self.run_blocking(move |backend| backend.resolve_entities(cids))
+    }
+}
+
+impl BackendRpcMethodNeo4jQuery for EmbeddedBackend {}
+impl BackendRpcMethods for EmbeddedBackend {
+    fn unsupported_rpc_methods(&self) -> Vec<&'static str> {
+        vec!["neo4j_query"]
+    }
+}
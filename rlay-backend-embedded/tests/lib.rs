@@ -0,0 +1,50 @@
+use rlay_backend::rpc::*;
+use rlay_backend_embedded::*;
+use rlay_ontology::prelude::*;
+use rustc_hex::ToHex;
+use serde_json::Value;
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+fn embedded_backend(data_dir: &TempDir) -> EmbeddedBackend {
+    let backend_config = config::EmbeddedBackendConfig::default();
+    let db = backend_config.open_db(data_dir.path()).unwrap();
+    EmbeddedBackend::from_config(backend_config, db).unwrap()
+}
+
+#[test]
+fn entities_persist_across_reopening() {
+    let _ = env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+
+    let inserted_entity = Annotation::default().into();
+    let formatted_cid = {
+        let mut backend = embedded_backend(&data_dir);
+        let inserted_cid = rt
+            .block_on(backend.store_entity(&inserted_entity, &Value::Null))
+            .unwrap();
+        backend.flush().unwrap();
+        format!("0x{}", inserted_cid.to_bytes().to_hex())
+    };
+
+    let mut backend = embedded_backend(&data_dir);
+    let retrieved_entity = rt
+        .block_on(BackendRpcMethodGetEntity::get_entity(
+            &mut backend,
+            &formatted_cid,
+        ))
+        .unwrap();
+
+    assert_eq!(Some(inserted_entity), retrieved_entity);
+}
+
+#[test]
+fn conformance_suite_passes() {
+    let _ = env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let mut backend = embedded_backend(&data_dir);
+
+    rt.block_on(rlay_backend::conformance::run_all(&mut backend));
+}
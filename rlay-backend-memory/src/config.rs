@@ -0,0 +1,17 @@
+use std::sync::{Arc, RwLock};
+
+use crate::EntityMap;
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MemoryBackendConfig {}
+
+impl MemoryBackendConfig {
+    /// Create a new empty entity store.
+    ///
+    /// All backends created from the same store share their entities, so the store should be
+    /// created once and be passed around via the `SyncState`.
+    pub fn store(&self) -> Arc<RwLock<EntityMap>> {
+        trace!("Creating new in-memory entity store");
+        Arc::new(RwLock::new(EntityMap::new()))
+    }
+}
@@ -0,0 +1,246 @@
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+extern crate static_assertions as sa;
+
+pub mod config;
+
+use async_trait::async_trait;
+use cid::{Cid, ToCid};
+use failure::{err_msg, format_err, Error};
+use futures::future::{self, BoxFuture, FutureExt};
+use rlay_backend::metrics;
+use rlay_backend::relationships::{entity_relationships, resolve_entity_with};
+use rlay_backend::rpc::*;
+use rlay_backend::{BackendFromConfigAndSyncState, GetEntity, ResolveEntity};
+use rlay_ontology::prelude::*;
+use rustc_hex::{FromHex, ToHex};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use crate::config::MemoryBackendConfig;
+
+/// Map of all stored entities, with their hex-encoded CID as key.
+pub type EntityMap = BTreeMap<String, Entity>;
+
+/// Map with CID of resolved entity as key, and Vec of all contained entities within the resolved
+/// entity as values.
+type ResolvedEntities = HashMap<String, Vec<Entity>>;
+
+sa::assert_impl_all!(MemoryBackend: Send, Sync);
+/// Backend that keeps all entities in the memory of the running process.
+///
+/// Nothing is persisted, so this is mainly useful for tests and local demos.
+#[derive(Clone)]
+pub struct MemoryBackend {
+    pub config: MemoryBackendConfig,
+    store: Arc<RwLock<EntityMap>>,
+}
+
+#[derive(Clone)]
+pub struct SyncState {
+    pub store: Option<Arc<RwLock<EntityMap>>>,
+}
+
+impl MemoryBackend {
+    pub fn from_config(config: MemoryBackendConfig) -> Self {
+        let store = config.store();
+        Self { config, store }
+    }
+
+    fn format_cid(cid: &Cid) -> String {
+        format!("0x{}", cid.to_bytes().to_hex())
+    }
+
+    fn get_entity(&self, cid: &str) -> Result<Option<Entity>, Error> {
+        let _timer = metrics::start_query_timer("memory", metrics::query::GET_ENTITY);
+        let store = self.store.read().unwrap();
+        Ok(store.get(cid).map(|n| n.to_owned()))
+    }
+
+    pub fn get_entities(&self, cids: Vec<String>) -> Result<Vec<Entity>, Error> {
+        let _timer = metrics::start_query_timer("memory", metrics::query::GET_ENTITIES);
+        let store = self.store.read().unwrap();
+
+        let mut seen_cids = HashSet::new();
+        let entities: Vec<Entity> = cids
+            .into_iter()
+            .filter(|cid| seen_cids.insert(cid.to_owned()))
+            .filter_map(|cid| store.get(&cid).map(|n| n.to_owned()))
+            .collect();
+        trace!("get_entities retrieved {} entities", entities.len());
+
+        Ok(entities)
+    }
+
+    fn store_entities(&self, entities: &[Entity]) -> Result<Vec<Cid>, Error> {
+        let _timer = metrics::start_query_timer("memory", metrics::query::STORE_ENTITIES);
+        let mut store = self.store.write().unwrap();
+
+        let mut cids = Vec::new();
+        for entity in entities {
+            let cid = entity
+                .to_cid()
+                .map_err(|_| err_msg("Unable to calculate CID of entity"))?;
+            store.insert(Self::format_cid(&cid), entity.to_owned());
+            cids.push(cid);
+        }
+
+        Ok(cids)
+    }
+
+    fn list_cids(&self, entity_kind: Option<&str>) -> Result<Vec<String>, Error> {
+        if let Some(kind) = entity_kind {
+            validate_entity_kind(kind)?;
+        }
+        let _timer = metrics::start_query_timer("memory", metrics::query::QUERY_ENTITIES);
+        let store = self.store.read().unwrap();
+
+        Ok(store
+            .iter()
+            .filter(|(_, entity)| match entity_kind {
+                None => true,
+                Some(kind) => Into::<&str>::into(entity.kind()) == kind,
+            })
+            .map(|(cid, _)| cid.to_owned())
+            .collect())
+    }
+
+    pub fn resolve_entities(&self, cids: Vec<String>) -> Result<ResolvedEntities, Error> {
+        let _timer = metrics::start_query_timer("memory", metrics::query::RESOLVE_ENTITIES);
+        let store = self.store.read().unwrap();
+
+        let mut resolved_entities = ResolvedEntities::new();
+        for cid in cids {
+            let entities = resolve_entity_with(
+                &cid,
+                |related_cid| Ok(store.get(related_cid).map(|n| n.to_owned())),
+                |subject_cid| {
+                    Ok(store
+                        .iter()
+                        .filter(|(_, entity)| {
+                            entity_relationships(entity)
+                                .iter()
+                                .any(|(field, related_cid)| {
+                                    field == "subject" && related_cid == subject_cid
+                                })
+                        })
+                        .map(|(related_cid, _)| related_cid.to_owned())
+                        .collect())
+                },
+            )?;
+            if let Some(entities) = entities {
+                resolved_entities.insert(cid, entities);
+            }
+        }
+        trace!(
+            "resolve_entities retrieved {} entities",
+            resolved_entities.len()
+        );
+
+        Ok(resolved_entities)
+    }
+}
+
+impl BackendFromConfigAndSyncState for MemoryBackend {
+    type C = MemoryBackendConfig;
+    type S = SyncState;
+    type R = future::Ready<Result<Self, Error>>;
+
+    fn from_config_and_syncstate(config: Self::C, sync_state: Self::S) -> Self::R {
+        let store = sync_state.store.unwrap_or_else(|| config.store());
+        future::ok(Self { config, store })
+    }
+}
+
+#[async_trait]
+impl GetEntity for MemoryBackend {
+    async fn get_entity(&self, cid: &[u8]) -> Result<Option<Entity>, Error> {
+        let cid = format!("0x{}", cid.to_hex());
+        Self::get_entity(self, &cid)
+    }
+}
+
+#[async_trait]
+impl ResolveEntity for MemoryBackend {
+    async fn resolve_entity(&self, cid: &[u8]) -> Result<HashMap<Vec<u8>, Vec<Entity>>, Error> {
+        let cid = format!("0x{}", cid.to_hex());
+        let resolved_entities = Self::resolve_entities(self, vec![cid])?;
+        resolved_entities
+            .into_iter()
+            .map(|(old_key, value)| {
+                let key = old_key
+                    .trim_start_matches("0x")
+                    .from_hex()
+                    .map_err(|_| format_err!("Invalid CID {}", old_key))?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+}
+
+impl BackendRpcMethodGetEntity for MemoryBackend {
+    fn get_entity(&mut self, cid: &str) -> BoxFuture<Result<Option<Entity>, Error>> {
+        future::ready(Self::get_entity(self, cid)).boxed()
+    }
+}
+
+impl BackendRpcMethodGetEntities for MemoryBackend {
+    fn get_entities(&mut self, cids: Vec<String>) -> BoxFuture<Result<Vec<Entity>, Error>> {
+        future::ready(Self::get_entities(self, cids)).boxed()
+    }
+}
+
+impl BackendRpcMethodStoreEntity for MemoryBackend {
+    fn store_entity(
+        &mut self,
+        entity: &Entity,
+        _options_object: &Value,
+    ) -> BoxFuture<Result<Cid, Error>> {
+        let res = Self::store_entities(self, &[entity.to_owned()]).map(|mut cids| cids.remove(0));
+        future::ready(res).boxed()
+    }
+}
+
+impl BackendRpcMethodStoreEntities for MemoryBackend {
+    fn store_entities(
+        &mut self,
+        entities: &Vec<Entity>,
+        _options_object: &Value,
+    ) -> BoxFuture<Result<Vec<Cid>, Error>> {
+        future::ready(Self::store_entities(self, entities)).boxed()
+    }
+}
+
+impl BackendRpcMethodListCids for MemoryBackend {
+    fn list_cids(&mut self, entity_kind: Option<&str>) -> BoxFuture<Result<Vec<String>, Error>> {
+        future::ready(Self::list_cids(self, entity_kind)).boxed()
+    }
+}
+
+impl BackendRpcMethodResolveEntity for MemoryBackend {
+    fn resolve_entity(
+        &mut self,
+        cid: &str,
+    ) -> BoxFuture<Result<HashMap<String, Vec<Entity>>, Error>> {
+        future::ready(Self::resolve_entities(self, vec![cid.to_owned()])).boxed()
+    }
+}
+
+impl BackendRpcMethodResolveEntities for MemoryBackend {
+    fn resolve_entities(
+        &mut self,
+        cids: Vec<String>,
+    ) -> BoxFuture<Result<HashMap<String, Vec<Entity>>, Error>> {
+        future::ready(Self::resolve_entities(self, cids)).boxed()
+    }
+}
+
+impl BackendRpcMethodNeo4jQuery for MemoryBackend {}
+impl BackendRpcMethods for MemoryBackend {
+    fn unsupported_rpc_methods(&self) -> Vec<&'static str> {
+        vec!["neo4j_query"]
+    }
+}
@@ -0,0 +1,67 @@
+use futures::executor::block_on;
+use rlay_backend::rpc::*;
+use rlay_backend::BackendFromConfigAndSyncState;
+use rlay_backend_memory::*;
+use rlay_ontology::prelude::*;
+use rustc_hex::{FromHex, ToHex};
+use serde_json::Value;
+
+#[test]
+fn backends_with_same_sync_state_share_entities() {
+    let _ = env_logger::try_init();
+    let config = config::MemoryBackendConfig::default();
+    let sync_state = SyncState {
+        store: Some(config.store()),
+    };
+    let mut backend = block_on(MemoryBackend::from_config_and_syncstate(
+        config.clone(),
+        sync_state.clone(),
+    ))
+    .unwrap();
+    let mut backend2 =
+        block_on(MemoryBackend::from_config_and_syncstate(config, sync_state)).unwrap();
+
+    let inserted_entity = Annotation::default().into();
+    let inserted_cid = block_on(backend.store_entity(&inserted_entity, &Value::Null)).unwrap();
+    let formatted_cid: String = format!("0x{}", inserted_cid.to_bytes().to_hex());
+
+    let retrieved_entity = block_on(BackendRpcMethodGetEntity::get_entity(
+        &mut backend2,
+        &formatted_cid,
+    ))
+    .unwrap();
+
+    assert_eq!(Some(inserted_entity), retrieved_entity);
+}
+
+#[test]
+/// Unlike the graph database backends, no leaf node is created for CIDs that are only referenced,
+/// so they are non-retrievable like a CID that is unknown.
+fn get_entity_referenced_cid_returns_none() {
+    let _ = env_logger::try_init();
+    let mut backend = MemoryBackend::from_config(config::MemoryBackendConfig::default());
+
+    let mut inserted_ann = Annotation::default();
+    let leaf_cid: Vec<u8> =
+        "019580031b201111111111111111111111111111111111111111111111111111111111111111"
+            .from_hex()
+            .unwrap();
+    inserted_ann.annotations.push(leaf_cid.clone());
+    block_on(backend.store_entity(&inserted_ann.into(), &Value::Null)).unwrap();
+
+    let retrieved_entity = block_on(BackendRpcMethodGetEntity::get_entity(
+        &mut backend,
+        "0x019580031b201111111111111111111111111111111111111111111111111111111111111111",
+    ))
+    .unwrap();
+
+    assert!(retrieved_entity.is_none());
+}
+
+#[test]
+fn conformance_suite_passes() {
+    let _ = env_logger::try_init();
+    let mut backend = MemoryBackend::from_config(config::MemoryBackendConfig::default());
+
+    block_on(rlay_backend::conformance::run_all(&mut backend));
+}
@@ -1,5 +1,8 @@
 use bb8_cypher::CypherConnectionManager;
 use l337::{Config, Pool};
+use rlay_backend::metrics;
+
+const MAX_CONNECTIONS: usize = 30;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Neo4jBackendConfig {
@@ -12,11 +15,12 @@ impl Neo4jBackendConfig {
             url: self.uri.to_owned(),
         };
 
+        metrics::set_pool_max_connections("neo4j", MAX_CONNECTIONS);
         Pool::new(
             manager,
             Config {
                 min_size: 3,
-                max_size: 30,
+                max_size: MAX_CONNECTIONS,
             },
         )
         .await
@@ -18,6 +18,7 @@ use futures::future::BoxFuture;
 use futures::prelude::*;
 use l337::Pool;
 use once_cell::sync::OnceCell;
+use rlay_backend::metrics::{self, PoolConnectionGuard};
 use rlay_backend::rpc::*;
 use rlay_backend::{BackendFromConfigAndSyncState, GetEntity, ResolveEntity};
 use rlay_ontology::prelude::*;
@@ -26,7 +27,7 @@ use rusted_cypher::cypher::result::Rows;
 use rusted_cypher::cypher::Statement;
 use rusted_cypher::GraphClient;
 use serde_json::{self, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 
 use crate::config::Neo4jBackendConfig;
@@ -43,6 +44,29 @@ pub struct SyncState {
     pub connection_pool: Option<Pool<CypherConnectionManager>>,
 }
 
+/// Connection from the connection pool, which is counted as in use while it is alive.
+struct PooledClient<C> {
+    connection: C,
+    _in_use: PoolConnectionGuard,
+}
+
+impl<C> PooledClient<C> {
+    fn new(connection: C) -> Self {
+        Self {
+            connection,
+            _in_use: PoolConnectionGuard::new("neo4j"),
+        }
+    }
+}
+
+impl<C: std::ops::Deref<Target = GraphClient>> std::ops::Deref for PooledClient<C> {
+    type Target = GraphClient;
+
+    fn deref(&self) -> &GraphClient {
+        &self.connection
+    }
+}
+
 /// Map with CID of resolved entity as key, and Vec of all contained entities within the resolved
 /// entity as values.
 type ResolvedEntities = HashMap<String, Vec<Entity>>;
@@ -57,13 +81,15 @@ impl Neo4jBackend {
 
     pub async fn client(&self) -> Result<impl std::ops::Deref<Target = GraphClient>, Error> {
         if let Some(client) = self.client.get() {
-            return Ok(client.connection().await.unwrap());
+            return Ok(PooledClient::new(client.connection().await.unwrap()));
         }
 
         trace!("Creating new connection pool for backend.");
         let new_connection = self.config.connection_pool().await;
         let _ = self.client.set(new_connection.clone());
-        Ok(new_connection.connection().await.unwrap())
+        Ok(PooledClient::new(
+            new_connection.connection().await.unwrap(),
+        ))
     }
 
     fn pattern_object_to_cid(object: &Value) -> String {
@@ -241,10 +267,11 @@ impl Neo4jBackend {
         let cids: Vec<String> = cids.to_owned();
         let client = self.client().await?;
 
-        let deduped_cids = {
-            let mut deduped_cids = cids.to_owned();
-            deduped_cids.dedup();
-            deduped_cids
+        let deduped_cids: Vec<String> = {
+            let mut seen_cids = HashSet::new();
+            cids.into_iter()
+                .filter(|cid| seen_cids.insert(cid.to_owned()))
+                .collect()
         };
 
         let query = format!(
@@ -270,6 +297,7 @@ impl Neo4jBackend {
         let query_res = client.exec(statement_query).await?;
         let end = std::time::Instant::now();
         trace!("Query duration: {:?}", end - start);
+        metrics::observe_query_duration("neo4j", metrics::query::GET_ENTITIES, end - start);
 
         if query_res.rows().count() == 0 {
             return Ok(vec![]);
@@ -277,12 +305,6 @@ impl Neo4jBackend {
 
         let entities = Self::pattern_rows_to_entities(query_res.rows());
         trace!("get_entities retrieved {} entities", entities.len());
-        debug_assert!(
-            deduped_cids.len() == entities.len(),
-            "{} cids provided and {} entities retrieved",
-            deduped_cids.len(),
-            entities.len()
-        );
 
         Ok(entities)
     }
@@ -295,10 +317,11 @@ impl Neo4jBackend {
         let cids: Vec<String> = cids.to_owned();
         let client = self.client().await?;
 
-        let deduped_cids = {
-            let mut deduped_cids = cids.to_owned();
-            deduped_cids.dedup();
-            deduped_cids
+        let deduped_cids: Vec<String> = {
+            let mut seen_cids = HashSet::new();
+            cids.into_iter()
+                .filter(|cid| seen_cids.insert(cid.to_owned()))
+                .collect()
         };
 
         let query = format!(
@@ -353,6 +376,7 @@ impl Neo4jBackend {
         let query_res = client.exec(statement_query).await?;
         let end = std::time::Instant::now();
         trace!("Query duration: {:?}", end - start);
+        metrics::observe_query_duration("neo4j", metrics::query::RESOLVE_ENTITIES, end - start);
 
         let entities = Self::pattern_rows_to_resolve_entities(query_res.rows());
         trace!("resolve_entities retrieved {} entities", entities.len());
@@ -360,15 +384,35 @@ impl Neo4jBackend {
         Ok(entities)
     }
 
-    async fn query_entities(&mut self, query: String) -> Result<Vec<String>, Error> {
+    async fn ping(&self) -> Result<(), Error> {
         let client = self.client().await?;
+        client.exec(Statement::new("RETURN 1")).await?;
+        Ok(())
+    }
 
-        let query_res = client.exec(query).await?;
+    async fn query_entities(&mut self, statement: Statement) -> Result<Vec<String>, Error> {
+        let client = self.client().await?;
+
+        let start = std::time::Instant::now();
+        let query_res = client.exec(statement).await?;
+        metrics::observe_query_duration("neo4j", metrics::query::QUERY_ENTITIES, start.elapsed());
         let cids: Vec<_> = query_res.rows().map(|row| row.get_n(0).unwrap()).collect();
 
         Ok(cids)
     }
 
+    async fn list_cids(&mut self, entity_kind: Option<String>) -> Result<Vec<String>, Error> {
+        let query = match entity_kind {
+            None => "MATCH (n:RlayEntity) RETURN DISTINCT n.cid".to_owned(),
+            Some(kind) => {
+                // labels can't be passed as parameters, so the kind has to be validated instead
+                validate_entity_kind(&kind)?;
+                format!("MATCH (n:RlayEntity:{}) RETURN DISTINCT n.cid", kind)
+            }
+        };
+        self.query_entities(Statement::new(query.as_str())).await
+    }
+
     async fn store_entity(&mut self, entity: Entity) -> Result<Cid, Error> {
         let cids = self.store_entities(vec![entity]).await?;
         Ok(cids[0].clone())
@@ -534,6 +578,7 @@ impl Neo4jBackend {
         client.exec(statement_query).await?;
         let end = std::time::Instant::now();
         trace!("Query duration: {:?}", end - start);
+        metrics::observe_query_duration("neo4j", metrics::query::STORE_ENTITIES, end - start);
 
         Ok(entities
             .iter()
@@ -619,17 +664,13 @@ impl BackendRpcMethodGetEntities for Neo4jBackend {
 
 impl BackendRpcMethodListCids for Neo4jBackend {
     fn list_cids(&mut self, entity_kind: Option<&str>) -> BoxFuture<Result<Vec<String>, Error>> {
-        let query = match entity_kind {
-            None => "MATCH (n:RlayEntity) RETURN DISTINCT n.cid".to_owned(),
-            Some(kind) => format!("MATCH (n:RlayEntity:{}) RETURN DISTINCT n.cid", kind),
-        };
-        self.neo4j_query(&query)
+        Box::pin(Self::list_cids(self, entity_kind.map(|n| n.to_owned())))
     }
 }
 
 impl BackendRpcMethodNeo4jQuery for Neo4jBackend {
     fn neo4j_query(&mut self, query: &str) -> BoxFuture<Result<Vec<String>, Error>> {
-        Box::pin(self.query_entities(query.to_owned()))
+        Box::pin(self.query_entities(Statement::new(query)))
     }
 }
 
@@ -651,4 +692,8 @@ impl BackendRpcMethodResolveEntities for Neo4jBackend {
     }
 }
 
-impl BackendRpcMethods for Neo4jBackend {}
+impl BackendRpcMethods for Neo4jBackend {
+    fn ping(&mut self) -> BoxFuture<Result<(), Error>> {
+        Box::pin(Self::ping(self))
+    }
+}
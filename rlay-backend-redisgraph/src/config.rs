@@ -1,15 +1,68 @@
+use failure::Error;
 use redis::{aio::MultiplexedConnection, Client};
+use std::time::Duration;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct RedisgraphBackendConfig {
     pub uri: String,
     pub graph_name: String,
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+/// Policy for retrying writes that failed because of the connection to Redis, e.g. because it was
+/// lost. Writes that were rejected by Redis are not retried.
+///
+/// The delay between attempts starts at `initial_backoff_ms` and doubles with every attempt, up
+/// to `max_backoff_ms`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RetryConfig {
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    100
+}
+
+fn default_max_backoff_ms() -> u64 {
+    5000
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Delay before the retry that follows the failed attempt `attempt` (starting at 1).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        let backoff_ms = self
+            .initial_backoff_ms
+            .saturating_mul(factor)
+            .min(self.max_backoff_ms);
+        Duration::from_millis(backoff_ms)
+    }
 }
 
 impl RedisgraphBackendConfig {
-    pub async fn connection_pool(&self) -> MultiplexedConnection {
+    pub async fn connection_pool(&self) -> Result<MultiplexedConnection, Error> {
         trace!("Creating new Redis connection");
-        let client = Client::open(self.uri.as_str()).unwrap();
-        client.get_multiplexed_tokio_connection().await.unwrap()
+        let client = Client::open(self.uri.as_str())?;
+        Ok(client.get_multiplexed_tokio_connection().await?)
     }
 }
@@ -8,6 +8,7 @@ extern crate serde_derive;
 extern crate static_assertions as sa;
 
 pub mod config;
+mod params;
 mod parse;
 
 use async_trait::async_trait;
@@ -16,79 +17,116 @@ use failure::{format_err, Error};
 use futures::future::BoxFuture;
 use futures::prelude::*;
 use itertools::Itertools;
-use once_cell::sync::OnceCell;
 use redis::{aio::MultiplexedConnection, FromRedisValue};
+use rlay_backend::metrics;
+use rlay_backend::relationships::entity_relationships;
 use rlay_backend::rpc::*;
 use rlay_backend::{BackendFromConfigAndSyncState, GetEntity, ResolveEntity};
 use rlay_ontology::prelude::*;
-use rustc_hex::ToHex;
+use rustc_hex::{FromHex, ToHex};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
 
 use crate::config::RedisgraphBackendConfig;
+use crate::params::{with_params, Param};
 use crate::parse::{CidList, GetQueryRelationship};
 
+/// Map with CID of resolved entity as key, and Vec of all contained entities within the resolved
+/// entity as values.
+type ResolvedEntities = HashMap<String, Vec<Entity>>;
+
 sa::assert_impl_all!(RedisgraphBackend: Send, Sync);
 #[derive(Clone)]
 pub struct RedisgraphBackend {
     pub config: RedisgraphBackendConfig,
-    client: OnceCell<MultiplexedConnection>,
+    /// Connection that is shared with all clones of the backend (and with other backends created
+    /// from the same `SyncState`), until it is discarded due to a connection error.
+    client: Arc<RwLock<Option<MultiplexedConnection>>>,
 }
 
 impl RedisgraphBackend {
     pub fn from_config(config: RedisgraphBackendConfig) -> Self {
         Self {
             config,
-            client: OnceCell::new(),
+            client: Arc::new(RwLock::new(None)),
         }
     }
 
     pub async fn client(&self) -> Result<MultiplexedConnection, Error> {
-        if let Some(client) = self.client.get() {
-            return Ok(client.clone());
+        let cached_client = self.client.read().unwrap().clone();
+        if let Some(client) = cached_client {
+            return Ok(client);
         }
 
         trace!("Creating new connection pool for backend.");
-        let new_connection = self.config.connection_pool().await;
-        let _ = self.client.set(new_connection.clone());
+        let new_connection = self.config.connection_pool().await?;
+        *self.client.write().unwrap() = Some(new_connection.clone());
         Ok(new_connection)
     }
 
-    async fn get_entity(&self, cid: String) -> Result<Option<Entity>, Error> {
+    /// Pass through `err`, discarding the cached connection if the error was caused by it, so
+    /// that the next call connects anew (e.g. after Redis has been restarted).
+    fn discard_broken_client(&self, err: redis::RedisError) -> redis::RedisError {
+        if is_redis_connection_error(&err) {
+            warn!("Discarding connection to Redis: {}", err);
+            *self.client.write().unwrap() = None;
+        }
+        err
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
         let mut client = self.client().await?;
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut client)
+            .await
+            .map_err(|err| self.discard_broken_client(err))?;
+        Ok(())
+    }
 
-        let query = format!(
-            "MATCH (n:RlayEntity {{ cid: '{0}' }})-[r]->(m) RETURN n,type(r),m",
-            cid
-        );
-        trace!("get_entity query: {:?}", query);
+    /// Run a read-only `query` on the graph, recording its duration as `query_type`.
+    ///
+    /// Returns `None` if the graph doesn't exist yet, as it is only created once the first entity
+    /// is stored.
+    async fn read_query(
+        &self,
+        query: String,
+        query_type: &str,
+    ) -> Result<Option<redis::Value>, Error> {
+        let mut client = self.client().await?;
 
-        let query_res: Option<redis::Value> = redis::cmd("GRAPH.QUERY")
+        let start = std::time::Instant::now();
+        let query_res = redis::cmd("GRAPH.QUERY")
             .arg(&self.config.graph_name)
             .arg(query)
             .query_async(&mut client)
-            .await
-            // TODO: cast error to none; Required because missing graph is throwing an error
-            .ok();
-        if let None = query_res {
-            return Ok(None);
+            .await;
+        metrics::observe_query_duration("redisgraph", query_type, start.elapsed());
+        match query_res {
+            Ok(value) => Ok(Some(value)),
+            Err(ref err) if is_missing_graph_error(err) => Ok(None),
+            Err(err) => Err(self.discard_broken_client(err).into()),
         }
-        let query_res = query_res.unwrap();
-        let results_with_meta = Vec::<redis::Value>::from_redis_value(&query_res).unwrap();
-        let results = Vec::<redis::Value>::from_redis_value(&results_with_meta[1]).unwrap();
+    }
 
-        let relationships: Vec<GetQueryRelationship> = results
-            .into_iter()
-            .map(|n| GetQueryRelationship::parse(n))
-            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()
-            .unwrap();
-        let entity = GetQueryRelationship::merge_into_entity(relationships).unwrap();
-        if let None = entity {
-            return Ok(None);
-        }
-        let entity = entity.unwrap();
+    async fn get_entity(&self, cid: String) -> Result<Option<Entity>, Error> {
+        let query = with_params(
+            "MATCH (n:RlayEntity { cid: $cid }) OPTIONAL MATCH (n)-[r]->(m) RETURN n,type(r),m",
+            &[("cid", Param::Str(&cid))],
+        );
+        trace!("get_entity query: {:?}", query);
+
+        let query_res = match self.read_query(query, metrics::query::GET_ENTITY).await? {
+            Some(query_res) => query_res,
+            None => return Ok(None),
+        };
+        let relationships = parse_relationships(&query_res)?;
+        let entity = match GetQueryRelationship::merge_into_entity(relationships)? {
+            Some(entity) => entity,
+            None => return Ok(None),
+        };
 
-        let retrieved_cid = format!("0x{}", entity.to_cid().unwrap().to_bytes().to_hex());
+        let retrieved_cid = format_entity_cid(&entity)?;
         if retrieved_cid != cid {
             return Err(format_err!(
                 "The retrieved CID did not match the requested cid: {} !+ {}",
@@ -100,187 +138,352 @@ impl RedisgraphBackend {
         Ok(Some(entity))
     }
 
-    pub async fn get_entities(&mut self, cids: Vec<String>) -> Result<Vec<Entity>, Error> {
-        let cids: Vec<String> = cids.to_owned();
-        let mut client = self.client().await?;
-
-        let deduped_cids = {
-            let mut deduped_cids = cids.to_owned();
-            deduped_cids.dedup();
-            deduped_cids
+    pub async fn get_entities(&self, cids: Vec<String>) -> Result<Vec<Entity>, Error> {
+        let deduped_cids: Vec<String> = {
+            let mut seen_cids = HashSet::new();
+            cids.into_iter()
+                .filter(|cid| seen_cids.insert(cid.to_owned()))
+                .collect()
         };
+        if deduped_cids.is_empty() {
+            return Ok(vec![]);
+        }
 
-        let query = format!(
-            "MATCH (n:RlayEntity)-[r]->(m) WHERE n.cid IN {0:?} RETURN n,type(r),m",
-            deduped_cids,
+        let query = with_params(
+            "MATCH (n:RlayEntity) WHERE n.cid IN $cids OPTIONAL MATCH (n)-[r]->(m) RETURN n,type(r),m",
+            &[("cids", Param::StrList(&deduped_cids))],
         );
         trace!("get_entities query: \"{}\"", query);
 
-        let query_res: Option<redis::Value> = redis::cmd("GRAPH.QUERY")
-            .arg(&self.config.graph_name)
-            .arg(query)
-            .query_async(&mut client)
-            .await
-            // TODO: cast error to none; Required because missing graph is throwing an error
-            .ok();
-        if let None = query_res {
-            return Ok(vec![]);
+        let query_res = match self.read_query(query, metrics::query::GET_ENTITIES).await? {
+            Some(query_res) => query_res,
+            None => return Ok(vec![]),
+        };
+        let relationships = parse_relationships(&query_res)?;
+        let mut entities_by_cid: HashMap<String, Entity> = HashMap::new();
+        for (_, group) in &relationships.into_iter().group_by(|n| n.n_id) {
+            if let Some(entity) = GetQueryRelationship::merge_into_entity(group.collect())? {
+                entities_by_cid.insert(format_entity_cid(&entity)?, entity);
+            }
         }
-        let query_res = query_res.unwrap();
-        let results_with_meta = Vec::<redis::Value>::from_redis_value(&query_res).unwrap();
-        let results = Vec::<redis::Value>::from_redis_value(&results_with_meta[1]).unwrap();
 
-        let relationships: Vec<GetQueryRelationship> = results
-            .into_iter()
-            .map(|n| GetQueryRelationship::parse(n))
-            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()
-            .unwrap();
-        let entities: Vec<Entity> = relationships
-            .into_iter()
-            .group_by(|n| n.n_id)
-            .into_iter()
-            .filter_map(|(_, group)| {
-                GetQueryRelationship::merge_into_entity(group.into_iter().collect()).unwrap()
-            })
+        // return the entities in the order they were requested in
+        let entities: Vec<Entity> = deduped_cids
+            .iter()
+            .filter_map(|cid| entities_by_cid.remove(cid))
             .collect();
-
         trace!("get_entities retrieved {} entities", entities.len());
-        debug_assert!(
-            deduped_cids.len() == entities.len(),
-            "{} cids provided and {} entities retrieved",
-            deduped_cids.len(),
-            entities.len()
-        );
 
         Ok(entities)
     }
 
-    async fn query_entities(&mut self, query: String) -> Result<Vec<String>, Error> {
-        let mut client = self.client().await?;
+    /// CIDs of all entities that have the entity with the provided CID as their `subject`.
+    async fn subject_of(&self, cid: &str) -> Result<Vec<String>, Error> {
+        let query = with_params(
+            "MATCH (n:RlayEntity)-[:subject]->(m:RlayEntity { cid: $cid }) RETURN n.cid",
+            &[("cid", Param::Str(cid))],
+        );
+        self.query_entities(query).await
+    }
 
-        dbg!(&query);
-        let query_res: Option<redis::Value> = redis::cmd("GRAPH.QUERY")
-            .arg(&self.config.graph_name)
-            .arg(query)
-            .query_async(&mut client)
-            .await
-            .ok();
-        dbg!(&query_res);
-        if let None = query_res {
-            return Ok(vec![]);
+    async fn resolve_entity(&self, cid: String) -> Result<ResolvedEntities, Error> {
+        self.resolve_entities(vec![cid]).await
+    }
+
+    /// Resolve the entities with the provided CIDs.
+    ///
+    /// Follows the same pattern as the query of the Neo4j backend: The entities referenced by
+    /// the resolved entity and the entities that have it as their `subject` are followed two
+    /// levels deep, and one level further for `Individual`s (except for the resolved entity
+    /// itself). RedisGraph doesn't support pattern comprehensions, so every level is retrieved
+    /// with a separate query.
+    pub async fn resolve_entities(&self, cids: Vec<String>) -> Result<ResolvedEntities, Error> {
+        // the queries of the individual levels are recorded on their own as well
+        let _timer = metrics::start_query_timer("redisgraph", metrics::query::RESOLVE_ENTITIES);
+        let mut resolved_entities = ResolvedEntities::new();
+        for cid in cids {
+            let root = match self.get_entity(cid.clone()).await? {
+                Some(root) => root,
+                None => continue,
+            };
+
+            let mut direct_cids = related_cids(&[root.clone()]);
+            direct_cids.extend(self.subject_of(&cid).await?);
+            let direct_entities = self.get_entities(direct_cids).await?;
+
+            let child_entities = self.get_entities(related_cids(&direct_entities)).await?;
+
+            let mut child_individuals: Vec<Entity> = Vec::new();
+            for entity in &child_entities {
+                if Into::<&str>::into(entity.kind()) == "Individual"
+                    && format_entity_cid(entity)? != cid
+                {
+                    child_individuals.push(entity.clone());
+                }
+            }
+            let grandchild_entities = self.get_entities(related_cids(&child_individuals)).await?;
+
+            let mut seen_cids = HashSet::new();
+            let mut entities: Vec<Entity> = Vec::new();
+            for entity in vec![root]
+                .into_iter()
+                .chain(direct_entities)
+                .chain(child_entities)
+                .chain(grandchild_entities)
+            {
+                if seen_cids.insert(format_entity_cid(&entity)?) {
+                    entities.push(entity);
+                }
+            }
+            resolved_entities.insert(cid, entities);
         }
-        let query_res = query_res.unwrap();
-        let results_with_meta = Vec::<redis::Value>::from_redis_value(&query_res).unwrap();
+        trace!(
+            "resolve_entities retrieved {} entities",
+            resolved_entities.len()
+        );
+
+        Ok(resolved_entities)
+    }
+
+    async fn list_cids(&self, entity_kind: Option<String>) -> Result<Vec<String>, Error> {
+        // leaf nodes that are only referenced don't have a type and are not listed
+        let query = match entity_kind {
+            None => "MATCH (n:RlayEntity) WHERE exists(n.type) RETURN n.cid".to_owned(),
+            Some(kind) => {
+                validate_entity_kind(&kind)?;
+                with_params(
+                    "MATCH (n:RlayEntity) WHERE n.type = $kind RETURN n.cid",
+                    &[("kind", Param::Str(&kind))],
+                )
+            }
+        };
+        self.query_entities(query).await
+    }
+
+    async fn query_entities(&self, query: String) -> Result<Vec<String>, Error> {
+        trace!("query_entities query: {:?}", query);
+        let query_res = match self
+            .read_query(query, metrics::query::QUERY_ENTITIES)
+            .await?
+        {
+            Some(query_res) => query_res,
+            None => return Ok(vec![]),
+        };
+        let results_with_meta = Vec::<redis::Value>::from_redis_value(&query_res)?;
         if results_with_meta.len() < 2 {
             return Ok(vec![]);
         }
 
-        let parsed = CidList::parse(results_with_meta[1].clone()).unwrap();
+        let parsed = CidList::parse(results_with_meta[1].clone())?;
         Ok(parsed.inner)
     }
 
     async fn store_entity(&mut self, entity: Entity) -> Result<Cid, Error> {
-        let raw_cid = entity.to_cid().unwrap();
+        let raw_cid = entity
+            .to_cid()
+            .map_err(|_| format_err!("Unable to calculate CID of entity"))?;
         let cid: String = format!("0x{}", raw_cid.to_bytes().to_hex());
 
         let kind_name: &str = entity.kind().into();
-        let entity_val = serde_json::to_value(FormatWeb3(entity.clone())).unwrap();
-        let val = entity_val.as_object().unwrap();
-
-        let mut values = Vec::new();
-        let mut relationship_queries = Vec::new();
-        {
-            let mut add_relationship_value = |source_cid, key, target_value| {
-                {
-                    let rel_query = format!(
-                        "MERGE (m:RlayEntity {{ cid: '{target_value}' }})",
-                        target_value = target_value
-                    );
-                    relationship_queries.push(rel_query);
-                }
-                {
-                    let rel_query = format!(
-                        "MATCH (n:RlayEntity {{ cid: '{source_cid}'}}),(m:RlayEntity {{ cid: '{target_value}' }}) CREATE (n)-[r:{relationship}]->(m)",
-                        source_cid = source_cid,
-                        target_value = target_value,
-                        relationship = key
-                    );
-                    relationship_queries.push(rel_query);
-                }
-            };
-
-            for (key, value) in val {
-                if key == "cid" || key == "type" {
-                    continue;
-                }
-                if (kind_name == "DataPropertyAssertion"
-                    || kind_name == "NegativeDataPropertyAssertion")
-                    && key == "target"
-                {
-                    values.push(format!("n.{0} = '{1}'", key, value.as_str().unwrap()));
-                    continue;
-                }
-                if kind_name == "Annotation" && key == "value" {
-                    values.push(format!("n.{0} = '{1}'", key, value.as_str().unwrap()));
-                    continue;
-                }
-                if let Value::Array(array_val) = value {
-                    for relationship_value in array_val {
-                        if let Value::String(str_val) = relationship_value {
-                            add_relationship_value(cid.clone(), key, str_val);
-                        }
+        let entity_val = serde_json::to_value(FormatWeb3(entity.clone()))?;
+        let val = entity_val
+            .as_object()
+            .ok_or_else(|| format_err!("Entity {} is not serialized as an object", cid))?;
+
+        let mut values: Vec<(&str, &str)> = Vec::new();
+        let mut relationships: Vec<(&str, &str)> = Vec::new();
+        for (key, value) in val {
+            if key == "cid" || key == "type" {
+                continue;
+            }
+            if (kind_name == "DataPropertyAssertion"
+                || kind_name == "NegativeDataPropertyAssertion")
+                && key == "target"
+            {
+                values.push((key.as_str(), value_as_str(&cid, key, value)?));
+                continue;
+            }
+            if kind_name == "Annotation" && key == "value" {
+                values.push((key.as_str(), value_as_str(&cid, key, value)?));
+                continue;
+            }
+            if let Value::Array(array_val) = value {
+                for relationship_value in array_val {
+                    if let Value::String(str_val) = relationship_value {
+                        relationships.push((key.as_str(), str_val.as_str()));
                     }
-                    continue;
-                }
-                if let Value::String(str_val) = value {
-                    add_relationship_value(cid.clone(), key, str_val);
                 }
+                continue;
+            }
+            if let Value::String(str_val) = value {
+                relationships.push((key.as_str(), str_val.as_str()));
             }
         }
 
-        let mut statement_query = format!(
-            "MERGE (n:RlayEntity {{cid: '{1}'}}) SET n.type = '{0}'",
-            kind_name, cid
-        );
-        if !values.is_empty() {
-            statement_query.push_str(", ");
-            statement_query.push_str(&values.join(", "));
+        // field names are known from the entity kind, so only the values have to be passed as
+        // parameters
+        let mut statement_query =
+            "MERGE (n:RlayEntity { cid: $cid }) SET n.type = $type".to_owned();
+        for (key, _) in &values {
+            statement_query.push_str(&format!(", n.{0} = ${0}", key));
+        }
+        let mut statement_params = vec![("cid", Param::Str(&cid)), ("type", Param::Str(kind_name))];
+        statement_params.extend(values.iter().map(|(key, value)| (*key, Param::Str(value))));
+
+        let mut transaction_queries = vec![with_params(&statement_query, &statement_params)];
+        for (key, target_cid) in relationships {
+            transaction_queries.push(with_params(
+                "MERGE (m:RlayEntity { cid: $target_cid })",
+                &[("target_cid", Param::Str(target_cid))],
+            ));
+            transaction_queries.push(with_params(
+                &format!(
+                    "MATCH (n:RlayEntity {{ cid: $source_cid }}),(m:RlayEntity {{ cid: $target_cid }}) MERGE (n)-[r:{relationship}]->(m)",
+                    relationship = key
+                ),
+                &[
+                    ("source_cid", Param::Str(&cid)),
+                    ("target_cid", Param::Str(target_cid)),
+                ],
+            ));
         }
-
-        let mut transaction_queries = vec![statement_query];
-        transaction_queries.append(&mut relationship_queries);
 
         trace!("Insert transaction queries: {:?}", transaction_queries);
 
+        // MULTI/EXEC, so that a node is never stored without its relationships
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for query in &transaction_queries {
+            pipe.cmd("GRAPH.QUERY")
+                .arg(&self.config.graph_name)
+                .arg(query);
+        }
+
+        let start = std::time::Instant::now();
+        let retry_config = &self.config.retry;
+        let mut attempt = 1;
         loop {
-            let mut client = self.client().await?;
-
-            let mut pipe = redis::pipe();
-            // pipe.cmd("MULTI").ignore();
-            for query in &transaction_queries {
-                pipe.cmd("GRAPH.QUERY")
-                    .arg(&self.config.graph_name)
-                    .arg(query)
-                    .ignore();
+            let res: Result<(), Error> = async {
+                // a broken connection is discarded, so that retries (and later calls) use a new one
+                let mut client = self.client().await?;
+                let replies = pipe
+                    .query_async::<_, Vec<redis::Value>>(&mut client)
+                    .await
+                    .map_err(|err| self.discard_broken_client(err))?;
+                // EXEC replies with the result of every query, unless the transaction was aborted
+                if replies.len() != transaction_queries.len()
+                    || replies.iter().any(|reply| match reply {
+                        redis::Value::Bulk(_) => false,
+                        _ => true,
+                    })
+                {
+                    return Err(format_err!(
+                        "Transaction storing entity {} was not executed: {:?}",
+                        cid,
+                        replies
+                    ));
+                }
+                Ok(())
             }
-            // pipe.cmd("EXEC").ignore();
-
-            match pipe
-                .query_async::<_, Option<redis::Value>>(&mut client)
-                .await
-                .unwrap()
-            {
-                Option::Some(_) => {
+            .await;
+
+            match res {
+                Ok(()) => {
+                    metrics::observe_query_duration(
+                        "redisgraph",
+                        metrics::query::STORE_ENTITIES,
+                        start.elapsed(),
+                    );
                     break;
                 }
-                Option::None => {
-                    continue;
+                Err(err) if is_connection_error(&err) && attempt < retry_config.max_attempts => {
+                    let backoff = retry_config.backoff(attempt);
+                    warn!(
+                        "Storing entity {} failed (attempt {}/{}), retrying in {:?}: {}",
+                        cid, attempt, retry_config.max_attempts, backoff, err
+                    );
+                    tokio::time::delay_for(backoff).await;
+                    attempt += 1;
+                }
+                Err(err) if attempt == 1 => return Err(err),
+                Err(err) => {
+                    return Err(format_err!(
+                        "Unable to store entity {} after {} attempts: {}",
+                        cid,
+                        attempt,
+                        err
+                    ));
                 }
             }
         }
 
         Ok(raw_cid)
     }
+
+    async fn store_entities(&mut self, entities: Vec<Entity>) -> Result<Vec<Cid>, Error> {
+        let mut cids = Vec::new();
+        for entity in entities {
+            cids.push(self.store_entity(entity).await?);
+        }
+        Ok(cids)
+    }
+}
+
+fn format_entity_cid(entity: &Entity) -> Result<String, Error> {
+    let cid = entity
+        .to_cid()
+        .map_err(|_| format_err!("Unable to calculate CID of entity"))?;
+    Ok(format!("0x{}", cid.to_bytes().to_hex()))
+}
+
+fn value_as_str<'a>(cid: &str, key: &str, value: &'a Value) -> Result<&'a str, Error> {
+    value
+        .as_str()
+        .ok_or_else(|| format_err!("Field {} of entity {} is not a string", key, cid))
+}
+
+/// Whether a failed write might succeed on a retry, because it failed due to the connection
+/// instead of the query itself.
+fn is_connection_error(err: &Error) -> bool {
+    match err.downcast_ref::<redis::RedisError>() {
+        Some(err) => is_redis_connection_error(err),
+        None => false,
+    }
+}
+
+fn is_redis_connection_error(err: &redis::RedisError) -> bool {
+    err.is_io_error()
+        || err.is_connection_refusal()
+        || err.is_connection_dropped()
+        || err.is_timeout()
+}
+
+/// Whether a query failed because the graph doesn't exist.
+fn is_missing_graph_error(err: &redis::RedisError) -> bool {
+    err.kind() == redis::ErrorKind::ResponseError && err.to_string().contains("empty key")
+}
+
+/// Parse the rows of a `RETURN n,type(r),m` query.
+fn parse_relationships(query_res: &redis::Value) -> Result<Vec<GetQueryRelationship>, Error> {
+    let results_with_meta = Vec::<redis::Value>::from_redis_value(query_res)?;
+    let results = match results_with_meta.get(1) {
+        Some(results) => Vec::<redis::Value>::from_redis_value(results)?,
+        None => return Ok(vec![]),
+    };
+
+    results
+        .into_iter()
+        .map(GetQueryRelationship::parse)
+        .collect()
+}
+
+/// CIDs of all entities that are referenced by the provided entities.
+fn related_cids(entities: &[Entity]) -> Vec<String> {
+    entities
+        .iter()
+        .flat_map(entity_relationships)
+        .map(|(_, related_cid)| related_cid)
+        .collect()
 }
 
 impl BackendFromConfigAndSyncState for RedisgraphBackend {
@@ -289,34 +492,43 @@ impl BackendFromConfigAndSyncState for RedisgraphBackend {
     type R = Box<dyn Future<Output = Result<Self, Error>> + Send + Unpin>;
 
     fn from_config_and_syncstate(config: Self::C, sync_state: Self::S) -> Self::R {
-        let client_cell = OnceCell::new();
-        if let Some(existing_connection) = sync_state.connection_pool {
-            let _ = client_cell.set(existing_connection.clone());
-        }
         Box::new(future::ok(Self {
             config,
-            client: client_cell,
+            client: sync_state.connection_pool,
         }))
     }
 }
 
 #[derive(Clone)]
 pub struct SyncState {
-    pub connection_pool: Option<MultiplexedConnection>,
+    /// Connection shared by all backends created from this state. A connection that broke is
+    /// replaced for all of them.
+    pub connection_pool: Arc<RwLock<Option<MultiplexedConnection>>>,
 }
 
 #[async_trait]
 impl GetEntity for RedisgraphBackend {
-    async fn get_entity(&self, _cid: &[u8]) -> Result<Option<Entity>, Error> {
-        todo!()
-        // future::ready(Ok(self.get(cid).map(|n| n.to_owned()))).boxed()
+    async fn get_entity(&self, cid: &[u8]) -> Result<Option<Entity>, Error> {
+        let cid = format!("0x{}", cid.to_hex());
+        Self::get_entity(self, cid.to_owned()).await
     }
 }
 
 #[async_trait]
 impl ResolveEntity for RedisgraphBackend {
-    async fn resolve_entity(&self, _cid: &[u8]) -> Result<HashMap<Vec<u8>, Vec<Entity>>, Error> {
-        todo!()
+    async fn resolve_entity(&self, cid: &[u8]) -> Result<HashMap<Vec<u8>, Vec<Entity>>, Error> {
+        let cid = format!("0x{}", cid.to_hex());
+        let resolved_entities = Self::resolve_entity(self, cid.to_owned()).await?;
+        resolved_entities
+            .into_iter()
+            .map(|(old_key, value)| {
+                let key = old_key
+                    .trim_start_matches("0x")
+                    .from_hex()
+                    .map_err(|_| format_err!("Invalid CID {}", old_key))?;
+                Ok((key, value))
+            })
+            .collect()
     }
 }
 
@@ -342,9 +554,48 @@ impl BackendRpcMethodNeo4jQuery for RedisgraphBackend {
     }
 }
 
-impl BackendRpcMethodGetEntities for RedisgraphBackend {}
-impl BackendRpcMethodListCids for RedisgraphBackend {}
-impl BackendRpcMethodStoreEntities for RedisgraphBackend {}
-impl BackendRpcMethodResolveEntity for RedisgraphBackend {}
-impl BackendRpcMethodResolveEntities for RedisgraphBackend {}
-impl BackendRpcMethods for RedisgraphBackend {}
+impl BackendRpcMethodGetEntities for RedisgraphBackend {
+    fn get_entities(&mut self, cids: Vec<String>) -> BoxFuture<Result<Vec<Entity>, Error>> {
+        Box::pin(Self::get_entities(self, cids))
+    }
+}
+
+impl BackendRpcMethodStoreEntities for RedisgraphBackend {
+    fn store_entities(
+        &mut self,
+        entities: &Vec<Entity>,
+        _options_object: &Value,
+    ) -> BoxFuture<Result<Vec<Cid>, Error>> {
+        Box::pin(self.store_entities(entities.to_owned()))
+    }
+}
+
+impl BackendRpcMethodListCids for RedisgraphBackend {
+    fn list_cids(&mut self, entity_kind: Option<&str>) -> BoxFuture<Result<Vec<String>, Error>> {
+        Box::pin(Self::list_cids(self, entity_kind.map(|n| n.to_owned())))
+    }
+}
+
+impl BackendRpcMethodResolveEntity for RedisgraphBackend {
+    fn resolve_entity(
+        &mut self,
+        cid: &str,
+    ) -> BoxFuture<Result<HashMap<String, Vec<Entity>>, Error>> {
+        Box::pin(Self::resolve_entity(self, cid.to_owned()))
+    }
+}
+
+impl BackendRpcMethodResolveEntities for RedisgraphBackend {
+    fn resolve_entities(
+        &mut self,
+        cids: Vec<String>,
+    ) -> BoxFuture<Result<HashMap<String, Vec<Entity>>, Error>> {
+        Box::pin(Self::resolve_entities(self, cids))
+    }
+}
+
+impl BackendRpcMethods for RedisgraphBackend {
+    fn ping(&mut self) -> BoxFuture<Result<(), Error>> {
+        Box::pin(Self::ping(self))
+    }
+}
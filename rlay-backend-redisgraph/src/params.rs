@@ -0,0 +1,44 @@
+//! Query parameters for RedisGraph.
+//!
+//! RedisGraph receives parameters as part of the query string, in a `CYPHER name=value ...`
+//! prefix, so the values have to be encoded as Cypher literals.
+
+/// Value of a query parameter.
+pub enum Param<'a> {
+    Str(&'a str),
+    StrList(&'a [String]),
+}
+
+impl<'a> Param<'a> {
+    fn to_literal(&self) -> String {
+        match self {
+            Param::Str(value) => string_literal(value),
+            Param::StrList(values) => format!(
+                "[{}]",
+                values
+                    .iter()
+                    .map(|value| string_literal(value))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Encode a string as a single-quoted Cypher string literal.
+fn string_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Prefix the query with the provided parameters, which can be referenced as `$name` in it.
+pub fn with_params(query: &str, params: &[(&str, Param)]) -> String {
+    if params.is_empty() {
+        return query.to_owned();
+    }
+    let params = params
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value.to_literal()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("CYPHER {} {}", params, query)
+}
@@ -1,37 +1,47 @@
-use failure::err_msg;
+use failure::{err_msg, format_err, Error};
 use redis::FromRedisValue;
 use redis::Value as RedisValue;
+use rlay_backend::rpc::validate_entity_kind;
 use rlay_ontology::prelude::*;
 use serde_json::Value as JsonValue;
 
-type StdError = Box<dyn std::error::Error>;
+/// Get the value at position `pos` of a row or pair in a query result.
+fn column(values: &[RedisValue], pos: usize) -> Result<&RedisValue, Error> {
+    values
+        .get(pos)
+        .ok_or_else(|| format_err!("Query result is missing column {}", pos))
+}
 
+/// A row of a `RETURN n,type(r),m` query.
+///
+/// `relationship` and `m_cid` are `None` for entities without relationships, which are returned
+/// via an `OPTIONAL MATCH`.
 #[derive(Debug)]
 pub struct GetQueryRelationship {
     pub n_id: u64,
     n_value: JsonValue,
-    relationship: String,
-    m_cid: String,
+    relationship: Option<String>,
+    m_cid: Option<String>,
 }
 
 impl GetQueryRelationship {
-    pub fn parse(rel_result: RedisValue) -> Result<Self, StdError> {
+    pub fn parse(rel_result: RedisValue) -> Result<Self, Error> {
         let val_inner = Vec::<RedisValue>::from_redis_value(&rel_result)?;
 
         Ok(Self {
-            n_id: Self::parse_n_id(&val_inner[0])?,
-            relationship: Self::parse_relationship(&val_inner[1])?,
-            m_cid: Self::parse_m_cid(&val_inner[2])?,
-            n_value: Self::parse_n_value(&val_inner[0])?,
+            n_id: Self::parse_n_id(column(&val_inner, 0)?)?,
+            relationship: Self::parse_relationship(column(&val_inner, 1)?)?,
+            m_cid: Self::parse_m_cid(column(&val_inner, 2)?)?,
+            n_value: Self::parse_n_value(column(&val_inner, 0)?)?,
         })
     }
 
-    fn parse_kv_pairs(vals: Vec<RedisValue>) -> Result<Vec<(String, RedisValue)>, StdError> {
+    fn parse_kv_pairs(vals: Vec<RedisValue>) -> Result<Vec<(String, RedisValue)>, Error> {
         vals.iter()
             .map(|kv_pair| {
                 let key_value = Vec::<RedisValue>::from_redis_value(kv_pair)?;
-                let key = String::from_redis_value(&key_value[0])?;
-                let value = key_value[1].clone();
+                let key = String::from_redis_value(column(&key_value, 0)?)?;
+                let value = column(&key_value, 1)?.clone();
 
                 Ok((key, value))
             })
@@ -54,7 +64,7 @@ impl GetQueryRelationship {
             .collect()
     }
 
-    fn parse_n_id(val: &RedisValue) -> Result<u64, StdError> {
+    fn parse_n_id(val: &RedisValue) -> Result<u64, Error> {
         let val_inner = Vec::<RedisValue>::from_redis_value(val)?;
         let kv_pairs = Self::parse_kv_pairs(val_inner)?;
         let wrapped_id = kv_pairs
@@ -66,7 +76,7 @@ impl GetQueryRelationship {
         Ok(id)
     }
 
-    fn parse_n_value(val: &RedisValue) -> Result<JsonValue, StdError> {
+    fn parse_n_value(val: &RedisValue) -> Result<JsonValue, Error> {
         let val_inner = Vec::<RedisValue>::from_redis_value(val)?;
         let kv_pairs = Self::parse_kv_pairs(val_inner)?;
         let properties = kv_pairs
@@ -83,11 +93,17 @@ impl GetQueryRelationship {
         Ok(JsonValue::Object(json_object))
     }
 
-    fn parse_relationship(val: &RedisValue) -> Result<String, StdError> {
-        Ok(String::from_redis_value(val)?)
+    fn parse_relationship(val: &RedisValue) -> Result<Option<String>, Error> {
+        if let RedisValue::Nil = val {
+            return Ok(None);
+        }
+        Ok(Some(String::from_redis_value(val)?))
     }
 
-    fn parse_m_cid(val: &RedisValue) -> Result<String, StdError> {
+    fn parse_m_cid(val: &RedisValue) -> Result<Option<String>, Error> {
+        if let RedisValue::Nil = val {
+            return Ok(None);
+        }
         let val_inner = Vec::<RedisValue>::from_redis_value(val)?;
         let kv_pairs = Self::parse_kv_pairs(val_inner)?;
         let properties = kv_pairs
@@ -105,40 +121,50 @@ impl GetQueryRelationship {
             .ok_or_else(|| err_msg("No cid property found in query result"))?
             .1;
 
-        Ok(cid
-            .as_str()
-            .ok_or_else(|| err_msg("cid property is not a string"))?
-            .to_owned())
+        Ok(Some(
+            cid.as_str()
+                .ok_or_else(|| err_msg("cid property is not a string"))?
+                .to_owned(),
+        ))
     }
 
     /// Construct a single Entity from all the relationships
-    pub fn merge_into_entity(relationships: Vec<Self>) -> Result<Option<Entity>, StdError> {
+    ///
+    /// Returns `None` for leaf nodes, which are only referenced by other entities and don't have
+    /// a type.
+    pub fn merge_into_entity(relationships: Vec<Self>) -> Result<Option<Entity>, Error> {
         trace!("Relationships to merge into entity: {:?}", &relationships);
-        if relationships.len() == 0 {
-            return Ok(None);
-        }
-        let mut entity = relationships
-            .first()
-            .unwrap()
-            .n_value
-            .as_object()
-            .unwrap()
-            .clone();
+        let mut entity = match relationships.first() {
+            Some(relationship) => relationship
+                .n_value
+                .as_object()
+                .ok_or_else(|| err_msg("Node properties are not an object"))?
+                .clone(),
+            None => return Ok(None),
+        };
 
         // build empty entity with which we can check if fields are supposed to be arrays
-        let entity_kind = EntityKind::from_name(entity["type"].clone().as_str().unwrap()).unwrap();
-        let empty_entity: JsonValue =
-            serde_json::to_value(FormatWeb3(entity_kind.empty_entity())).unwrap();
+        let kind_name = match entity.get("type").and_then(|kind| kind.as_str()) {
+            Some(kind_name) => kind_name.to_owned(),
+            None => return Ok(None),
+        };
+        validate_entity_kind(&kind_name)?;
+        // the kind has been validated above
+        let entity_kind = EntityKind::from_name(&kind_name).unwrap();
+        let empty_entity: JsonValue = serde_json::to_value(FormatWeb3(entity_kind.empty_entity()))?;
         let is_array_key = |key: &str| match empty_entity.get(key) {
             Some(JsonValue::Array(_)) => Some(true),
             Some(JsonValue::String(_)) => Some(false),
             Some(JsonValue::Null) => Some(false),
-            None => None,
-            _ => unimplemented!(),
+            _ => None,
         };
 
         for relationship in relationships {
-            let rel_type = &relationship.relationship;
+            let (rel_type, m_cid) = match (relationship.relationship, relationship.m_cid) {
+                (Some(rel_type), Some(m_cid)) => (rel_type, m_cid),
+                _ => continue,
+            };
+            let rel_type = &rel_type;
             match is_array_key(rel_type) {
                 None => {
                     continue;
@@ -147,19 +173,17 @@ impl GetQueryRelationship {
                     if !entity.contains_key(rel_type) {
                         entity.insert(rel_type.clone(), JsonValue::Array(Vec::new()));
                     }
-                    entity[rel_type]
-                        .as_array_mut()
-                        .unwrap()
-                        .push(JsonValue::String(relationship.m_cid));
+                    if let Some(values) = entity[rel_type].as_array_mut() {
+                        values.push(JsonValue::String(m_cid));
+                    }
                 }
                 Some(false) => {
-                    entity.insert(rel_type.clone(), JsonValue::String(relationship.m_cid));
+                    entity.insert(rel_type.clone(), JsonValue::String(m_cid));
                 }
             }
         }
 
-        let web3_entity: FormatWeb3<Entity> =
-            serde_json::from_value(JsonValue::Object(entity)).unwrap();
+        let web3_entity: FormatWeb3<Entity> = serde_json::from_value(JsonValue::Object(entity))?;
         Ok(Some(web3_entity.0))
     }
 }
@@ -170,14 +194,14 @@ pub struct CidList {
 }
 
 impl CidList {
-    pub fn parse(rel_result: RedisValue) -> Result<Self, StdError> {
+    pub fn parse(rel_result: RedisValue) -> Result<Self, Error> {
         let val_inner = Vec::<RedisValue>::from_redis_value(&rel_result)?;
-        let cids: Result<Vec<String>, _> = val_inner
+        let cids: Result<Vec<String>, Error> = val_inner
             .into_iter()
             .map(|n| {
                 let bulk = Vec::<RedisValue>::from_redis_value(&n)?;
-                let cid = String::from_redis_value(&bulk[0]);
-                cid
+                let cid = String::from_redis_value(column(&bulk, 0)?)?;
+                Ok(cid)
             })
             .collect();
 
@@ -1,12 +1,13 @@
 #![recursion_limit = "128"]
 
-use redis::FromRedisValue;
+use cid::ToCid;
 use rlay_backend::rpc::*;
 use rlay_backend::BackendRpcMethods;
 use rlay_backend_redisgraph::*;
 use rlay_ontology::prelude::*;
 use rustc_hex::{FromHex, ToHex};
 use serde_json::Value;
+use std::time::Duration;
 use testcontainers::*;
 use tokio::runtime::Runtime;
 
@@ -18,7 +19,7 @@ fn redis_container() -> images::generic::GenericImage {
 }
 
 #[test]
-fn store_entity_returns_correct_cid() {
+fn restore_and_get_roundtrip_works() {
     let _ = env_logger::try_init();
     let mut rt = Runtime::new().unwrap();
     let docker = clients::Cli::default();
@@ -29,75 +30,25 @@ fn store_entity_returns_correct_cid() {
     let backend_config = config::RedisgraphBackendConfig {
         uri: connection_string,
         graph_name: "rlaygraph".to_owned(),
+        retry: config::RetryConfig::default(),
     };
     let mut backend = RedisgraphBackend::from_config(backend_config);
 
-    let insert_cid = rt
+    let ann_cid = rt
         .block_on(backend.store_entity(&Annotation::default().into(), &Value::Null))
         .unwrap();
-    let expected_cid: Vec<u8> =
-        "019580031b2088868a58d3aac6d2558a29b3b8cacf3c9788364f57a3470158283121a15dcae0"
-            .from_hex()
-            .unwrap();
-
-    assert_eq!(expected_cid, insert_cid.to_bytes());
-}
-
-#[test]
-fn multiple_store_produces_correct_number_of_nodes() {
-    let _ = env_logger::try_init();
-    let mut rt = Runtime::new().unwrap();
-    let docker = clients::Cli::default();
-    let node = docker.run(redis_container());
-
-    let connection_string = format!("redis://127.0.0.1:{}", node.get_host_port(6379).unwrap());
-
-    let backend_config = config::RedisgraphBackendConfig {
-        uri: connection_string.clone(),
-        graph_name: "rlaygraph".to_owned(),
-    };
-    let mut backend = RedisgraphBackend::from_config(backend_config);
-    let mut backend2 = backend.clone();
-
-    rt.block_on(backend.store_entity(&Annotation::default().into(), &Value::Null))
-        .unwrap();
-    rt.block_on(backend2.store_entity(&Annotation::default().into(), &Value::Null))
-        .unwrap();
-
-    let redis_client = redis::Client::open(connection_string.as_str()).unwrap();
-    let count_query_res: Vec<redis::Value> = redis::cmd("GRAPH.QUERY")
-        .arg("rlaygraph")
-        .arg("MATCH (n:RlayEntity) RETURN COUNT(n)")
-        .query(&mut redis_client.get_connection().unwrap())
-        .unwrap();
-    let count_res = Vec::<redis::Value>::from_redis_value(&count_query_res[1]).unwrap();
-    let count_bulk = Vec::<redis::Value>::from_redis_value(&count_res[0]).unwrap();
-    let count = u64::from_redis_value(&count_bulk[0]).unwrap();
-
-    assert_eq!(2, count);
-}
-
-#[test]
-fn store_and_get_roundtrip_works() {
-    let _ = env_logger::try_init();
-    let mut rt = Runtime::new().unwrap();
-    let docker = clients::Cli::default();
-    let node = docker.run(redis_container());
-
-    let connection_string = format!("redis://127.0.0.1:{}", node.get_host_port(6379).unwrap());
-
-    let backend_config = config::RedisgraphBackendConfig {
-        uri: connection_string,
-        graph_name: "rlaygraph".to_owned(),
-    };
-    let mut backend = RedisgraphBackend::from_config(backend_config);
-    let mut backend2 = backend.clone();
-
-    let inserted_entity: Entity = Annotation::default().into();
-    let inserted_entity2 = inserted_entity.clone();
+    let inserted_entity: Entity = Individual {
+        annotations: vec![ann_cid.to_bytes()],
+        ..Individual::default()
+    }
+    .into();
     let inserted_cid = rt
-        .block_on(backend2.store_entity(&inserted_entity2, &Value::Null))
+        .block_on(backend.store_entity(&inserted_entity, &Value::Null))
+        .unwrap();
+    let restored_cid = rt
+        .block_on(backend.store_entity(&inserted_entity, &Value::Null))
         .unwrap();
+    assert_eq!(inserted_cid, restored_cid);
     let formatted_cid: String = format!("0x{}", inserted_cid.to_bytes().to_hex());
 
     let retrieved_entity = rt
@@ -107,7 +58,11 @@ fn store_and_get_roundtrip_works() {
 
     assert_eq!(
         inserted_entity, retrieved_entity,
-        "inserted and retrieved entity don't match"
+        "re-stored and retrieved entity don't match"
+    );
+    assert_eq!(
+        inserted_cid.to_bytes(),
+        retrieved_entity.to_cid().unwrap().to_bytes()
     );
 }
 
@@ -123,6 +78,7 @@ fn cypher_query_works() {
     let backend_config = config::RedisgraphBackendConfig {
         uri: connection_string,
         graph_name: "rlaygraph".to_owned(),
+        retry: config::RetryConfig::default(),
     };
     let mut backend = RedisgraphBackend::from_config(backend_config);
     let mut backend2 = backend.clone();
@@ -165,6 +121,7 @@ fn get_entity_leaf_node_returns_none() {
     let backend_config = config::RedisgraphBackendConfig {
         uri: connection_string,
         graph_name: "rlaygraph".to_owned(),
+        retry: config::RetryConfig::default(),
     };
     let mut backend = RedisgraphBackend::from_config(backend_config);
 
@@ -185,3 +142,84 @@ fn get_entity_leaf_node_returns_none() {
 
     assert!(retrieved_entity.is_none());
 }
+
+#[test]
+fn conformance_suite_passes() {
+    let _ = env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let docker = clients::Cli::default();
+    let node = docker.run(redis_container());
+
+    let connection_string = format!("redis://127.0.0.1:{}", node.get_host_port(6379).unwrap());
+
+    let backend_config = config::RedisgraphBackendConfig {
+        uri: connection_string,
+        graph_name: "rlaygraph".to_owned(),
+        retry: config::RetryConfig::default(),
+    };
+    let mut backend = RedisgraphBackend::from_config(backend_config);
+
+    rt.block_on(rlay_backend::conformance::run_all(&mut backend));
+}
+
+#[test]
+fn list_cids_rejects_unknown_kind() {
+    let _ = env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let docker = clients::Cli::default();
+    let node = docker.run(redis_container());
+
+    let connection_string = format!("redis://127.0.0.1:{}", node.get_host_port(6379).unwrap());
+
+    let backend_config = config::RedisgraphBackendConfig {
+        uri: connection_string,
+        graph_name: "rlaygraph".to_owned(),
+        retry: config::RetryConfig::default(),
+    };
+    let mut backend = RedisgraphBackend::from_config(backend_config);
+
+    let res = rt.block_on(backend.list_cids(Some("Annotation' OR 1=1 OR n.type = '")));
+
+    assert!(res.is_err());
+}
+
+#[test]
+fn get_entity_with_quotes_in_cid_returns_none() {
+    let _ = env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let docker = clients::Cli::default();
+    let node = docker.run(redis_container());
+
+    let connection_string = format!("redis://127.0.0.1:{}", node.get_host_port(6379).unwrap());
+
+    let backend_config = config::RedisgraphBackendConfig {
+        uri: connection_string,
+        graph_name: "rlaygraph".to_owned(),
+        retry: config::RetryConfig::default(),
+    };
+    let mut backend = RedisgraphBackend::from_config(backend_config);
+
+    rt.block_on(backend.store_entity(&Annotation::default().into(), &Value::Null))
+        .unwrap();
+
+    let retrieved_entity = rt
+        .block_on(backend.get_entity("' OR 1=1 OR n.cid = '"))
+        .unwrap();
+
+    assert!(retrieved_entity.is_none());
+}
+
+#[test]
+fn retry_backoff_doubles_up_to_max() {
+    let retry_config = config::RetryConfig {
+        max_attempts: 10,
+        initial_backoff_ms: 100,
+        max_backoff_ms: 1000,
+    };
+
+    assert_eq!(Duration::from_millis(100), retry_config.backoff(1));
+    assert_eq!(Duration::from_millis(200), retry_config.backoff(2));
+    assert_eq!(Duration::from_millis(800), retry_config.backoff(4));
+    assert_eq!(Duration::from_millis(1000), retry_config.backoff(5));
+    assert_eq!(Duration::from_millis(1000), retry_config.backoff(64));
+}
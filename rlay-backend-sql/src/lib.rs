@@ -0,0 +1,344 @@
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+extern crate static_assertions as sa;
+
+pub mod config;
+mod schema;
+
+use async_trait::async_trait;
+use cid::{Cid, ToCid};
+use failure::{err_msg, format_err, Error};
+use futures::future::BoxFuture;
+use futures::prelude::*;
+use once_cell::sync::OnceCell;
+use rlay_backend::metrics;
+use rlay_backend::relationships::entity_relationships;
+use rlay_backend::rpc::*;
+use rlay_backend::{BackendFromConfigAndSyncState, GetEntity, ResolveEntity};
+use rlay_ontology::prelude::*;
+use rustc_hex::{FromHex, ToHex};
+use serde_json::Value;
+use sqlx::any::AnyPool;
+use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+
+use crate::config::SqlBackendConfig;
+
+/// Map with CID of resolved entity as key, and Vec of all contained entities within the resolved
+/// entity as values.
+type ResolvedEntities = HashMap<String, Vec<Entity>>;
+
+sa::assert_impl_all!(SqlBackend: Send, Sync);
+/// Backend that stores entities in a SQL database (SQLite or PostgreSQL).
+#[derive(Clone)]
+pub struct SqlBackend {
+    pub config: SqlBackendConfig,
+    client: OnceCell<AnyPool>,
+}
+
+#[derive(Clone)]
+pub struct SyncState {
+    pub connection_pool: Option<AnyPool>,
+}
+
+impl SqlBackend {
+    pub fn from_config(config: SqlBackendConfig) -> Self {
+        Self {
+            config,
+            client: OnceCell::new(),
+        }
+    }
+
+    pub async fn client(&self) -> Result<AnyPool, Error> {
+        if let Some(client) = self.client.get() {
+            let in_use = (client.size() as usize).saturating_sub(client.num_idle());
+            metrics::set_pool_connections_in_use("sql", in_use);
+            return Ok(client.clone());
+        }
+
+        trace!("Creating new connection pool for backend.");
+        let new_connection = self.config.connection_pool().await?;
+        let _ = self.client.set(new_connection.clone());
+        Ok(new_connection)
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        let client = self.client().await?;
+        sqlx::query("SELECT 1").execute(&client).await?;
+        Ok(())
+    }
+
+    fn parse_payload(payload: &str) -> Result<Entity, Error> {
+        let web3_entity: FormatWeb3<Entity> = serde_json::from_str(payload)?;
+        Ok(web3_entity.0)
+    }
+
+    async fn get_entity(&self, cid: String) -> Result<Option<Entity>, Error> {
+        let entities = Self::get_entities(self, vec![cid]).await?;
+        Ok(entities.get(0).map(|n| n.to_owned()))
+    }
+
+    pub async fn get_entities(&self, cids: Vec<String>) -> Result<Vec<Entity>, Error> {
+        let client = self.client().await?;
+
+        let deduped_cids: Vec<String> = {
+            let mut seen_cids = HashSet::new();
+            cids.into_iter()
+                .filter(|cid| seen_cids.insert(cid.to_owned()))
+                .collect()
+        };
+        if deduped_cids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // query in chunks, so that the number of placeholders stays within the database limits
+        let mut entities_by_cid = HashMap::new();
+        for chunk in deduped_cids.chunks(schema::GET_ENTITIES_CHUNK_SIZE) {
+            let query = schema::get_entities(chunk.len());
+            trace!("SQL QUERY: {:?}", query);
+            let mut statement_query = sqlx::query(&query);
+            for cid in chunk {
+                statement_query = statement_query.bind(cid.to_owned());
+            }
+
+            let start = std::time::Instant::now();
+            let rows = statement_query.fetch_all(&client).await?;
+            let end = std::time::Instant::now();
+            trace!("Query duration: {:?}", end - start);
+            metrics::observe_query_duration("sql", metrics::query::GET_ENTITIES, end - start);
+
+            for row in rows {
+                let cid: String = row.try_get("cid")?;
+                let payload: String = row.try_get("payload")?;
+                entities_by_cid.insert(cid, Self::parse_payload(&payload)?);
+            }
+        }
+
+        // return the entities in the order they were requested in
+        let entities: Vec<Entity> = deduped_cids
+            .iter()
+            .filter_map(|cid| entities_by_cid.remove(cid))
+            .collect();
+        trace!("get_entities retrieved {} entities", entities.len());
+
+        Ok(entities)
+    }
+
+    async fn resolve_entity(&self, cid: String) -> Result<ResolvedEntities, Error> {
+        self.resolve_entities(vec![cid]).await
+    }
+
+    pub async fn resolve_entities(&self, cids: Vec<String>) -> Result<ResolvedEntities, Error> {
+        let client = self.client().await?;
+
+        let mut resolved_entities = ResolvedEntities::new();
+        for cid in cids {
+            trace!("SQL QUERY: {:?}", schema::RESOLVE_ENTITY);
+            let start = std::time::Instant::now();
+            let rows = sqlx::query(schema::RESOLVE_ENTITY)
+                .bind(cid.to_owned())
+                .fetch_all(&client)
+                .await?;
+            let end = std::time::Instant::now();
+            trace!("Query duration: {:?}", end - start);
+            metrics::observe_query_duration("sql", metrics::query::RESOLVE_ENTITIES, end - start);
+
+            // the first row is always the resolved entity itself, if it is known
+            if rows.is_empty() {
+                continue;
+            }
+            let entities = rows
+                .iter()
+                .map(|row| {
+                    let payload: String = row.try_get("payload")?;
+                    Self::parse_payload(&payload)
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            resolved_entities.insert(cid, entities);
+        }
+        trace!(
+            "resolve_entities retrieved {} entities",
+            resolved_entities.len()
+        );
+
+        Ok(resolved_entities)
+    }
+
+    async fn list_cids(&self, entity_kind: Option<String>) -> Result<Vec<String>, Error> {
+        let client = self.client().await?;
+
+        let start = std::time::Instant::now();
+        let rows = match entity_kind {
+            None => sqlx::query(schema::LIST_CIDS).fetch_all(&client).await?,
+            Some(kind) => {
+                validate_entity_kind(&kind)?;
+                sqlx::query(schema::LIST_CIDS_FOR_KIND)
+                    .bind(kind)
+                    .fetch_all(&client)
+                    .await?
+            }
+        };
+        metrics::observe_query_duration("sql", metrics::query::QUERY_ENTITIES, start.elapsed());
+
+        rows.iter()
+            .map(|row| Ok(row.try_get::<String, _>("cid")?))
+            .collect()
+    }
+
+    async fn store_entity(&self, entity: Entity) -> Result<Cid, Error> {
+        let cids = self.store_entities(vec![entity]).await?;
+        Ok(cids[0].clone())
+    }
+
+    async fn store_entities(&self, entities: Vec<Entity>) -> Result<Vec<Cid>, Error> {
+        let client = self.client().await?;
+
+        let start = std::time::Instant::now();
+        let mut transaction = client.begin().await?;
+        let mut cids = Vec::new();
+        for entity in entities.iter() {
+            let cid = entity
+                .to_cid()
+                .map_err(|_| err_msg("Unable to calculate CID of entity"))?;
+            let formatted_cid = format!("0x{}", cid.to_bytes().to_hex());
+            let kind_name: &str = entity.kind().into();
+            let payload = serde_json::to_string(&FormatWeb3(entity.clone()))?;
+
+            sqlx::query(schema::INSERT_ENTITY)
+                .bind(formatted_cid.clone())
+                .bind(kind_name.to_owned())
+                .bind(payload)
+                .execute(&mut transaction)
+                .await?;
+
+            for (field, related_cid) in entity_relationships(entity) {
+                sqlx::query(schema::INSERT_EDGE)
+                    .bind(formatted_cid.clone())
+                    .bind(field)
+                    .bind(related_cid)
+                    .execute(&mut transaction)
+                    .await?;
+            }
+
+            cids.push(cid);
+        }
+        transaction.commit().await?;
+        let end = std::time::Instant::now();
+        trace!("Query duration: {:?}", end - start);
+        metrics::observe_query_duration("sql", metrics::query::STORE_ENTITIES, end - start);
+
+        Ok(cids)
+    }
+}
+
+impl BackendFromConfigAndSyncState for SqlBackend {
+    type C = SqlBackendConfig;
+    type S = SyncState;
+    type R = Box<dyn Future<Output = Result<Self, Error>> + Send + Unpin>;
+
+    fn from_config_and_syncstate(config: Self::C, sync_state: Self::S) -> Self::R {
+        let client_cell = OnceCell::new();
+        if let Some(existing_connection) = sync_state.connection_pool {
+            let _ = client_cell.set(existing_connection.clone());
+        }
+        Box::new(future::ok(Self {
+            config,
+            client: client_cell,
+        }))
+    }
+}
+
+#[async_trait]
+impl GetEntity for SqlBackend {
+    async fn get_entity(&self, cid: &[u8]) -> Result<Option<Entity>, Error> {
+        let cid = format!("0x{}", cid.to_hex());
+        Self::get_entity(self, cid.to_owned()).await
+    }
+}
+
+#[async_trait]
+impl ResolveEntity for SqlBackend {
+    async fn resolve_entity(&self, cid: &[u8]) -> Result<HashMap<Vec<u8>, Vec<Entity>>, Error> {
+        let cid = format!("0x{}", cid.to_hex());
+        let resolved_entities = Self::resolve_entity(self, cid.to_owned()).await?;
+        resolved_entities
+            .into_iter()
+            .map(|(old_key, value)| {
+                let key = old_key
+                    .trim_start_matches("0x")
+                    .from_hex()
+                    .map_err(|_| format_err!("Invalid CID {}", old_key))?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+}
+
+impl BackendRpcMethodGetEntity for SqlBackend {
+    fn get_entity(&mut self, cid: &str) -> BoxFuture<Result<Option<Entity>, Error>> {
+        Box::pin(Self::get_entity(self, cid.to_owned()))
+    }
+}
+
+impl BackendRpcMethodGetEntities for SqlBackend {
+    fn get_entities(&mut self, cids: Vec<String>) -> BoxFuture<Result<Vec<Entity>, Error>> {
+        Box::pin(Self::get_entities(self, cids))
+    }
+}
+
+impl BackendRpcMethodStoreEntity for SqlBackend {
+    fn store_entity(
+        &mut self,
+        entity: &Entity,
+        _options_object: &Value,
+    ) -> BoxFuture<Result<Cid, Error>> {
+        Box::pin(Self::store_entity(self, entity.to_owned()))
+    }
+}
+
+impl BackendRpcMethodStoreEntities for SqlBackend {
+    fn store_entities(
+        &mut self,
+        entities: &Vec<Entity>,
+        _options_object: &Value,
+    ) -> BoxFuture<Result<Vec<Cid>, Error>> {
+        Box::pin(Self::store_entities(self, entities.to_owned()))
+    }
+}
+
+impl BackendRpcMethodListCids for SqlBackend {
+    fn list_cids(&mut self, entity_kind: Option<&str>) -> BoxFuture<Result<Vec<String>, Error>> {
+        Box::pin(Self::list_cids(self, entity_kind.map(|n| n.to_owned())))
+    }
+}
+
+impl BackendRpcMethodResolveEntity for SqlBackend {
+    fn resolve_entity(
+        &mut self,
+        cid: &str,
+    ) -> BoxFuture<Result<HashMap<String, Vec<Entity>>, Error>> {
+        Box::pin(Self::resolve_entity(self, cid.to_owned()))
+    }
+}
+
+impl BackendRpcMethodResolveEntities for SqlBackend {
+    fn resolve_entities(
+        &mut self,
+        cids: Vec<String>,
+    ) -> BoxFuture<Result<HashMap<String, Vec<Entity>>, Error>> {
+        Box::pin(Self::resolve_entities(self, cids))
+    }
+}
+
+impl BackendRpcMethodNeo4jQuery for SqlBackend {}
+impl BackendRpcMethods for SqlBackend {
+    fn unsupported_rpc_methods(&self) -> Vec<&'static str> {
+        vec!["neo4j_query"]
+    }
+
+    fn ping(&mut self) -> BoxFuture<Result<(), Error>> {
+        Box::pin(Self::ping(self))
+    }
+}
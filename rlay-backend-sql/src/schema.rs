@@ -0,0 +1,80 @@
+//! Database schema and queries of the SQL backend.
+//!
+//! All statements are written in the common subset of SQLite and PostgreSQL SQL.
+
+/// Statements that create the tables and indexes if they don't exist yet.
+///
+/// - `entities` contains every stored entity in its web3 JSON format.
+/// - `edges` contains a row for every CID-typed field value of a stored entity, see
+///   `rlay_backend::relationships`.
+pub const SCHEMA_STATEMENTS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS entities (
+        cid TEXT PRIMARY KEY,
+        kind TEXT NOT NULL,
+        payload TEXT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS entities_kind ON entities (kind)",
+    "CREATE TABLE IF NOT EXISTS edges (
+        from_cid TEXT NOT NULL,
+        field TEXT NOT NULL,
+        to_cid TEXT NOT NULL,
+        PRIMARY KEY (from_cid, field, to_cid)
+    )",
+    "CREATE INDEX IF NOT EXISTS edges_to_cid ON edges (to_cid, field)",
+];
+
+pub const INSERT_ENTITY: &str =
+    "INSERT INTO entities (cid, kind, payload) VALUES ($1, $2, $3) ON CONFLICT (cid) DO NOTHING";
+
+pub const INSERT_EDGE: &str = "INSERT INTO edges (from_cid, field, to_cid) VALUES ($1, $2, $3)
+    ON CONFLICT (from_cid, field, to_cid) DO NOTHING";
+
+pub const LIST_CIDS: &str = "SELECT cid FROM entities ORDER BY cid";
+
+pub const LIST_CIDS_FOR_KIND: &str = "SELECT cid FROM entities WHERE kind = $1 ORDER BY cid";
+
+/// Query for all entities that make up the resolved entity with the CID `$1`.
+///
+/// Mirrors the pattern used by the Neo4j backend: Starting from the entity itself and the
+/// entities that have it as `subject`, outgoing relationships are followed two levels deep, and
+/// one level further for `Individual`s (except for the resolved entity itself).
+pub const RESOLVE_ENTITY: &str = "
+    WITH RECURSIVE resolved (cid, depth) AS (
+        SELECT CAST($1 AS TEXT), 0
+        UNION
+        SELECT from_cid, 1 FROM edges WHERE to_cid = $1 AND field = 'subject'
+        UNION
+        SELECT edges.to_cid, resolved.depth + 1
+        FROM resolved
+        JOIN edges ON edges.from_cid = resolved.cid
+        WHERE resolved.depth < 2
+            OR (
+                resolved.depth = 2
+                AND resolved.cid <> $1
+                AND EXISTS (
+                    SELECT 1 FROM entities
+                    WHERE entities.cid = resolved.cid AND entities.kind = 'Individual'
+                )
+            )
+    )
+    SELECT entities.cid, entities.payload, MIN(resolved.depth) AS depth
+    FROM resolved
+    JOIN entities ON entities.cid = resolved.cid
+    GROUP BY entities.cid, entities.payload
+    ORDER BY depth, entities.cid";
+
+/// Maximum number of CIDs that are queried with a single `get_entities` statement.
+///
+/// Stays below the host parameter limits of SQLite (999 before 3.32) and PostgreSQL (65535).
+pub const GET_ENTITIES_CHUNK_SIZE: usize = 500;
+
+/// Query for the entities with the provided CIDs, with `$1` to `$<count>` as placeholders.
+///
+/// `count` must not exceed `GET_ENTITIES_CHUNK_SIZE`.
+pub fn get_entities(count: usize) -> String {
+    let placeholders: Vec<String> = (1..=count).map(|i| format!("${}", i)).collect();
+    format!(
+        "SELECT cid, payload FROM entities WHERE cid IN ({})",
+        placeholders.join(", ")
+    )
+}
@@ -0,0 +1,108 @@
+use rlay_backend::rpc::*;
+use rlay_backend_sql::*;
+use rlay_ontology::prelude::*;
+use rustc_hex::ToHex;
+use serde_json::Value;
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+fn sql_backend(data_dir: &TempDir) -> SqlBackend {
+    let backend_config = config::SqlBackendConfig {
+        uri: format!(
+            "sqlite://{}?mode=rwc",
+            data_dir.path().join("rlay.db").display()
+        ),
+        max_connections: 1,
+    };
+    SqlBackend::from_config(backend_config)
+}
+
+#[test]
+/// The recursive query follows outgoing edges two levels deep, plus the entities that have the
+/// resolved entity as their `subject`.
+fn resolve_entity_follows_edges_up_to_depth_two() {
+    let _ = env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let mut backend = sql_backend(&data_dir);
+
+    let mut store = |entity: &Entity| {
+        rt.block_on(backend.store_entity(entity, &Value::Null))
+            .unwrap()
+            .to_bytes()
+    };
+    let annotation = |seed: u8, annotations: Vec<Vec<u8>>| -> Entity {
+        Annotation {
+            annotations,
+            property: vec![1, seed],
+            value: vec![2, seed],
+            ..Annotation::default()
+        }
+        .into()
+    };
+    let ann3 = annotation(3, vec![]);
+    let ann2 = annotation(2, vec![store(&ann3)]);
+    let ann1 = annotation(1, vec![store(&ann2)]);
+    let ind: Entity = Individual {
+        annotations: vec![store(&ann1)],
+        ..Individual::default()
+    }
+    .into();
+    let ind_cid = store(&ind);
+    let dpa: Entity = DataPropertyAssertion {
+        subject: Some(ind_cid.clone()),
+        property: Some(vec![12, 34]),
+        target: Some(vec![56, 78]),
+        ..DataPropertyAssertion::default()
+    }
+    .into();
+    store(&dpa);
+
+    let formatted_cid: String = format!("0x{}", ind_cid.to_hex());
+    let resolved_entities = rt
+        .block_on(BackendRpcMethodResolveEntity::resolve_entity(
+            &mut backend,
+            &formatted_cid,
+        ))
+        .unwrap();
+    let resolved = resolved_entities.get(&formatted_cid).unwrap();
+
+    assert_eq!(Some(&ind), resolved.first());
+    assert_eq!(4, resolved.len(), "unexpected entities: {:?}", resolved);
+    for entity in &[dpa, ann1, ann2] {
+        assert!(resolved.contains(entity), "missing {:?}", entity);
+    }
+    assert!(!resolved.contains(&ann3));
+}
+
+#[test]
+fn conformance_suite_passes() {
+    let _ = env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let mut backend = sql_backend(&data_dir);
+
+    rt.block_on(rlay_backend::conformance::run_all(&mut backend));
+}
+
+#[test]
+fn get_entities_with_more_cids_than_placeholders_works() {
+    let _ = env_logger::try_init();
+    let mut rt = Runtime::new().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let mut backend = sql_backend(&data_dir);
+
+    let inserted_entity: Entity = Annotation::default().into();
+    let inserted_cid = rt
+        .block_on(backend.store_entity(&inserted_entity, &Value::Null))
+        .unwrap();
+
+    // more unknown CIDs than SQLite accepts placeholders in a single statement
+    let mut cids: Vec<String> = (0..2000).map(|i| format!("0x{:064x}", i)).collect();
+    cids.push(format!("0x{}", inserted_cid.to_bytes().to_hex()));
+    let retrieved_entities = rt
+        .block_on(SqlBackend::get_entities(&backend, cids))
+        .unwrap();
+
+    assert_eq!(vec![inserted_entity], retrieved_entities);
+}
@@ -0,0 +1,321 @@
+//! Test suite that checks that a backend behaves like all other backends.
+//!
+//! Every check stores the entities it needs itself and tolerates other entities being present,
+//! so all checks can be run against the same backend instance (e.g. via `run_all`). Failures
+//! panic like regular test assertions, with the name of the check in the message.
+//!
+//! Only available with the `conformance` feature, which is meant to be enabled in the
+//! `dev-dependencies` of backend crates.
+use cid::ToCid;
+use rlay_ontology::prelude::*;
+use rustc_hex::{FromHex, ToHex};
+use serde_json::Value;
+
+use crate::rpc::{BackendRpcMethodGetEntity, BackendRpcMethodResolveEntity, BackendRpcMethods};
+use crate::{GetEntity, ResolveEntity};
+
+/// CID that is never stored by any of the checks.
+const UNKNOWN_CID: &str =
+    "0x019580031b20ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+/// CID of `Annotation::default()`, as documented for the CID calculation.
+const DEFAULT_ANNOTATION_CID: &str =
+    "0x019580031b2088868a58d3aac6d2558a29b3b8cacf3c9788364f57a3470158283121a15dcae0";
+
+fn format_cid(cid_bytes: &[u8]) -> String {
+    format!("0x{}", cid_bytes.to_hex())
+}
+
+fn entity_cid(entity: &Entity) -> String {
+    format_cid(&entity.to_cid().unwrap().to_bytes())
+}
+
+/// Annotation that is unique to the provided `seed`, so checks don't see each others entities.
+fn annotation(seed: u8) -> Entity {
+    Annotation {
+        property: vec![1, seed],
+        value: vec![2, seed],
+        ..Annotation::default()
+    }
+    .into()
+}
+
+async fn store<B: BackendRpcMethods>(backend: &mut B, entity: &Entity) -> String {
+    let cid = backend.store_entity(entity, &Value::Null).await.unwrap();
+    format_cid(&cid.to_bytes())
+}
+
+/// Run all checks of the suite against `backend`.
+pub async fn run_all<B>(backend: &mut B)
+where
+    B: BackendRpcMethods + GetEntity + ResolveEntity,
+{
+    ping_succeeds(backend).await;
+    store_entity_returns_correct_cid(backend).await;
+    store_default_annotation_returns_known_cid(backend).await;
+    store_and_get_roundtrip(backend).await;
+    store_is_idempotent(backend).await;
+    get_entity_unknown_cid_returns_none(backend).await;
+    get_entities_skips_duplicates_and_missing(backend).await;
+    list_cids_filters_by_kind(backend).await;
+    list_cids_unknown_kind_fails(backend).await;
+    resolve_individual_with_class_assertion(backend).await;
+    resolve_unknown_cid_returns_nothing(backend).await;
+}
+
+/// A backend that is able to run the checks is also reported as able to serve requests.
+pub async fn ping_succeeds<B: BackendRpcMethods>(backend: &mut B) {
+    if let Err(err) = backend.ping().await {
+        panic!("ping_succeeds: ping failed: {}", err);
+    }
+}
+
+/// The CIDs returned by `store_entity` and `store_entities` match the ones calculated via `ToCid`.
+pub async fn store_entity_returns_correct_cid<B: BackendRpcMethods>(backend: &mut B) {
+    let entity = annotation(10);
+    let cid = backend.store_entity(&entity, &Value::Null).await.unwrap();
+    assert_eq!(
+        entity.to_cid().unwrap().to_bytes(),
+        cid.to_bytes(),
+        "store_entity_returns_correct_cid: CID returned by store_entity doesn't match"
+    );
+
+    let entities = vec![annotation(11), annotation(12)];
+    let cids = backend
+        .store_entities(&entities, &Value::Null)
+        .await
+        .unwrap();
+    assert_eq!(
+        entities
+            .iter()
+            .map(|entity| entity.to_cid().unwrap().to_bytes())
+            .collect::<Vec<_>>(),
+        cids.iter().map(|cid| cid.to_bytes()).collect::<Vec<_>>(),
+        "store_entity_returns_correct_cid: CIDs returned by store_entities don't match"
+    );
+}
+
+/// Storing `Annotation::default()` returns its well-known CID, under which it can be retrieved.
+pub async fn store_default_annotation_returns_known_cid<B: BackendRpcMethods>(backend: &mut B) {
+    let entity: Entity = Annotation::default().into();
+    let cid = store(backend, &entity).await;
+    assert_eq!(
+        DEFAULT_ANNOTATION_CID, cid,
+        "store_default_annotation_returns_known_cid: returned CID doesn't match"
+    );
+
+    let retrieved_entity = backend.get_entity(DEFAULT_ANNOTATION_CID).await.unwrap();
+    assert_eq!(
+        Some(entity),
+        retrieved_entity,
+        "store_default_annotation_returns_known_cid: entity retrieved via known CID doesn't match"
+    );
+}
+
+/// A stored entity can be retrieved via both the RPC methods and `GetEntity`.
+pub async fn store_and_get_roundtrip<B>(backend: &mut B)
+where
+    B: BackendRpcMethods + GetEntity,
+{
+    let entity = annotation(20);
+    let cid = store(backend, &entity).await;
+
+    let retrieved_entity = BackendRpcMethodGetEntity::get_entity(backend, &cid)
+        .await
+        .unwrap();
+    assert_eq!(
+        Some(&entity),
+        retrieved_entity.as_ref(),
+        "store_and_get_roundtrip: entity retrieved via RPC method doesn't match"
+    );
+
+    let cid_bytes: Vec<u8> = cid[2..].from_hex().unwrap();
+    let retrieved_entity = GetEntity::get_entity(backend, &cid_bytes).await.unwrap();
+    assert_eq!(
+        Some(&entity),
+        retrieved_entity.as_ref(),
+        "store_and_get_roundtrip: entity retrieved via GetEntity doesn't match"
+    );
+}
+
+/// Storing an entity that has already been stored doesn't change it or duplicate it.
+pub async fn store_is_idempotent<B: BackendRpcMethods>(backend: &mut B) {
+    let entity = annotation(30);
+    let first_cid = store(backend, &entity).await;
+    let second_cid = store(backend, &entity).await;
+    assert_eq!(
+        first_cid, second_cid,
+        "store_is_idempotent: CIDs of repeated stores don't match"
+    );
+
+    let retrieved_entities = backend.get_entities(vec![first_cid.clone()]).await.unwrap();
+    assert_eq!(
+        vec![entity],
+        retrieved_entities,
+        "store_is_idempotent: entity changed after being stored again"
+    );
+
+    let listed_cids = backend.list_cids(Some("Annotation")).await.unwrap();
+    assert_eq!(
+        1,
+        listed_cids.iter().filter(|cid| **cid == first_cid).count(),
+        "store_is_idempotent: CID is listed more than once"
+    );
+}
+
+/// Retrieving a CID that has never been stored yields no entity.
+pub async fn get_entity_unknown_cid_returns_none<B>(backend: &mut B)
+where
+    B: BackendRpcMethods + GetEntity,
+{
+    let retrieved_entity = BackendRpcMethodGetEntity::get_entity(backend, UNKNOWN_CID)
+        .await
+        .unwrap();
+    assert_eq!(
+        None, retrieved_entity,
+        "get_entity_unknown_cid_returns_none: RPC method returned an entity"
+    );
+
+    let cid_bytes: Vec<u8> = UNKNOWN_CID[2..].from_hex().unwrap();
+    let retrieved_entity = GetEntity::get_entity(backend, &cid_bytes).await.unwrap();
+    assert_eq!(
+        None, retrieved_entity,
+        "get_entity_unknown_cid_returns_none: GetEntity returned an entity"
+    );
+}
+
+/// `get_entities` returns every requested entity once, in order, and leaves out unknown CIDs.
+pub async fn get_entities_skips_duplicates_and_missing<B: BackendRpcMethods>(backend: &mut B) {
+    let first_entity = annotation(40);
+    let second_entity = annotation(41);
+    let first_cid = store(backend, &first_entity).await;
+    let second_cid = store(backend, &second_entity).await;
+
+    let retrieved_entities = backend
+        .get_entities(vec![
+            first_cid.clone(),
+            UNKNOWN_CID.to_owned(),
+            first_cid,
+            second_cid,
+        ])
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![first_entity, second_entity],
+        retrieved_entities,
+        "get_entities_skips_duplicates_and_missing: retrieved entities don't match"
+    );
+}
+
+/// `list_cids` lists stored entities, and only the ones of the requested kind.
+pub async fn list_cids_filters_by_kind<B: BackendRpcMethods>(backend: &mut B) {
+    let ann_cid = store(backend, &annotation(50)).await;
+    let ind: Entity = Individual {
+        annotations: vec![ann_cid[2..].from_hex().unwrap()],
+        ..Individual::default()
+    }
+    .into();
+    let ind_cid = store(backend, &ind).await;
+
+    let all_cids = backend.list_cids(None).await.unwrap();
+    assert!(
+        all_cids.contains(&ann_cid) && all_cids.contains(&ind_cid),
+        "list_cids_filters_by_kind: stored CIDs are missing without kind filter"
+    );
+
+    let ann_cids = backend.list_cids(Some("Annotation")).await.unwrap();
+    assert!(
+        ann_cids.contains(&ann_cid) && !ann_cids.contains(&ind_cid),
+        "list_cids_filters_by_kind: CIDs listed for Annotation don't match"
+    );
+
+    let ind_cids = backend.list_cids(Some("Individual")).await.unwrap();
+    assert!(
+        ind_cids.contains(&ind_cid) && !ind_cids.contains(&ann_cid),
+        "list_cids_filters_by_kind: CIDs listed for Individual don't match"
+    );
+}
+
+/// `list_cids` fails for kinds that aren't entity kinds instead of listing nothing.
+pub async fn list_cids_unknown_kind_fails<B: BackendRpcMethods>(backend: &mut B) {
+    assert!(
+        backend.list_cids(Some("NoEntityKind")).await.is_err(),
+        "list_cids_unknown_kind_fails: listing CIDs of unknown kind succeeded"
+    );
+}
+
+/// Resolving an `Individual` yields the `Individual` itself first, and (among others) the
+/// `ClassAssertion`s that have it as their subject, without duplicates.
+pub async fn resolve_individual_with_class_assertion<B>(backend: &mut B)
+where
+    B: BackendRpcMethods + ResolveEntity,
+{
+    let ann_cid = store(backend, &annotation(60)).await;
+    let class: Entity = Class {
+        annotations: vec![ann_cid[2..].from_hex().unwrap()],
+        ..Class::default()
+    }
+    .into();
+    let class_cid = store(backend, &class).await;
+    let ind: Entity = Individual {
+        annotations: vec![ann_cid[2..].from_hex().unwrap()],
+        ..Individual::default()
+    }
+    .into();
+    let ind_cid = store(backend, &ind).await;
+    let class_assertion: Entity = ClassAssertion {
+        subject: Some(ind_cid[2..].from_hex().unwrap()),
+        class: class_cid[2..].from_hex().unwrap(),
+        ..ClassAssertion::default()
+    }
+    .into();
+    store(backend, &class_assertion).await;
+
+    let resolved_entities = BackendRpcMethodResolveEntity::resolve_entity(backend, &ind_cid)
+        .await
+        .unwrap();
+    let resolved_ind = resolved_entities.get(&ind_cid).unwrap_or_else(|| {
+        panic!("resolve_individual_with_class_assertion: Individual has not been resolved")
+    });
+    assert_eq!(
+        Some(&ind),
+        resolved_ind.first(),
+        "resolve_individual_with_class_assertion: resolved entities don't start with Individual"
+    );
+    assert!(
+        resolved_ind.contains(&class_assertion),
+        "resolve_individual_with_class_assertion: ClassAssertion {} is missing in resolved entities",
+        entity_cid(&class_assertion)
+    );
+    assert_eq!(
+        resolved_ind.len(),
+        resolved_ind
+            .iter()
+            .map(entity_cid)
+            .collect::<std::collections::HashSet<_>>()
+            .len(),
+        "resolve_individual_with_class_assertion: resolved entities contain duplicates"
+    );
+
+    let cid_bytes: Vec<u8> = ind_cid[2..].from_hex().unwrap();
+    let resolved_entities = ResolveEntity::resolve_entity(backend, &cid_bytes)
+        .await
+        .unwrap();
+    assert_eq!(
+        Some(resolved_ind),
+        resolved_entities.get(&cid_bytes),
+        "resolve_individual_with_class_assertion: results of RPC method and ResolveEntity differ"
+    );
+}
+
+/// Resolving a CID that has never been stored yields no entities.
+pub async fn resolve_unknown_cid_returns_nothing<B: BackendRpcMethods>(backend: &mut B) {
+    let resolved_entities = backend.resolve_entity(UNKNOWN_CID).await.unwrap();
+    assert!(
+        resolved_entities
+            .get(UNKNOWN_CID)
+            .map(|entities| entities.is_empty())
+            .unwrap_or(true),
+        "resolve_unknown_cid_returns_nothing: entities have been resolved"
+    );
+}
@@ -1,3 +1,7 @@
+#[cfg(feature = "conformance")]
+pub mod conformance;
+pub mod metrics;
+pub mod relationships;
 #[cfg(feature = "rpc")]
 pub mod rpc;
 
@@ -0,0 +1,103 @@
+//! Prometheus metrics shared by all backends.
+//!
+//! The metrics are registered in the default prometheus registry, so that the client can expose
+//! them together with its own metrics.
+use once_cell::sync::Lazy;
+use prometheus::{HistogramTimer, HistogramVec, IntGaugeVec};
+use std::time::Duration;
+
+/// Types of queries, used as the `query` label of the query durations of all backends.
+pub mod query {
+    /// Retrieving a single entity by its CID.
+    pub const GET_ENTITY: &str = "get_entity";
+    /// Retrieving multiple entities by their CIDs.
+    pub const GET_ENTITIES: &str = "get_entities";
+    /// Storing one or more entities.
+    pub const STORE_ENTITIES: &str = "store_entities";
+    /// Resolving one or more entities with the entities they reference.
+    pub const RESOLVE_ENTITIES: &str = "resolve_entities";
+    /// Listing CIDs, either of all entities, of the entities of a kind, or via a custom query.
+    pub const QUERY_ENTITIES: &str = "query_entities";
+}
+
+static QUERY_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    prometheus::register_histogram_vec!(
+        "rlay_backend_query_duration_seconds",
+        "Duration of the queries to the backend database",
+        &["backend", "query"],
+        prometheus::exponential_buckets(0.001, 2.0, 16).unwrap()
+    )
+    .unwrap()
+});
+
+static POOL_CONNECTIONS_IN_USE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    prometheus::register_int_gauge_vec!(
+        "rlay_backend_pool_connections_in_use",
+        "Number of connections of the backend connection pool that are currently in use",
+        &["backend"]
+    )
+    .unwrap()
+});
+
+static POOL_CONNECTIONS_MAX: Lazy<IntGaugeVec> = Lazy::new(|| {
+    prometheus::register_int_gauge_vec!(
+        "rlay_backend_pool_connections_max",
+        "Maximum number of connections of the backend connection pool",
+        &["backend"]
+    )
+    .unwrap()
+});
+
+/// Record the duration of a query of type `query` (one of the constants in `query`) to the
+/// database of `backend`.
+pub fn observe_query_duration(backend: &str, query: &str, duration: Duration) {
+    QUERY_DURATION
+        .with_label_values(&[backend, query])
+        .observe(duration.as_secs_f64());
+}
+
+/// Start timing a query of type `query`, whose duration is recorded when the returned timer is
+/// dropped.
+///
+/// For backends where the time spent on a query is the time spent in a function call.
+pub fn start_query_timer(backend: &str, query: &str) -> HistogramTimer {
+    QUERY_DURATION
+        .with_label_values(&[backend, query])
+        .start_timer()
+}
+
+/// Record the number of connections of the pool of `backend` that are in use.
+pub fn set_pool_connections_in_use(backend: &str, in_use: usize) {
+    POOL_CONNECTIONS_IN_USE
+        .with_label_values(&[backend])
+        .set(in_use as i64);
+}
+
+/// Record the maximum number of connections of the pool of `backend`.
+pub fn set_pool_max_connections(backend: &str, max: usize) {
+    POOL_CONNECTIONS_MAX
+        .with_label_values(&[backend])
+        .set(max as i64);
+}
+
+/// Counts a connection of the pool of a backend as in use for as long as it is alive.
+///
+/// For connection pools that don't report their utilisation themselves.
+pub struct PoolConnectionGuard {
+    backend: &'static str,
+}
+
+impl PoolConnectionGuard {
+    pub fn new(backend: &'static str) -> Self {
+        POOL_CONNECTIONS_IN_USE.with_label_values(&[backend]).inc();
+        Self { backend }
+    }
+}
+
+impl Drop for PoolConnectionGuard {
+    fn drop(&mut self) {
+        POOL_CONNECTIONS_IN_USE
+            .with_label_values(&[self.backend])
+            .dec();
+    }
+}
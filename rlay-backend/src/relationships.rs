@@ -0,0 +1,122 @@
+//! Helpers for deriving the relationships between entities.
+//!
+//! Every CID-typed field of an entity (as reported by `cid_field_names()` of its kind) is
+//! a relationship from the entity to the entity with that CID. Backends that don't store entities
+//! in a graph database can use these helpers to build their own indexes.
+use failure::Error;
+use rlay_ontology::prelude::*;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Names of all fields of the entity kind that reference other entities via their CID.
+pub fn cid_field_names(kind_name: &str) -> Vec<String> {
+    let mut field_names: Vec<String> = vec![];
+
+    macro_rules! cid_field_names {
+        ($kind:path) => {{
+            let entity: Entity = <$kind>::default().into();
+            if Into::<&str>::into(entity.kind()) == kind_name {
+                field_names.extend(
+                    <$kind>::cid_field_names()
+                        .into_iter()
+                        .map(|field| field.to_owned().to_owned())
+                        .collect::<Vec<String>>(),
+                );
+            }
+        }};
+    }
+
+    rlay_ontology::call_with_entity_kinds!(ALL; cid_field_names!);
+
+    field_names
+}
+
+/// Outgoing relationships of an entity as `(field name, CID)` tuples.
+///
+/// The CIDs are in the hex-encoded, `0x`-prefixed format that is also used by the RPC methods.
+pub fn entity_relationships(entity: &Entity) -> Vec<(String, String)> {
+    let kind_name: &str = entity.kind().into();
+    let entity_val = serde_json::to_value(FormatWeb3(entity.clone())).unwrap();
+
+    let mut relationships = Vec::new();
+    for field_name in cid_field_names(kind_name) {
+        match entity_val.get(&field_name) {
+            Some(Value::Array(array_val)) => {
+                for cid in array_val.iter().filter_map(|n| n.as_str()) {
+                    relationships.push((field_name.clone(), cid.to_owned()));
+                }
+            }
+            Some(Value::String(cid)) => {
+                relationships.push((field_name.clone(), cid.to_owned()));
+            }
+            _ => {}
+        }
+    }
+
+    relationships
+}
+
+/// Resolve an entity into the entities that are related to it.
+///
+/// The result contains the entity itself, the entities it references, the entities that have
+/// it as their `subject` and the entities referenced by those. Referenced `Individual`s are
+/// followed one level further, which mirrors the query used by the Neo4j backend.
+///
+/// `get_entity` looks up a stored entity by its CID and `subject_of` returns the CIDs of all
+/// entities that have the provided CID as their `subject`. Returns `None` if the entity itself is
+/// unknown.
+pub fn resolve_entity_with<G, S>(
+    cid: &str,
+    mut get_entity: G,
+    mut subject_of: S,
+) -> Result<Option<Vec<Entity>>, Error>
+where
+    G: FnMut(&str) -> Result<Option<Entity>, Error>,
+    S: FnMut(&str) -> Result<Vec<String>, Error>,
+{
+    let root = match get_entity(cid)? {
+        Some(root) => root,
+        None => return Ok(None),
+    };
+
+    let mut resolved: Vec<Entity> = vec![root.clone()];
+    let mut seen_cids: HashSet<String> = HashSet::new();
+    seen_cids.insert(cid.to_owned());
+    let mut add_resolved = |related_cid: &str| -> Result<Option<Entity>, Error> {
+        let entity = match get_entity(related_cid)? {
+            Some(entity) => entity,
+            None => return Ok(None),
+        };
+        if seen_cids.insert(related_cid.to_owned()) {
+            resolved.push(entity.clone());
+        }
+        Ok(Some(entity))
+    };
+
+    let mut direct_cids: Vec<String> = entity_relationships(&root)
+        .into_iter()
+        .map(|(_, related_cid)| related_cid)
+        .collect();
+    direct_cids.extend(subject_of(cid)?);
+
+    for direct_cid in direct_cids {
+        let direct_entity = match add_resolved(&direct_cid)? {
+            Some(entity) => entity,
+            None => continue,
+        };
+        for (_, child_cid) in entity_relationships(&direct_entity) {
+            let child_entity = match add_resolved(&child_cid)? {
+                Some(entity) => entity,
+                None => continue,
+            };
+            if Into::<&str>::into(child_entity.kind()) != "Individual" || child_cid == cid {
+                continue;
+            }
+            for (_, grandchild_cid) in entity_relationships(&child_entity) {
+                add_resolved(&grandchild_cid)?;
+            }
+        }
+    }
+
+    Ok(Some(resolved))
+}
@@ -1,8 +1,8 @@
 use ambassador::delegatable_trait;
 use cid::Cid;
-use failure::{err_msg, Error};
-use futures::future::{err, BoxFuture, FutureExt};
-use rlay_ontology::ontology::Entity;
+use failure::{err_msg, format_err, Error};
+use futures::future::{err, ok, BoxFuture, FutureExt};
+use rlay_ontology::ontology::{Entity, EntityKind};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -119,4 +119,31 @@ pub trait BackendRpcMethods:
     + BackendRpcMethodListCids
     + BackendRpcMethodNeo4jQuery
 {
+    /// Names of the RPC methods (e.g. `"neo4j_query"`) for which the backend keeps the default
+    /// implementation, which only returns an error.
+    fn unsupported_rpc_methods(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// Check that the backend is able to serve requests, e.g. by running a trivial query against
+    /// its database.
+    ///
+    /// Backends that don't depend on an external database are always able to.
+    fn ping(&mut self) -> BoxFuture<Result<(), Error>> {
+        ok(()).boxed()
+    }
+}
+
+/// Check that `kind` is the name of a known entity kind.
+///
+/// Entity kinds provided via RPC methods have to be validated before they are used in queries,
+/// in places where query parameters are not supported (e.g. Neo4j labels).
+pub fn validate_entity_kind(kind: &str) -> Result<(), Error> {
+    match EntityKind::variants()
+        .iter()
+        .any(|variant| *variant == kind)
+    {
+        true => Ok(()),
+        false => Err(format_err!("Unknown entity kind \"{}\"", kind)),
+    }
 }
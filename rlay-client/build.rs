@@ -0,0 +1,5 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/rlay.proto")
+        .expect("Unable to compile protobuf definitions");
+}
@@ -9,10 +9,19 @@ use rlay_ontology::ontology::Entity;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::future::Future;
+use std::path::Path;
 use std::pin::Pin;
 
 use crate::config::backend::BackendConfig;
 
+#[cfg(feature = "backend_embedded")]
+pub use rlay_backend_embedded::{
+    config::EmbeddedBackendConfig, EmbeddedBackend, SyncState as EmbeddedSyncState,
+};
+#[cfg(feature = "backend_memory")]
+pub use rlay_backend_memory::{
+    config::MemoryBackendConfig, MemoryBackend, SyncState as MemorySyncState,
+};
 #[cfg(feature = "backend_neo4j")]
 pub use rlay_backend_neo4j::{
     config::Neo4jBackendConfig, Neo4jBackend, SyncState as Neo4jSyncState,
@@ -21,25 +30,62 @@ pub use rlay_backend_neo4j::{
 pub use rlay_backend_redisgraph::{
     config::RedisgraphBackendConfig, RedisgraphBackend, SyncState as RedisgraphSyncState,
 };
+#[cfg(feature = "backend_sql")]
+pub use rlay_backend_sql::{config::SqlBackendConfig, SqlBackend, SyncState as SqlSyncState};
 
 #[derive(Clone)]
 pub enum SyncState {
+    #[cfg(feature = "backend_embedded")]
+    Embedded(EmbeddedSyncState),
+    #[cfg(feature = "backend_memory")]
+    Memory(MemorySyncState),
     #[cfg(feature = "backend_neo4j")]
     Neo4j(Neo4jSyncState),
     #[cfg(feature = "backend_redisgraph")]
     Redisgraph(RedisgraphSyncState),
+    #[cfg(feature = "backend_sql")]
+    Sql(SqlSyncState),
 }
 
 impl SyncState {
-    pub async fn new(config: &BackendConfig) -> Self {
+    /// Create the state that is shared between all backend instances.
+    ///
+    /// `data_path` is the data directory of rlay-client, which backends that persist to disk
+    /// store their data in.
+    #[cfg_attr(not(feature = "backend_embedded"), allow(unused_variables))]
+    pub async fn new(config: &BackendConfig, data_path: &Path) -> Self {
         match config {
+            #[cfg(feature = "backend_embedded")]
+            BackendConfig::Embedded(config) => SyncState::new_embedded(&config, data_path),
+            #[cfg(feature = "backend_memory")]
+            BackendConfig::Memory(config) => SyncState::new_memory(&config),
             #[cfg(feature = "backend_neo4j")]
             BackendConfig::Neo4j(config) => SyncState::new_neo4j(&config).await,
             #[cfg(feature = "backend_redisgraph")]
             BackendConfig::Redisgraph(config) => SyncState::new_redisgraph(&config).await,
+            #[cfg(feature = "backend_sql")]
+            BackendConfig::Sql(config) => SyncState::new_sql(&config).await,
         }
     }
 
+    #[cfg(feature = "backend_embedded")]
+    pub fn new_embedded(config: &EmbeddedBackendConfig, data_path: &Path) -> Self {
+        SyncState::Embedded(EmbeddedSyncState {
+            db: Some(
+                config
+                    .open_db(data_path)
+                    .expect("Unable to open database of embedded backend"),
+            ),
+        })
+    }
+
+    #[cfg(feature = "backend_memory")]
+    pub fn new_memory(config: &MemoryBackendConfig) -> Self {
+        SyncState::Memory(MemorySyncState {
+            store: Some(config.store()),
+        })
+    }
+
     #[cfg(feature = "backend_neo4j")]
     pub async fn new_neo4j(config: &Neo4jBackendConfig) -> Self {
         SyncState::Neo4j(Neo4jSyncState {
@@ -49,11 +95,69 @@ impl SyncState {
 
     #[cfg(feature = "backend_redisgraph")]
     pub async fn new_redisgraph(config: &RedisgraphBackendConfig) -> Self {
+        let connection_pool = config
+            .connection_pool()
+            .await
+            .expect("Unable to connect to Redis of RedisGraph backend");
         SyncState::Redisgraph(RedisgraphSyncState {
-            connection_pool: Some(config.connection_pool().await),
+            connection_pool: std::sync::Arc::new(std::sync::RwLock::new(Some(connection_pool))),
+        })
+    }
+
+    #[cfg(feature = "backend_sql")]
+    pub async fn new_sql(config: &SqlBackendConfig) -> Self {
+        SyncState::Sql(SqlSyncState {
+            connection_pool: Some(
+                config
+                    .connection_pool()
+                    .await
+                    .expect("Unable to connect to database of SQL backend"),
+            ),
         })
     }
 
+    /// Flush pending writes to the backend and close its connections, before shutting down.
+    pub async fn close(&self) -> Result<(), Error> {
+        match self {
+            #[cfg(feature = "backend_embedded")]
+            SyncState::Embedded(sync_state) => {
+                if let Some(db) = sync_state.db.as_ref() {
+                    db.flush_async().await?;
+                }
+            }
+            #[cfg(feature = "backend_memory")]
+            SyncState::Memory(_) => {}
+            // The connections of the pool are closed once the last backend using it is dropped
+            #[cfg(feature = "backend_neo4j")]
+            SyncState::Neo4j(_) => {}
+            #[cfg(feature = "backend_redisgraph")]
+            SyncState::Redisgraph(_) => {}
+            #[cfg(feature = "backend_sql")]
+            SyncState::Sql(sync_state) => {
+                if let Some(connection_pool) = sync_state.connection_pool.as_ref() {
+                    connection_pool.close().await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "backend_embedded")]
+    pub fn as_embedded(self) -> Option<EmbeddedSyncState> {
+        match self {
+            SyncState::Embedded(sync_state) => Some(sync_state),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "backend_memory")]
+    pub fn as_memory(self) -> Option<MemorySyncState> {
+        match self {
+            SyncState::Memory(sync_state) => Some(sync_state),
+            _ => None,
+        }
+    }
+
     #[cfg(feature = "backend_neo4j")]
     pub fn as_neo4j(self) -> Option<Neo4jSyncState> {
         match self {
@@ -69,6 +173,14 @@ impl SyncState {
             _ => None,
         }
     }
+
+    #[cfg(feature = "backend_sql")]
+    pub fn as_sql(self) -> Option<SqlSyncState> {
+        match self {
+            SyncState::Sql(sync_state) => Some(sync_state),
+            _ => None,
+        }
+    }
 }
 
 sa::assert_impl_all!(Backend: Send);
@@ -83,10 +195,16 @@ sa::assert_impl_all!(Backend: Send);
 #[delegate(rlay_backend::BackendRpcMethodListCids)]
 #[delegate(rlay_backend::BackendRpcMethodNeo4jQuery)]
 pub enum Backend {
+    #[cfg(feature = "backend_embedded")]
+    Embedded(EmbeddedBackend),
+    #[cfg(feature = "backend_memory")]
+    Memory(MemoryBackend),
     #[cfg(feature = "backend_neo4j")]
     Neo4j(Neo4jBackend),
     #[cfg(feature = "backend_redisgraph")]
     Redisgraph(RedisgraphBackend),
+    #[cfg(feature = "backend_sql")]
+    Sql(SqlBackend),
 }
 
 impl BackendFromConfigAndSyncState for Backend {
@@ -96,6 +214,22 @@ impl BackendFromConfigAndSyncState for Backend {
 
     fn from_config_and_syncstate(config: Self::C, sync_state: Self::S) -> Self::R {
         match config {
+            #[cfg(feature = "backend_embedded")]
+            BackendConfig::Embedded(config) => {
+                let backend = EmbeddedBackend::from_config_and_syncstate(
+                    config,
+                    sync_state.unwrap().as_embedded().unwrap(),
+                );
+                backend.map_ok(|backend| Backend::Embedded(backend)).boxed()
+            }
+            #[cfg(feature = "backend_memory")]
+            BackendConfig::Memory(config) => {
+                let backend = MemoryBackend::from_config_and_syncstate(
+                    config,
+                    sync_state.unwrap().as_memory().unwrap(),
+                );
+                backend.map_ok(|backend| Backend::Memory(backend)).boxed()
+            }
             #[cfg(feature = "backend_neo4j")]
             BackendConfig::Neo4j(config) => {
                 let backend = Neo4jBackend::from_config_and_syncstate(
@@ -114,6 +248,14 @@ impl BackendFromConfigAndSyncState for Backend {
                     .map_ok(|backend| Backend::Redisgraph(backend))
                     .boxed()
             }
+            #[cfg(feature = "backend_sql")]
+            BackendConfig::Sql(config) => {
+                let backend = SqlBackend::from_config_and_syncstate(
+                    config,
+                    sync_state.unwrap().as_sql().unwrap(),
+                );
+                backend.map_ok(|backend| Backend::Sql(backend)).boxed()
+            }
         }
     }
 }
@@ -121,10 +263,16 @@ impl BackendFromConfigAndSyncState for Backend {
 impl BackendRpcMethodGetEntities for Backend {
     fn get_entities(&mut self, cids: Vec<String>) -> BoxFuture<Result<Vec<Entity>, Error>> {
         match self {
+            #[cfg(feature = "backend_embedded")]
+            Backend::Embedded(backend) => BackendRpcMethods::get_entities(backend, cids),
+            #[cfg(feature = "backend_memory")]
+            Backend::Memory(backend) => BackendRpcMethods::get_entities(backend, cids),
             #[cfg(feature = "backend_neo4j")]
             Backend::Neo4j(backend) => BackendRpcMethods::get_entities(backend, cids),
             #[cfg(feature = "backend_redisgraph")]
             Backend::Redisgraph(backend) => BackendRpcMethods::get_entities(backend, cids),
+            #[cfg(feature = "backend_sql")]
+            Backend::Sql(backend) => BackendRpcMethods::get_entities(backend, cids),
         }
     }
 }
@@ -135,24 +283,66 @@ impl BackendRpcMethodResolveEntities for Backend {
         cids: Vec<String>,
     ) -> BoxFuture<Result<HashMap<String, Vec<Entity>>, Error>> {
         match self {
+            #[cfg(feature = "backend_embedded")]
+            Backend::Embedded(backend) => BackendRpcMethods::resolve_entities(backend, cids),
+            #[cfg(feature = "backend_memory")]
+            Backend::Memory(backend) => BackendRpcMethods::resolve_entities(backend, cids),
             #[cfg(feature = "backend_neo4j")]
             Backend::Neo4j(backend) => BackendRpcMethods::resolve_entities(backend, cids),
             #[cfg(feature = "backend_redisgraph")]
             Backend::Redisgraph(backend) => BackendRpcMethods::resolve_entities(backend, cids),
+            #[cfg(feature = "backend_sql")]
+            Backend::Sql(backend) => BackendRpcMethods::resolve_entities(backend, cids),
         }
     }
 }
 
-impl BackendRpcMethods for Backend {}
+impl BackendRpcMethods for Backend {
+    fn unsupported_rpc_methods(&self) -> Vec<&'static str> {
+        match self {
+            #[cfg(feature = "backend_embedded")]
+            Backend::Embedded(backend) => backend.unsupported_rpc_methods(),
+            #[cfg(feature = "backend_memory")]
+            Backend::Memory(backend) => backend.unsupported_rpc_methods(),
+            #[cfg(feature = "backend_neo4j")]
+            Backend::Neo4j(backend) => backend.unsupported_rpc_methods(),
+            #[cfg(feature = "backend_redisgraph")]
+            Backend::Redisgraph(backend) => backend.unsupported_rpc_methods(),
+            #[cfg(feature = "backend_sql")]
+            Backend::Sql(backend) => backend.unsupported_rpc_methods(),
+        }
+    }
+
+    fn ping(&mut self) -> BoxFuture<Result<(), Error>> {
+        match self {
+            #[cfg(feature = "backend_embedded")]
+            Backend::Embedded(backend) => BackendRpcMethods::ping(backend),
+            #[cfg(feature = "backend_memory")]
+            Backend::Memory(backend) => BackendRpcMethods::ping(backend),
+            #[cfg(feature = "backend_neo4j")]
+            Backend::Neo4j(backend) => BackendRpcMethods::ping(backend),
+            #[cfg(feature = "backend_redisgraph")]
+            Backend::Redisgraph(backend) => BackendRpcMethods::ping(backend),
+            #[cfg(feature = "backend_sql")]
+            Backend::Sql(backend) => BackendRpcMethods::ping(backend),
+        }
+    }
+}
 
 #[async_trait]
 impl GetEntity for Backend {
     async fn get_entity(&self, cid: &[u8]) -> Result<Option<Entity>, Error> {
         match self {
+            #[cfg(feature = "backend_embedded")]
+            Backend::Embedded(backend) => GetEntity::get_entity(backend, cid).await,
+            #[cfg(feature = "backend_memory")]
+            Backend::Memory(backend) => GetEntity::get_entity(backend, cid).await,
             #[cfg(feature = "backend_neo4j")]
             Backend::Neo4j(backend) => GetEntity::get_entity(backend, cid).await,
             #[cfg(feature = "backend_redisgraph")]
             Backend::Redisgraph(backend) => GetEntity::get_entity(backend, cid).await,
+            #[cfg(feature = "backend_sql")]
+            Backend::Sql(backend) => GetEntity::get_entity(backend, cid).await,
         }
     }
 }
@@ -161,10 +351,16 @@ impl GetEntity for Backend {
 impl ResolveEntity for Backend {
     async fn resolve_entity(&self, cid: &[u8]) -> Result<HashMap<Vec<u8>, Vec<Entity>>, Error> {
         match self {
+            #[cfg(feature = "backend_embedded")]
+            Backend::Embedded(backend) => ResolveEntity::resolve_entity(backend, cid).await,
+            #[cfg(feature = "backend_memory")]
+            Backend::Memory(backend) => ResolveEntity::resolve_entity(backend, cid).await,
             #[cfg(feature = "backend_neo4j")]
             Backend::Neo4j(backend) => ResolveEntity::resolve_entity(backend, cid).await,
             #[cfg(feature = "backend_redisgraph")]
             Backend::Redisgraph(backend) => ResolveEntity::resolve_entity(backend, cid).await,
+            #[cfg(feature = "backend_sql")]
+            Backend::Sql(backend) => ResolveEntity::resolve_entity(backend, cid).await,
         }
     }
 }
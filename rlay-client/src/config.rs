@@ -116,6 +116,8 @@ impl Config {
 }
 
 pub mod rpc {
+    use std::collections::HashMap;
+
     #[derive(Debug, Deserialize, Clone)]
     pub struct RpcConfig {
         #[serde(default = "default_rpc_disabled")]
@@ -124,11 +126,113 @@ pub mod rpc {
         /// Network address of the upstream Ethereum RPC.
         pub proxy_target_network_address: Option<String>,
         #[serde(default = "default_network_address")]
-        /// Network address to serve the RPC on.
+        /// Network address to serve the RPC on. `https://` addresses require `tls_cert_path` and
+        /// `tls_key_path`.
         pub network_address: String,
+        #[serde(default)]
+        /// Path of the PEM encoded certificate chain to serve the RPC with via TLS.
+        pub tls_cert_path: Option<String>,
+        #[serde(default)]
+        /// Path of the PEM encoded private key (PKCS8 or RSA) of the TLS certificate.
+        pub tls_key_path: Option<String>,
+        #[serde(default)]
+        /// Path of the PEM encoded CA certificates to verify client certificates with. If set,
+        /// clients have to authenticate with a certificate signed by one of them (mTLS).
+        pub tls_client_ca_path: Option<String>,
         #[serde(default = "default_ws_network_address")]
         /// Network address to serve the Websocket RPC on.
         pub ws_network_address: Option<String>,
+        #[serde(default)]
+        /// Path of the Unix socket to serve the IPC RPC on. Relative paths are relative to the
+        /// `data_path`.
+        pub ipc_path: Option<String>,
+        #[serde(default)]
+        /// Network address to serve the gRPC API on. Requires the `grpc` feature.
+        pub grpc_network_address: Option<String>,
+        #[serde(default)]
+        /// API keys and the RPC methods they may call. If not set, all requests are allowed.
+        pub auth: Option<AuthConfig>,
+        #[serde(default)]
+        /// Origins that browsers may call the HTTP RPC from (CORS), e.g. `https://example.com`.
+        /// `"*"` allows all origins. If empty, no CORS headers are sent.
+        pub cors_allowed_origins: Vec<String>,
+        #[serde(default = "default_cors_allowed_headers")]
+        /// Request headers that may be sent in cross-origin requests.
+        pub cors_allowed_headers: Vec<String>,
+        #[serde(default = "default_limits_section")]
+        pub limits: LimitsConfig,
+        #[serde(default = "default_readiness_timeout_ms")]
+        /// Timeout for each of the checks of `/health/ready` (e.g. pinging the backend), in
+        /// milliseconds.
+        pub readiness_timeout_ms: u64,
+        #[serde(default = "default_shutdown_timeout_secs")]
+        /// Time that in-flight requests are given to finish when shutting down on SIGTERM or
+        /// SIGINT, in seconds.
+        pub shutdown_timeout_secs: u64,
+    }
+
+    /// Limits protecting the client and its backend from excessive requests.
+    #[derive(Debug, Deserialize, Clone)]
+    pub struct LimitsConfig {
+        #[serde(default = "default_max_body_size")]
+        /// Maximum size of HTTP request bodies, WebSocket messages and IPC payloads, in bytes.
+        pub max_body_size: usize,
+        #[serde(default = "default_max_entities_per_call")]
+        /// Maximum number of entities (or CIDs) per `rlay_experimentalStoreEntities`,
+        /// `rlay_experimentalGetEntities` and `rlay_experimentalResolveEntities` call.
+        pub max_entities_per_call: usize,
+        #[serde(default)]
+        /// Maximum number of HTTP and gRPC requests that are handled concurrently. Unlimited if not
+        /// set.
+        pub max_in_flight_requests: Option<usize>,
+        #[serde(default = "default_max_queued_requests")]
+        /// Number of HTTP and gRPC requests that may wait for one of the `max_in_flight_requests`
+        /// to finish. Further requests are rejected with `503 Service Unavailable` (`UNAVAILABLE`
+        /// for gRPC).
+        pub max_queued_requests: usize,
+        #[serde(default)]
+        /// Rate limit per API key, or per remote IP for requests without a valid API key.
+        pub rate_limit: Option<RateLimitConfig>,
+    }
+
+    #[derive(Debug, Deserialize, Clone)]
+    pub struct RateLimitConfig {
+        /// Number of requests per second a client may make on average.
+        pub requests_per_second: f64,
+        #[serde(default)]
+        /// Number of requests a client may make in a burst. Defaults to `requests_per_second`.
+        pub burst: Option<u32>,
+    }
+
+    #[derive(Debug, Deserialize, Clone)]
+    pub struct AuthConfig {
+        #[serde(default)]
+        /// Role of requests without an API key. Such requests are rejected if not set.
+        pub anonymous_role: Option<String>,
+        #[serde(default)]
+        pub keys: Vec<ApiKeyConfig>,
+        #[serde(default)]
+        /// Method allow/deny lists per role. Entries for the builtin roles (`read-only`, `writer`
+        /// and `admin`) override their defaults.
+        pub roles: HashMap<String, RoleConfig>,
+    }
+
+    #[derive(Debug, Deserialize, Clone)]
+    pub struct ApiKeyConfig {
+        /// The key, which is provided by clients as `Authorization: Bearer <key>` header.
+        pub key: String,
+        pub role: String,
+    }
+
+    #[derive(Debug, Deserialize, Clone, Default)]
+    pub struct RoleConfig {
+        #[serde(default)]
+        /// Methods the role may call. A trailing `*` matches all methods with that prefix, which
+        /// also applies to methods that are proxied to `proxy_target_network_address`.
+        pub allow: Option<Vec<String>>,
+        #[serde(default)]
+        /// Methods the role may not call, even if they are allowed.
+        pub deny: Vec<String>,
     }
 
     fn default_rpc_disabled() -> bool {
@@ -139,6 +243,34 @@ pub mod rpc {
         None
     }
 
+    fn default_cors_allowed_headers() -> Vec<String> {
+        vec!["Content-Type".to_owned(), "Authorization".to_owned()]
+    }
+
+    fn default_limits_section() -> LimitsConfig {
+        ::toml::from_str("").unwrap()
+    }
+
+    fn default_max_body_size() -> usize {
+        10 * 1024 * 1024
+    }
+
+    fn default_max_entities_per_call() -> usize {
+        10_000
+    }
+
+    fn default_max_queued_requests() -> usize {
+        1024
+    }
+
+    fn default_readiness_timeout_ms() -> u64 {
+        2000
+    }
+
+    fn default_shutdown_timeout_secs() -> u64 {
+        30
+    }
+
     fn default_network_address() -> String {
         "http://127.0.0.1:8546".to_owned()
     }
@@ -149,19 +281,34 @@ pub mod rpc {
 }
 
 pub mod backend {
+    #[cfg(feature = "backend_embedded")]
+    use rlay_backend_embedded::config::EmbeddedBackendConfig;
+    #[cfg(feature = "backend_memory")]
+    use rlay_backend_memory::config::MemoryBackendConfig;
     #[cfg(feature = "backend_neo4j")]
     use rlay_backend_neo4j::config::Neo4jBackendConfig;
     #[cfg(feature = "backend_redisgraph")]
     use rlay_backend_redisgraph::config::RedisgraphBackendConfig;
+    #[cfg(feature = "backend_sql")]
+    use rlay_backend_sql::config::SqlBackendConfig;
 
     #[derive(Debug, Deserialize, Clone)]
     #[serde(tag = "type")]
     pub enum BackendConfig {
+        #[serde(rename = "embedded")]
+        #[cfg(feature = "backend_embedded")]
+        Embedded(EmbeddedBackendConfig),
+        #[serde(rename = "memory")]
+        #[cfg(feature = "backend_memory")]
+        Memory(MemoryBackendConfig),
         #[serde(rename = "neo4j")]
         #[cfg(feature = "backend_neo4j")]
         Neo4j(Neo4jBackendConfig),
         #[serde(rename = "redisgraph")]
         #[cfg(feature = "backend_redisgraph")]
         Redisgraph(RedisgraphBackendConfig),
+        #[serde(rename = "sql")]
+        #[cfg(feature = "backend_sql")]
+        Sql(SqlBackendConfig),
     }
 }
@@ -1,12 +1,23 @@
 use async_trait::async_trait;
+use failure::Error;
 use libloading as lib;
 use libloading::Symbol;
+use notify::Watcher;
 use rlay_ontology::prelude::Entity;
 use rlay_plugin_interface::prelude::{FilterContext, RlayFilter};
 use std::collections::HashMap;
 use std::ffi::OsStr;
-use std::path::Path;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Time that changes to the plugins directory are collected for before the plugins are reloaded.
+const RELOAD_DELAY: Duration = Duration::from_secs(2);
+
+/// Number of plugin copies that have been loaded, used to give every copy a unique path.
+static LOADED_COPIES: AtomicUsize = AtomicUsize::new(0);
 
 sa::assert_impl_all!(RlayFilterPlugin: Send, Sync);
 // #[derive(Delegate)]
@@ -31,20 +42,55 @@ impl RlayFilter for RlayFilterPlugin {
 }
 
 impl RlayFilterPlugin {
-    pub fn load_filter<P: AsRef<OsStr> + std::fmt::Debug>(path: P) -> Self {
-        let plugin_lib = lib::Library::new(path).unwrap();
+    pub fn load_filter<P: AsRef<OsStr> + std::fmt::Debug>(path: P) -> Result<Self, Error> {
+        let plugin_lib = lib::Library::new(&path)
+            .map_err(|err| format_err!("Unable to load plugin {:?}: {}", path, err))?;
         let filter = unsafe {
-            let init_fn: Symbol<extern "C" fn() -> Box<dyn RlayFilter + Send + Sync>> = plugin_lib
-                .get(b"init_filter_plugin")
-                .expect("Plugin does not expose init_filter_plugin initialization function");
+            let init_fn: Symbol<extern "C" fn() -> Box<dyn RlayFilter + Send + Sync>> =
+                plugin_lib.get(b"init_filter_plugin").map_err(|_| {
+                    format_err!(
+                        "Plugin {:?} does not expose init_filter_plugin initialization function",
+                        path
+                    )
+                })?;
             let filter = init_fn();
             filter
         };
 
-        RlayFilterPlugin {
+        Ok(RlayFilterPlugin {
             library: plugin_lib,
             filter,
-        }
+        })
+    }
+
+    /// Load the plugin at `path` from a copy with a unique path.
+    ///
+    /// Loading a path that is already loaded returns the library that is already loaded, so a
+    /// changed plugin could otherwise never be reloaded while the previous version is still in
+    /// use. The copy also keeps the loaded library intact if the plugin is overwritten.
+    ///
+    /// The copy is created exclusively (with a random name that is only accessible to the user
+    /// running the client), so other users of the temporary directory can't substitute it.
+    pub fn load_filter_copy(path: &Path) -> Result<Self, Error> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| format_err!("Invalid plugin path {:?}", path))?;
+        let copy_err =
+            |err: std::io::Error| format_err!("Unable to copy plugin {:?}: {}", path, err);
+        let mut copy = tempfile::Builder::new()
+            .prefix(&format!(
+                "rlay-plugin-{}-",
+                LOADED_COPIES.fetch_add(1, Ordering::SeqCst)
+            ))
+            .suffix(&format!("-{}", file_name.to_string_lossy()))
+            .tempfile()
+            .map_err(copy_err)?;
+        let mut original = std::fs::File::open(path).map_err(copy_err)?;
+        std::io::copy(&mut original, copy.as_file_mut()).map_err(copy_err)?;
+        let filter = Self::load_filter(copy.path());
+        // the library stays loaded after its file has been removed
+        let _ = copy.close();
+        filter
     }
 }
 
@@ -54,31 +100,149 @@ pub struct PluginRegistry {
 }
 
 impl PluginRegistry {
-    pub fn from_dir<P: AsRef<Path>>(dir_path: P) -> Self {
-        let filters: Vec<_> = std::fs::read_dir(dir_path)
-            .unwrap()
-            .map(|dir_entry| dir_entry.unwrap())
-            .map(|dir_entry| RlayFilterPlugin::load_filter(dir_entry.path()))
-            .collect();
+    /// Load all plugins in `dir_path`. A missing directory is treated like an empty one.
+    ///
+    /// Files that can't be loaded as plugins, and plugins with the name of a filter that has
+    /// already been loaded, are skipped with a warning.
+    pub fn from_dir<P: AsRef<Path>>(dir_path: P) -> Result<Self, Error> {
+        let dir_path = dir_path.as_ref();
+        let dir_entries = match std::fs::read_dir(dir_path) {
+            Ok(dir_entries) => dir_entries,
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {
+                debug!("Plugins directory {:?} doesn't exist", dir_path);
+                return Ok(Self {
+                    filters: HashMap::new(),
+                });
+            }
+            Err(err) => return Err(err.into()),
+        };
 
         let mut filter_map = HashMap::new();
-        for filter in filters {
+        for dir_entry in dir_entries {
+            let path = match dir_entry {
+                Ok(dir_entry) => dir_entry.path(),
+                Err(err) => {
+                    warn!("Unable to read plugins directory {:?}: {}", dir_path, err);
+                    continue;
+                }
+            };
+            let filter = match RlayFilterPlugin::load_filter_copy(&path) {
+                Ok(filter) => filter,
+                Err(err) => {
+                    warn!("Skipping plugin {:?}: {}", path, err);
+                    continue;
+                }
+            };
             let filter_name = filter.filter_name();
-            let existing_filter = filter_map.insert(filter_name.to_owned(), Arc::new(filter));
-            if existing_filter.is_some() {
-                panic!(
-                    "Tried to load two filters with same name \"{name}\".",
-                    name = filter_name
+            if filter_map.contains_key(filter_name) {
+                warn!(
+                    "Skipping plugin {:?}: A filter with the name \"{}\" has already been loaded",
+                    path, filter_name
                 );
+                continue;
             }
+            filter_map.insert(filter_name.to_owned(), Arc::new(filter));
         }
 
-        Self {
+        Ok(Self {
             filters: filter_map,
-        }
+        })
     }
 
     pub fn filter(&self, name: &str) -> Option<Arc<RlayFilterPlugin>> {
         self.filters.get(name).map(|n| n.to_owned())
     }
+
+    /// Names of all loaded filters.
+    pub fn filter_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.filters.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+/// The plugins of the plugins directory, which can be reloaded while the client is running.
+///
+/// Requests use the registry that was loaded when they started, so plugins that are unloaded by a
+/// reload stay loaded until the requests using them have finished.
+#[derive(Clone)]
+pub struct SharedPluginRegistry {
+    dir_path: PathBuf,
+    registry: Arc<RwLock<Arc<PluginRegistry>>>,
+}
+
+impl SharedPluginRegistry {
+    pub fn from_dir<P: AsRef<Path>>(dir_path: P) -> Result<Self, Error> {
+        let dir_path = dir_path.as_ref().to_owned();
+        let registry = PluginRegistry::from_dir(&dir_path)?;
+        Ok(Self {
+            dir_path,
+            registry: Arc::new(RwLock::new(Arc::new(registry))),
+        })
+    }
+
+    /// The currently loaded plugins.
+    pub fn current(&self) -> Arc<PluginRegistry> {
+        self.registry.read().unwrap().clone()
+    }
+
+    /// Load the plugins of the plugins directory again.
+    ///
+    /// If loading fails, the previously loaded plugins are kept.
+    pub fn reload(&self) -> Result<Arc<PluginRegistry>, Error> {
+        let registry = Arc::new(PluginRegistry::from_dir(&self.dir_path)?);
+        *self.registry.write().unwrap() = registry.clone();
+        debug!("Reloaded plugins: {:?}", registry.filter_names());
+        Ok(registry)
+    }
+
+    /// Reload the plugins whenever a file in the plugins directory is created, changed, moved or
+    /// removed.
+    ///
+    /// Changes are only reported once a file hasn't changed for `RELOAD_DELAY`, so a plugin that is
+    /// written in place is only loaded once it is complete. Plugins should still preferably be
+    /// replaced atomically, by moving them into the directory.
+    ///
+    /// The directory is watched from a background thread for as long as the process is running.
+    pub fn watch(&self) -> Result<(), Error> {
+        let (sender, receiver) = channel();
+        let mut watcher = notify::watcher(sender, RELOAD_DELAY)?;
+        watcher.watch(&self.dir_path, notify::RecursiveMode::NonRecursive)?;
+
+        let shared_registry = self.clone();
+        std::thread::spawn(move || {
+            // the watcher stops watching once it is dropped
+            let _watcher = watcher;
+            while let Ok(event) = receiver.recv() {
+                if !requires_reload(&event) {
+                    continue;
+                }
+                // the events of a single change (e.g. `Create` and `Write` of a copied plugin)
+                // are reported together, and are handled with a single reload
+                while let Ok(event) = receiver.recv_timeout(Duration::from_millis(100)) {
+                    requires_reload(&event);
+                }
+                if let Err(err) = shared_registry.reload() {
+                    warn!("Unable to reload plugins: {}", err);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Whether `event` of the plugins directory watcher changes the plugins that have to be loaded.
+fn requires_reload(event: &notify::DebouncedEvent) -> bool {
+    match event {
+        notify::DebouncedEvent::Create(_)
+        | notify::DebouncedEvent::Write(_)
+        | notify::DebouncedEvent::Remove(_)
+        | notify::DebouncedEvent::Rename(_, _)
+        | notify::DebouncedEvent::Rescan => true,
+        notify::DebouncedEvent::Error(err, _) => {
+            warn!("Unable to watch plugins directory: {}", err);
+            false
+        }
+        _ => false,
+    }
 }
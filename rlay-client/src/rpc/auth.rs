@@ -0,0 +1,189 @@
+//! Authentication of RPC requests via API keys, and authorization of the methods they call.
+//!
+//! Every API key has a role, which determines the methods that may be called with it. The builtin
+//! roles are:
+//!
+//! - `read-only`: Methods that only retrieve entities
+//! - `writer`: Additionally the methods that store entities
+//! - `admin`: All methods, including `rlay_experimentalNeo4jQuery` and methods that are proxied
+//!
+//! Their method lists can be overridden, and further roles can be defined, in `[rpc.auth.roles]`.
+use failure::Error;
+use hyper::{header, Body, Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::GenericError;
+use crate::config::rpc::AuthConfig;
+
+const READ_ONLY_METHODS: &[&str] = &[
+    "rlay_version",
+    "rlay_experimentalGetEntity",
+    "rlay_experimentalGetEntities",
+    "rlay_experimentalResolveEntity",
+    "rlay_experimentalResolveEntities",
+    "rlay_experimentalListCids",
+    "rlay_experimentalGetEntityCid",
+    "rlay_subscribe",
+    "rlay_unsubscribe",
+    "rpc.discover",
+];
+
+const WRITE_METHODS: &[&str] = &[
+    "rlay_experimentalStoreEntity",
+    "rlay_experimentalStoreEntities",
+];
+
+/// The RPC methods that may be called with an API key.
+#[derive(Debug, Clone)]
+pub struct Permissions {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl Permissions {
+    /// Permissions allowing all methods.
+    pub fn unrestricted() -> Self {
+        Self {
+            allow: vec!["*".to_owned()],
+            deny: Vec::new(),
+        }
+    }
+
+    fn allowing<'a>(methods: impl Iterator<Item = &'a &'a str>) -> Self {
+        Self {
+            allow: methods.map(|method| method.to_string()).collect(),
+            deny: Vec::new(),
+        }
+    }
+
+    fn builtin(role: &str) -> Option<Self> {
+        match role {
+            "read-only" => Some(Self::allowing(READ_ONLY_METHODS.iter())),
+            "writer" => Some(Self::allowing(
+                READ_ONLY_METHODS.iter().chain(WRITE_METHODS.iter()),
+            )),
+            "admin" => Some(Self::unrestricted()),
+            _ => None,
+        }
+    }
+
+    /// Whether the RPC method `method` may be called.
+    pub fn is_allowed(&self, method: &str) -> bool {
+        let matches = |pattern: &String| {
+            if pattern.ends_with('*') {
+                method.starts_with(&pattern[..pattern.len() - 1])
+            } else {
+                pattern == method
+            }
+        };
+        self.allow.iter().any(matches) && !self.deny.iter().any(matches)
+    }
+}
+
+/// The API keys that are accepted by the RPC, shared between all transports.
+#[derive(Clone)]
+pub struct Auth {
+    /// Permissions per API key, or `None` if authentication is disabled.
+    keys: Option<Arc<HashMap<String, Arc<Permissions>>>>,
+    /// Permissions of requests without an API key.
+    anonymous: Option<Arc<Permissions>>,
+}
+
+impl Auth {
+    pub fn from_config(auth_config: Option<&AuthConfig>) -> Result<Self, Error> {
+        let auth_config = match auth_config {
+            Some(auth_config) => auth_config,
+            None => {
+                return Ok(Self {
+                    keys: None,
+                    anonymous: Some(Arc::new(Permissions::unrestricted())),
+                })
+            }
+        };
+
+        let role_permissions = |role: &str| -> Result<Arc<Permissions>, Error> {
+            let builtin = Permissions::builtin(role);
+            let permissions = match (auth_config.roles.get(role), builtin) {
+                (Some(role_config), builtin) => Permissions {
+                    allow: match (&role_config.allow, builtin) {
+                        (Some(allow), _) => allow.clone(),
+                        (None, Some(builtin)) => builtin.allow,
+                        (None, None) => Vec::new(),
+                    },
+                    deny: role_config.deny.clone(),
+                },
+                (None, Some(builtin)) => builtin,
+                (None, None) => return Err(format_err!("Unknown role \"{}\"", role)),
+            };
+            Ok(Arc::new(permissions))
+        };
+
+        let mut keys = HashMap::new();
+        for key_config in auth_config.keys.iter() {
+            keys.insert(key_config.key.clone(), role_permissions(&key_config.role)?);
+        }
+        let anonymous = match auth_config.anonymous_role.as_ref() {
+            Some(role) => Some(role_permissions(role)?),
+            None => None,
+        };
+
+        Ok(Self {
+            keys: Some(Arc::new(keys)),
+            anonymous,
+        })
+    }
+
+    /// Permissions of a request with the `Authorization` header value `authorization`.
+    ///
+    /// Returns `None` if the request has to be rejected, because it provided an unknown API key
+    /// or none at all while anonymous requests are not allowed.
+    pub fn authenticate(&self, authorization: Option<&str>) -> Option<Arc<Permissions>> {
+        let keys = match self.keys.as_ref() {
+            Some(keys) => keys,
+            None => return self.anonymous.clone(),
+        };
+        match authorization {
+            None => self.anonymous.clone(),
+            Some(authorization) => keys.get(api_key(authorization)).cloned(),
+        }
+    }
+
+    /// The API key provided via the `Authorization` header value `authorization`, if it is one
+    /// of the accepted keys.
+    pub fn known_api_key<'a>(&self, authorization: &'a str) -> Option<&'a str> {
+        let key = api_key(authorization);
+        match self.keys.as_ref() {
+            Some(keys) if keys.contains_key(key) => Some(key),
+            _ => None,
+        }
+    }
+}
+
+fn api_key(authorization: &str) -> &str {
+    let key = if authorization.starts_with("Bearer ") {
+        &authorization["Bearer ".len()..]
+    } else {
+        authorization
+    };
+    key.trim()
+}
+
+/// Error of a JSON-RPC call to a method that may not be called with the provided API key.
+pub fn method_not_allowed_error(method: &str) -> jsonrpc_core::Error {
+    jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::ServerError(-32001),
+        message: format!("Method not allowed for this API key: {}", method),
+        data: None,
+    }
+}
+
+pub async fn unauthorized_response() -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(header::WWW_AUTHENTICATE, "Bearer")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({ "error": "Missing or invalid API key" }).to_string(),
+        ))?)
+}
@@ -0,0 +1,86 @@
+//! CORS handling for the HTTP RPC, so that it can be used from browsers on other origins.
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Body, Response, StatusCode};
+use std::sync::Arc;
+
+use super::GenericError;
+use crate::config::RpcConfig;
+
+const ALLOWED_METHODS: &str = "GET, POST, OPTIONS";
+/// How long browsers may cache the result of a preflight request, in seconds.
+const MAX_AGE: &str = "86400";
+
+#[derive(Clone)]
+pub struct Cors {
+    allowed_origins: Arc<Vec<String>>,
+    allowed_headers: String,
+}
+
+impl Cors {
+    pub fn from_config(rpc_config: &RpcConfig) -> Self {
+        Self {
+            allowed_origins: Arc::new(rpc_config.cors_allowed_origins.clone()),
+            allowed_headers: rpc_config.cors_allowed_headers.join(", "),
+        }
+    }
+
+    fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    /// The `Access-Control-Allow-Origin` value for a request, or `None` if it isn't a
+    /// cross-origin request from an allowed origin.
+    pub fn allowed_origin(&self, request_headers: &HeaderMap) -> Option<HeaderValue> {
+        let origin = request_headers.get(header::ORIGIN)?;
+        if self.allows_any_origin() {
+            return Some(HeaderValue::from_static("*"));
+        }
+        let origin_str = origin.to_str().ok()?;
+        if self
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed.trim_end_matches('/') == origin_str)
+        {
+            Some(origin.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Response to an `OPTIONS` request, which browsers send as preflight before cross-origin
+    /// requests.
+    ///
+    /// The CORS headers are only added via `add_headers`, so requests from origins that are not
+    /// allowed still receive a response, but browsers will refuse the actual request.
+    pub fn preflight_response(&self, allowed_origin: bool) -> Result<Response<Body>, GenericError> {
+        let mut response = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(header::ALLOW, ALLOWED_METHODS);
+        if allowed_origin {
+            response = response
+                .header(header::ACCESS_CONTROL_ALLOW_METHODS, ALLOWED_METHODS)
+                .header(
+                    header::ACCESS_CONTROL_ALLOW_HEADERS,
+                    self.allowed_headers.as_str(),
+                )
+                .header(header::ACCESS_CONTROL_MAX_AGE, MAX_AGE);
+        }
+        Ok(response.body(Body::empty())?)
+    }
+
+    /// Add the CORS headers for a request with the allowed origin `allowed_origin` to its
+    /// response.
+    pub fn add_headers(&self, allowed_origin: Option<HeaderValue>, response: &mut Response<Body>) {
+        if self.allowed_origins.is_empty() {
+            return;
+        }
+        let headers = response.headers_mut();
+        if !self.allows_any_origin() {
+            // the response depends on the origin, so caches must not share it between origins
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+        if let Some(allowed_origin) = allowed_origin {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allowed_origin);
+        }
+    }
+}
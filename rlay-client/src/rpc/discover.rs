@@ -0,0 +1,334 @@
+//! OpenRPC description of the RPC methods implemented by the client (see `rpc.discover`).
+use rlay_backend::relationships::cid_field_names;
+use rlay_backend::rpc::BackendRpcMethods;
+use rlay_ontology::prelude::*;
+use serde_json::{Map, Value};
+
+use super::{get_backend, JsonRpcResult, CLIENT_VERSION};
+use crate::backend::SyncState;
+use crate::config::Config;
+
+const OPENRPC_VERSION: &str = "1.2.4";
+
+/// Description of a RPC method that is implemented by the client.
+struct MethodDescription {
+    name: &'static str,
+    summary: &'static str,
+    params: Vec<Value>,
+    result: Value,
+    /// Method of `BackendRpcMethods` the RPC method relies on, if any.
+    backend_method: Option<&'static str>,
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn param(name: &str, required: bool, schema: Value) -> Value {
+    json!({
+        "name": name,
+        "required": required,
+        "schema": schema,
+    })
+}
+
+fn result(name: &str, schema: Value) -> Value {
+    json!({
+        "name": name,
+        "schema": schema,
+    })
+}
+
+fn array_of(schema: Value) -> Value {
+    json!({
+        "type": "array",
+        "items": schema,
+    })
+}
+
+fn resolved_entities_schema() -> Value {
+    json!({
+        "type": "object",
+        "additionalProperties": array_of(schema_ref("Entity")),
+    })
+}
+
+fn method_descriptions() -> Vec<MethodDescription> {
+    vec![
+        MethodDescription {
+            name: "rlay_version",
+            summary: "Returns information about the client and the Rlay network.",
+            params: vec![],
+            result: result("version", schema_ref("Version")),
+            backend_method: None,
+        },
+        MethodDescription {
+            name: "rlay_experimentalStoreEntity",
+            summary: "Stores an entity, returning its CID.",
+            params: vec![
+                param("entity", true, schema_ref("Entity")),
+                param("options", false, schema_ref("StoreOptions")),
+            ],
+            result: result("cid", schema_ref("Cid")),
+            backend_method: Some("store_entity"),
+        },
+        MethodDescription {
+            name: "rlay_experimentalStoreEntities",
+            summary: "Stores multiple entities, returning their CIDs.",
+            params: vec![
+                param("entities", true, array_of(schema_ref("Entity"))),
+                param("options", false, schema_ref("StoreOptions")),
+            ],
+            result: result("cids", array_of(schema_ref("Cid"))),
+            backend_method: Some("store_entities"),
+        },
+        MethodDescription {
+            name: "rlay_experimentalGetEntity",
+            summary: "Retrieves an entity by its CID, returning null if it is not known.",
+            params: vec![param("cid", true, schema_ref("Cid"))],
+            result: result(
+                "entity",
+                json!({ "oneOf": [schema_ref("Entity"), { "type": "null" }] }),
+            ),
+            backend_method: Some("get_entity"),
+        },
+        MethodDescription {
+            name: "rlay_experimentalGetEntities",
+            summary: "Retrieves multiple entities by their CIDs, skipping unknown ones.",
+            params: vec![param("cids", true, array_of(schema_ref("Cid")))],
+            result: result("entities", array_of(schema_ref("Entity"))),
+            backend_method: Some("get_entities"),
+        },
+        MethodDescription {
+            name: "rlay_experimentalResolveEntity",
+            summary: "Resolves an entity into the entities related to it.",
+            params: vec![
+                param("cid", true, schema_ref("Cid")),
+                param("options", false, schema_ref("FilterOptions")),
+            ],
+            result: result("resolvedEntities", resolved_entities_schema()),
+            backend_method: Some("resolve_entity"),
+        },
+        MethodDescription {
+            name: "rlay_experimentalResolveEntities",
+            summary: "Resolves multiple entities into the entities related to them.",
+            params: vec![
+                param("cids", true, array_of(schema_ref("Cid"))),
+                param("options", false, schema_ref("FilterOptions")),
+            ],
+            result: result("resolvedEntities", resolved_entities_schema()),
+            backend_method: Some("resolve_entities"),
+        },
+        MethodDescription {
+            name: "rlay_experimentalNeo4jQuery",
+            summary: "Runs a Cypher query returning CIDs, and retrieves the entities for them.",
+            params: vec![
+                param("query", true, json!({ "type": "string" })),
+                param("options", false, schema_ref("FilterOptions")),
+            ],
+            result: result("entities", array_of(schema_ref("Entity"))),
+            backend_method: Some("neo4j_query"),
+        },
+        MethodDescription {
+            name: "rlay_experimentalListCids",
+            summary: "Lists the CIDs of all entities, or of all entities of a kind.",
+            params: vec![param(
+                "entityKind",
+                false,
+                json!({ "enum": EntityKind::variants() }),
+            )],
+            result: result("cids", array_of(schema_ref("Cid"))),
+            backend_method: Some("list_cids"),
+        },
+        MethodDescription {
+            name: "rlay_experimentalGetEntityCid",
+            summary: "Calculates the CID of an entity.",
+            params: vec![param("entity", true, schema_ref("Entity"))],
+            result: result("cid", schema_ref("Cid")),
+            backend_method: None,
+        },
+        MethodDescription {
+            name: "rlay_subscribe",
+            summary: "Subscribes to newly stored entities (WebSocket and IPC only).",
+            params: vec![
+                param("subscriptionType", true, json!({ "enum": ["newEntities"] })),
+                param("options", false, schema_ref("NewEntitiesFilter")),
+            ],
+            result: result("subscriptionId", json!({ "type": "string" })),
+            backend_method: None,
+        },
+        MethodDescription {
+            name: "rlay_unsubscribe",
+            summary: "Cancels a subscription of the same connection (WebSocket and IPC only).",
+            params: vec![param("subscriptionId", true, json!({ "type": "string" }))],
+            result: result("existed", json!({ "type": "boolean" })),
+            backend_method: None,
+        },
+        MethodDescription {
+            name: "rlay_reloadPlugins",
+            summary: "Reloads the plugins of the plugins directory, returning the loaded filters.",
+            params: vec![],
+            result: result("filters", array_of(json!({ "type": "string" }))),
+            backend_method: None,
+        },
+        MethodDescription {
+            name: "rpc.discover",
+            summary: "Returns the OpenRPC document describing the RPC methods of the client.",
+            params: vec![],
+            result: result("openrpcDocument", json!({ "type": "object" })),
+            backend_method: None,
+        },
+    ]
+}
+
+/// JSON schema of every entity kind, derived from the fields of its empty entity.
+fn entity_schemas() -> Map<String, Value> {
+    let bytes_schema = schema_ref("Bytes");
+    let cid_schema = schema_ref("Cid");
+
+    let mut schemas = Map::new();
+    for kind_name in EntityKind::variants().iter() {
+        let empty_entity = EntityKind::from_name(kind_name).unwrap().empty_entity();
+        let empty_value = serde_json::to_value(FormatWeb3(empty_entity)).unwrap();
+        let cid_fields = cid_field_names(kind_name);
+
+        let mut properties = Map::new();
+        properties.insert("type".to_owned(), json!({ "const": kind_name }));
+        let mut required = vec![Value::String("type".to_owned())];
+        for (field_name, field_value) in empty_value.as_object().unwrap() {
+            if field_name == "type" || field_name == "cid" {
+                continue;
+            }
+            let value_schema = if cid_fields.contains(field_name) {
+                cid_schema.clone()
+            } else {
+                bytes_schema.clone()
+            };
+            let field_schema = match field_value {
+                Value::Array(_) => array_of(value_schema),
+                Value::Null => json!({ "oneOf": [value_schema, { "type": "null" }] }),
+                _ => {
+                    required.push(Value::String(field_name.to_owned()));
+                    value_schema
+                }
+            };
+            properties.insert(field_name.to_owned(), field_schema);
+        }
+
+        schemas.insert(
+            kind_name.to_string(),
+            json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            }),
+        );
+    }
+    schemas
+}
+
+fn component_schemas() -> Map<String, Value> {
+    let mut schemas = entity_schemas();
+    let entity_kind_refs: Vec<Value> = EntityKind::variants()
+        .iter()
+        .map(|kind_name| schema_ref(kind_name))
+        .collect();
+    schemas.insert("Entity".to_owned(), json!({ "oneOf": entity_kind_refs }));
+    schemas.insert(
+        "Bytes".to_owned(),
+        json!({ "type": "string", "pattern": "^0x([0-9a-fA-F]{2})*$" }),
+    );
+    schemas.insert(
+        "Cid".to_owned(),
+        json!({ "type": "string", "pattern": "^0x([0-9a-fA-F]{2})+$" }),
+    );
+    schemas.insert(
+        "Version".to_owned(),
+        json!({
+            "type": "object",
+            "properties": {
+                "networkVersion": { "type": "string" },
+                "clientVersion": { "type": "string" },
+                "contractAddresses": { "type": "null" },
+            },
+        }),
+    );
+    schemas.insert(
+        "StoreOptions".to_owned(),
+        json!({ "type": "object", "description": "Backend specific options" }),
+    );
+    schemas.insert(
+        "FilterOptions".to_owned(),
+        json!({
+            "type": "object",
+            "properties": {
+                "filters": array_of(json!({
+                    "type": "object",
+                    "properties": {
+                        "filter": { "type": "string" },
+                        "params": {},
+                    },
+                    "required": ["filter"],
+                })),
+            },
+        }),
+    );
+    schemas.insert(
+        "NewEntitiesFilter".to_owned(),
+        json!({
+            "type": "object",
+            "properties": {
+                "kind": { "enum": EntityKind::variants() },
+                "referencing": schema_ref("Cid"),
+            },
+        }),
+    );
+    schemas
+}
+
+/// `rpc.discover` RPC call.
+///
+/// Returns an OpenRPC document describing all RPC methods implemented by the client. Methods
+/// that the configured backend doesn't support are marked with `"x-supported": false`.
+pub async fn rpc_discover(config: Config, sync_state: SyncState) -> JsonRpcResult<Value> {
+    // The document is still useful without the backend, so it is only marked as unknown
+    let unsupported_backend_methods = match get_backend(&config, &sync_state).await {
+        Ok(backend) => Some(backend.unsupported_rpc_methods()),
+        Err(err) => {
+            debug!("Unable to determine supported RPC methods: {}", err.message);
+            None
+        }
+    };
+
+    let methods: Vec<Value> = method_descriptions()
+        .into_iter()
+        .map(|method| {
+            let is_supported = match (method.backend_method, &unsupported_backend_methods) {
+                (None, _) => Value::Bool(true),
+                (Some(backend_method), Some(unsupported)) => {
+                    Value::Bool(!unsupported.contains(&backend_method))
+                }
+                (Some(_), None) => Value::Null,
+            };
+            json!({
+                "name": method.name,
+                "summary": method.summary,
+                "params": method.params,
+                "result": method.result,
+                "x-supported": is_supported,
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": "rlay-client",
+            "version": CLIENT_VERSION,
+        },
+        "methods": methods,
+        "components": {
+            "schemas": component_schemas(),
+        },
+    }))
+}
@@ -0,0 +1,569 @@
+//! GraphQL endpoint over the entity graph.
+//!
+//! The schema is generated from the entity kinds of `rlay_ontology`: every kind is an object
+//! type, with its data fields as (hex-encoded) strings and its CID fields resolving to the
+//! referenced entities as the `Entity` union of all kinds. Referenced entities are only retrieved
+//! if they are part of the query, level by level with a single backend call per level.
+//!
+//! Every entity a query resolves, and every CID returned by `cids`, counts against
+//! `rpc.limits.max_entities_per_call`, so that deeply nested queries over entities that are
+//! referenced many times can't fan out without bound.
+//!
+//! `POST /graphql` executes a query, `GET /graphql` serves GraphiQL for exploring the schema.
+//! Queries require the API key to be allowed to call `rlay_experimentalGetEntity` and
+//! `rlay_experimentalListCids`.
+use cid::ToCid;
+use futures::executor::block_on;
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use juniper::http::graphiql::graphiql_source;
+use juniper::http::GraphQLRequest;
+use juniper::meta::MetaType;
+use juniper::{
+    Arguments, DefaultScalarValue, EmptyMutation, ExecutionResult, Executor, FieldError,
+    GraphQLType, LookAheadMethods, LookAheadSelection, Registry, RootNode, Selection,
+    Value as GraphQLValue,
+};
+use rlay_backend::rpc::BackendRpcMethods;
+use rlay_ontology::prelude::*;
+use rustc_hex::{FromHex, ToHex};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Handle;
+
+use super::auth::Permissions;
+use super::limits;
+use super::{get_backend, GenericError, RpcContext};
+use crate::backend::Backend;
+
+type Schema = RootNode<'static, Query, EmptyMutation<Context>>;
+
+struct FieldInfo {
+    name: String,
+    is_list: bool,
+}
+
+struct KindInfo {
+    name: String,
+    cid_fields: Vec<FieldInfo>,
+    data_fields: Vec<FieldInfo>,
+}
+
+/// Fields of all entity kinds, from which the GraphQL types are generated.
+pub struct EntityTypes {
+    kinds: Vec<KindInfo>,
+}
+
+impl EntityTypes {
+    pub fn new() -> Self {
+        let mut kinds = Vec::new();
+
+        macro_rules! kind_info {
+            ($kind:path) => {{
+                let entity: Entity = <$kind>::default().into();
+                // fields that hold multiple values are serialized as arrays, even if empty
+                let empty_value = serde_json::to_value(FormatWeb3(&entity)).unwrap();
+                let field_info = |name: String| FieldInfo {
+                    is_list: empty_value[name.as_str()].is_array(),
+                    name,
+                };
+                kinds.push(KindInfo {
+                    name: Into::<&str>::into(entity.kind()).to_owned(),
+                    cid_fields: <$kind>::cid_field_names()
+                        .into_iter()
+                        .map(|field| field_info(field.to_owned().to_owned()))
+                        .collect(),
+                    data_fields: <$kind>::data_field_names()
+                        .into_iter()
+                        .map(|field| field_info(field.to_owned().to_owned()))
+                        .collect(),
+                });
+            }};
+        }
+
+        rlay_ontology::call_with_entity_kinds!(ALL; kind_info!);
+
+        Self { kinds }
+    }
+
+    fn kind_index(&self, kind_name: &str) -> Option<usize> {
+        self.kinds.iter().position(|kind| kind.name == kind_name)
+    }
+
+    fn kind(&self, kind_name: &str) -> Option<&KindInfo> {
+        self.kinds.iter().find(|kind| kind.name == kind_name)
+    }
+}
+
+fn schema() -> Schema {
+    RootNode::new_with_info(
+        Query,
+        EmptyMutation::new(),
+        Arc::new(EntityTypes::new()),
+        (),
+    )
+}
+
+/// Context of a single query, caching the entities that have been retrieved for it.
+pub struct Context {
+    backend: Backend,
+    /// Runtime that drives the backend calls of the resolvers, which are executed outside of it.
+    runtime: Handle,
+    entities: Mutex<HashMap<String, Option<Entity>>>,
+    /// Maximum number of entities the query may resolve.
+    max_entities: usize,
+    resolved_entities: AtomicUsize,
+}
+
+impl juniper::Context for Context {}
+
+/// Format a CID the way the CIDs of retrieved entities are formatted, so they can be looked up.
+fn normalize_cid(cid: &str) -> Result<String, FieldError> {
+    let cid_bytes: Vec<u8> = cid
+        .trim_start_matches("0x")
+        .from_hex()
+        .map_err(|_| FieldError::from(format!("Invalid CID: {}", cid)))?;
+    Ok(format!("0x{}", cid_bytes.to_hex()))
+}
+
+/// Values of the field of an entity serialized in the web3 format, which may either be a single
+/// string or an array of strings.
+fn field_values(entity_value: &Value, field_name: &str) -> Vec<String> {
+    match &entity_value[field_name] {
+        Value::String(value) => vec![value.to_owned()],
+        Value::Array(values) => values
+            .iter()
+            .filter_map(|value| value.as_str().map(ToOwned::to_owned))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl Context {
+    fn new(backend: Backend, runtime: Handle, max_entities: usize) -> Self {
+        Self {
+            backend,
+            runtime,
+            entities: Mutex::new(HashMap::new()),
+            max_entities,
+            resolved_entities: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wait for a backend call within the runtime of the RPC, whose reactor and timers it uses.
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.enter(|| block_on(future))
+    }
+
+    /// Count `count` entities as resolved, failing once the query resolves more than it may.
+    fn count_resolved(&self, count: usize) -> Result<(), FieldError> {
+        let resolved_count = self.resolved_entities.fetch_add(count, Ordering::SeqCst) + count;
+        if resolved_count > self.max_entities {
+            return Err(FieldError::from(format!(
+                "The query resolves more than {} entities",
+                self.max_entities
+            )));
+        }
+        Ok(())
+    }
+
+    /// Retrieve the entities with the provided CIDs that aren't cached yet, with a single backend
+    /// call.
+    fn load_entities(&self, cids: &[String]) -> Result<(), FieldError> {
+        let missing_cids: Vec<String> = {
+            let entities = self.entities.lock().unwrap();
+            let mut seen_cids = HashSet::new();
+            cids.iter()
+                .map(|cid| normalize_cid(cid))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|cid| !entities.contains_key(cid) && seen_cids.insert(cid.to_owned()))
+                .collect()
+        };
+        if missing_cids.is_empty() {
+            return Ok(());
+        }
+
+        let mut backend = self.backend.clone();
+        let retrieved_entities = self.block_on(BackendRpcMethods::get_entities(
+            &mut backend,
+            missing_cids.clone(),
+        ))?;
+        let mut entities = self.entities.lock().unwrap();
+        for entity in retrieved_entities {
+            let cid = entity
+                .to_cid()
+                .map_err(|_| FieldError::from("Unable to calculate CID of entity"))?;
+            entities.insert(format!("0x{}", cid.to_bytes().to_hex()), Some(entity));
+        }
+        // CIDs without an entity are cached as well, so they aren't retrieved again
+        for cid in missing_cids {
+            entities.entry(cid).or_insert(None);
+        }
+        Ok(())
+    }
+
+    /// Retrieve the entities with the provided CIDs, and the entities referenced by the CID fields
+    /// that `selection` selects on them, with one backend call per level of the query.
+    ///
+    /// Stops early once more entities than the query may resolve are referenced, as the query is
+    /// going to be rejected while it is resolved.
+    fn prefetch(
+        &self,
+        types: &EntityTypes,
+        cids: Vec<String>,
+        selection: &LookAheadSelection<DefaultScalarValue>,
+    ) -> Result<(), FieldError> {
+        let mut referenced_count = 0;
+        let mut level = vec![(cids, selection)];
+        while !level.is_empty() {
+            let level_cids: Vec<String> = level
+                .iter()
+                .flat_map(|(cids, _)| cids.iter().cloned())
+                .collect();
+            referenced_count += level_cids.len();
+            if referenced_count > self.max_entities {
+                return Ok(());
+            }
+            self.load_entities(&level_cids)?;
+
+            let mut next_level = Vec::new();
+            for (cids, selection) in level {
+                for cid in cids {
+                    let entity = match self.entities.lock().unwrap().get(&normalize_cid(&cid)?) {
+                        Some(Some(entity)) => entity.clone(),
+                        _ => continue,
+                    };
+                    let kind = match types.kind(entity.kind().into()) {
+                        Some(kind) => kind,
+                        None => continue,
+                    };
+                    let entity_value = serde_json::to_value(FormatWeb3(&entity))?;
+                    for field in kind.cid_fields.iter() {
+                        if let Some(field_selection) = selection.select_child(&field.name) {
+                            next_level
+                                .push((field_values(&entity_value, &field.name), field_selection));
+                        }
+                    }
+                }
+            }
+            level = next_level;
+        }
+        Ok(())
+    }
+
+    fn get_entity(&self, cid: &str) -> Result<Option<EntityUnion>, FieldError> {
+        self.count_resolved(1)?;
+
+        let cid = normalize_cid(cid)?;
+        self.load_entities(&[cid.clone()])?;
+        let entity = self.entities.lock().unwrap().get(&cid).cloned().flatten();
+        Ok(entity.map(EntityUnion))
+    }
+}
+
+pub struct Query;
+
+impl GraphQLType for Query {
+    type Context = Context;
+    type TypeInfo = Arc<EntityTypes>;
+
+    fn name(_info: &Self::TypeInfo) -> Option<&str> {
+        Some("Query")
+    }
+
+    fn meta<'r>(info: &Self::TypeInfo, registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let cid_arg = registry.arg::<String>("cid", &());
+        let kind_arg = registry.arg::<Option<String>>("kind", &());
+        let fields = [
+            registry
+                .field::<Option<EntityUnion>>("entity", info)
+                .argument(cid_arg),
+            registry
+                .field::<Vec<String>>("cids", &())
+                .argument(kind_arg),
+        ];
+        registry
+            .build_object_type::<Query>(info, &fields)
+            .into_meta()
+    }
+
+    fn resolve_field(
+        &self,
+        info: &Self::TypeInfo,
+        field_name: &str,
+        arguments: &Arguments,
+        executor: &Executor<Self::Context>,
+    ) -> ExecutionResult {
+        match field_name {
+            "entity" => {
+                let cid: String = arguments.get("cid").expect("Argument cid is mandatory");
+                let context = executor.context();
+                context.prefetch(info, vec![cid.clone()], &executor.look_ahead())?;
+                let entity = context.get_entity(&cid)?;
+                executor.resolve(info, &entity)
+            }
+            "cids" => {
+                let kind: Option<String> = arguments.get("kind");
+                let context = executor.context();
+                let mut backend = context.backend.clone();
+                let cids = context.block_on(BackendRpcMethods::list_cids(
+                    &mut backend,
+                    kind.as_ref().map(|n| &**n),
+                ))?;
+                context.count_resolved(cids.len())?;
+                executor.resolve_with_ctx(&(), &cids)
+            }
+            _ => panic!("Field {} not found on type Query", field_name),
+        }
+    }
+}
+
+/// Union of all entity kinds.
+pub struct EntityUnion(Entity);
+
+impl GraphQLType for EntityUnion {
+    type Context = Context;
+    type TypeInfo = Arc<EntityTypes>;
+
+    fn name(_info: &Self::TypeInfo) -> Option<&str> {
+        Some("Entity")
+    }
+
+    fn meta<'r>(info: &Self::TypeInfo, registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let types: Vec<_> = (0..info.kinds.len())
+            .map(|kind| {
+                registry.get_type::<EntityObject>(&EntityObjectInfo {
+                    types: info.clone(),
+                    kind,
+                })
+            })
+            .collect();
+        registry
+            .build_union_type::<EntityUnion>(info, &types)
+            .into_meta()
+    }
+
+    fn concrete_type_name(&self, _context: &Self::Context, _info: &Self::TypeInfo) -> String {
+        Into::<&str>::into(self.0.kind()).to_owned()
+    }
+
+    fn resolve_into_type(
+        &self,
+        info: &Self::TypeInfo,
+        type_name: &str,
+        _selection_set: Option<&[Selection]>,
+        executor: &Executor<Self::Context>,
+    ) -> ExecutionResult {
+        let kind_name: &str = self.0.kind().into();
+        if kind_name != type_name {
+            return Ok(GraphQLValue::null());
+        }
+        let object_info = EntityObjectInfo {
+            types: info.clone(),
+            kind: info.kind_index(kind_name).expect("Unknown entity kind"),
+        };
+        executor.resolve(&object_info, &EntityObject::new(&self.0)?)
+    }
+}
+
+pub struct EntityObjectInfo {
+    types: Arc<EntityTypes>,
+    kind: usize,
+}
+
+impl EntityObjectInfo {
+    fn kind(&self) -> &KindInfo {
+        &self.types.kinds[self.kind]
+    }
+}
+
+/// An entity as an object of the type of its kind.
+pub struct EntityObject {
+    cid: String,
+    value: Value,
+}
+
+impl EntityObject {
+    fn new(entity: &Entity) -> Result<Self, FieldError> {
+        let cid = entity
+            .to_cid()
+            .map_err(|_| FieldError::from("Unable to calculate CID of entity"))?;
+        Ok(Self {
+            cid: format!("0x{}", cid.to_bytes().to_hex()),
+            value: serde_json::to_value(FormatWeb3(entity))?,
+        })
+    }
+}
+
+impl GraphQLType for EntityObject {
+    type Context = Context;
+    type TypeInfo = EntityObjectInfo;
+
+    fn name(info: &Self::TypeInfo) -> Option<&str> {
+        Some(&info.kind().name)
+    }
+
+    fn meta<'r>(info: &Self::TypeInfo, registry: &mut Registry<'r>) -> MetaType<'r>
+    where
+        DefaultScalarValue: 'r,
+    {
+        let kind = info.kind();
+        let mut fields = vec![registry.field::<String>("cid", &())];
+        for field in kind.data_fields.iter() {
+            fields.push(if field.is_list {
+                registry.field::<Vec<String>>(&field.name, &())
+            } else {
+                registry.field::<Option<String>>(&field.name, &())
+            });
+        }
+        for field in kind.cid_fields.iter() {
+            fields.push(if field.is_list {
+                registry.field::<Vec<Option<EntityUnion>>>(&field.name, &info.types)
+            } else {
+                registry.field::<Option<EntityUnion>>(&field.name, &info.types)
+            });
+        }
+        registry
+            .build_object_type::<EntityObject>(info, &fields)
+            .into_meta()
+    }
+
+    fn resolve_field(
+        &self,
+        info: &Self::TypeInfo,
+        field_name: &str,
+        _arguments: &Arguments,
+        executor: &Executor<Self::Context>,
+    ) -> ExecutionResult {
+        if field_name == "cid" {
+            return executor.resolve_with_ctx(&(), &self.cid);
+        }
+
+        let kind = info.kind();
+        let values = field_values(&self.value, field_name);
+        if let Some(field) = kind
+            .cid_fields
+            .iter()
+            .find(|field| field.name == field_name)
+        {
+            let context = executor.context();
+            if field.is_list {
+                // usually cached by the prefetch of the query, otherwise retrieved together
+                context.load_entities(&values)?;
+                let entities = values
+                    .iter()
+                    .map(|cid| context.get_entity(cid))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return executor.resolve(&info.types, &entities);
+            }
+            let entity = match values.first() {
+                Some(cid) => context.get_entity(cid)?,
+                None => None,
+            };
+            return executor.resolve(&info.types, &entity);
+        }
+
+        match kind
+            .data_fields
+            .iter()
+            .find(|field| field.name == field_name)
+        {
+            Some(field) if field.is_list => executor.resolve_with_ctx(&(), &values),
+            Some(_) => executor.resolve_with_ctx(&(), &values.into_iter().next()),
+            None => panic!("Field {} not found on type {}", field_name, kind.name),
+        }
+    }
+}
+
+/// Name of a request, under which it is recorded in the metrics.
+pub fn method_name(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET /graphql",
+        Method::POST => "POST /graphql",
+        _ => "other",
+    }
+}
+
+pub async fn handle_graphql(
+    ctx: RpcContext,
+    permissions: Arc<Permissions>,
+    req: Request<Body>,
+) -> Result<Response<Body>, GenericError> {
+    match *req.method() {
+        Method::GET => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(Body::from(graphiql_source("/graphql")))?),
+        Method::POST => {
+            if !permissions.is_allowed("rlay_experimentalGetEntity")
+                || !permissions.is_allowed("rlay_experimentalListCids")
+            {
+                return json_response(
+                    StatusCode::FORBIDDEN,
+                    json!({ "error": "Not allowed for this API key" }).to_string(),
+                );
+            }
+            let body = match ctx.limits.read_body(req.into_body()).await? {
+                Some(body) => body,
+                None => return limits::payload_too_large_response(),
+            };
+            let graphql_request: GraphQLRequest = match serde_json::from_slice(&body) {
+                Ok(graphql_request) => graphql_request,
+                Err(err) => {
+                    return json_response(
+                        StatusCode::BAD_REQUEST,
+                        json!({ "error": err.to_string() }).to_string(),
+                    )
+                }
+            };
+            let backend = match get_backend(&ctx.config, &ctx.sync_state).await {
+                Ok(backend) => backend,
+                Err(err) => {
+                    return json_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        json!({ "error": err.message }).to_string(),
+                    )
+                }
+            };
+
+            // Resolvers retrieve entities synchronously, so the query is executed outside of the
+            // async executor.
+            let max_entities = ctx.config.rpc.limits.max_entities_per_call;
+            let runtime = Handle::current();
+            let (is_ok, response_json) = tokio::task::spawn_blocking(move || {
+                let schema = schema();
+                let context = Context::new(backend, runtime, max_entities);
+                let response = graphql_request.execute(&schema, &context);
+                (response.is_ok(), serde_json::to_string(&response))
+            })
+            .await?;
+
+            let status = if is_ok {
+                StatusCode::OK
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            json_response(status, response_json?)
+        }
+        _ => json_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            json!({ "error": "Method not allowed" }).to_string(),
+        ),
+    }
+}
+
+fn json_response(status: StatusCode, body: String) -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))?)
+}
@@ -0,0 +1,533 @@
+//! gRPC API, serving the most common entity operations with a binary encoding of entities.
+//!
+//! The service is defined in `proto/rlay.proto`. In contrast to the JSON-RPC methods, entity
+//! fields and CIDs are transferred as raw bytes instead of hex strings.
+//!
+//! API keys are provided via `authorization` metadata, and every RPC requires the key to be allowed
+//! to call the JSON-RPC method it corresponds to. The rate limit and `max_in_flight_requests` of
+//! `[rpc.limits]` apply in the same way as to HTTP requests.
+//!
+//! Streamed responses are produced by a background task while the client receives them, so only a
+//! bounded number of messages is buffered at any time. `GetEntities` also retrieves the entities
+//! from the backend in chunks, while `ListCids` still retrieves all CIDs at once, as the backends
+//! can't list them in pages, and only streams their conversion to messages.
+use futures::prelude::*;
+use rlay_backend::rpc::*;
+use rlay_backend::GetEntity;
+use rlay_ontology::prelude::*;
+use rustc_hex::{FromHex, ToHex};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::net::ToSocketAddrs;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+use url::Url;
+
+use self::proto::rlay_server::{Rlay, RlayServer};
+use super::limits::{check_entity_count, Client, RequestSlot};
+use super::{get_backend, metrics, GenericError, RpcContext};
+use crate::backend::Backend;
+
+pub mod proto {
+    tonic::include_proto!("rlay");
+}
+
+/// Number of messages of a streamed response that are buffered until the client receives them.
+const STREAM_BUFFER_SIZE: usize = 64;
+
+/// Number of entities of a `GetEntities` call that are retrieved from the backend at once.
+const GET_ENTITIES_CHUNK_SIZE: usize = 256;
+
+pub async fn run_grpc(ctx: RpcContext, grpc_network_address: String) -> Result<(), GenericError> {
+    let addr = grpc_network_address
+        .parse::<Url>()
+        .map_err(|err| format!("Unable to parse rpc.grpc_network_address: {}", err))?
+        .to_socket_addrs()?
+        .next()
+        .ok_or("Unable to resolve rpc.grpc_network_address")?;
+
+    println!("Listening on http://{} (gRPC)", addr);
+
+    let shutdown_requested = ctx.shutdown.requested();
+    Server::builder()
+        .add_service(RlayServer::new(RlayService { ctx }))
+        .serve_with_shutdown(addr, shutdown_requested)
+        .await?;
+
+    Ok(())
+}
+
+fn backend_error(err: ::failure::Error) -> Status {
+    Status::internal(err.to_string())
+}
+
+fn encode_hex(value: &[u8]) -> String {
+    format!("0x{}", value.to_hex())
+}
+
+/// Decode a hex value retrieved from the backend, which is never caused by the request if invalid.
+fn decode_hex(value: &str) -> Result<Vec<u8>, Status> {
+    value
+        .trim_start_matches("0x")
+        .from_hex()
+        .map_err(|_| Status::internal(format!("Unable to decode hex value {}", value)))
+}
+
+/// Send the `messages` of a streamed response, until one of them is an error or the client has
+/// gone away.
+async fn send_messages<T>(
+    sender: &mut mpsc::Sender<Result<T, Status>>,
+    messages: impl IntoIterator<Item = Result<T, Status>>,
+) -> Result<(), ()> {
+    for message in messages {
+        let is_err = message.is_err();
+        sender.send(message).await.map_err(|_| ())?;
+        if is_err {
+            return Err(());
+        }
+    }
+    Ok(())
+}
+
+/// Convert an entity into its protobuf representation.
+fn entity_to_proto(entity: &Entity) -> Result<proto::Entity, Status> {
+    let kind_name: &str = entity.kind().into();
+    let entity_value = serde_json::to_value(FormatWeb3(entity))
+        .map_err(|err| Status::internal(err.to_string()))?;
+
+    let entity_object = entity_value
+        .as_object()
+        .ok_or_else(|| Status::internal("Unable to serialize entity"))?;
+
+    let mut fields = Vec::new();
+    for (name, field_value) in entity_object {
+        if name == "type" || name == "cid" {
+            continue;
+        }
+        let values = match field_value {
+            Value::Null => Vec::new(),
+            Value::String(value) => vec![decode_hex(value)?],
+            Value::Array(values) => values
+                .iter()
+                .map(|value| {
+                    value
+                        .as_str()
+                        .ok_or_else(|| Status::internal(format!("Invalid value of {}", name)))
+                        .and_then(decode_hex)
+                })
+                .collect::<Result<_, _>>()?,
+            _ => return Err(Status::internal(format!("Invalid value of {}", name))),
+        };
+        fields.push(proto::Field {
+            name: name.to_owned(),
+            values,
+        });
+    }
+
+    Ok(proto::Entity {
+        kind: kind_name.to_owned(),
+        fields,
+    })
+}
+
+/// Convert an entity from its protobuf representation.
+fn entity_from_proto(proto_entity: proto::Entity) -> Result<Entity, Status> {
+    validate_entity_kind(&proto_entity.kind)
+        .map_err(|err| Status::invalid_argument(err.to_string()))?;
+    let entity_kind = EntityKind::from_name(&proto_entity.kind).unwrap();
+
+    // the empty entity of the kind also tells us which fields are supposed to be arrays
+    let mut entity_value = serde_json::to_value(FormatWeb3(entity_kind.empty_entity()))
+        .map_err(|err| Status::internal(err.to_string()))?;
+    let entity_object = entity_value
+        .as_object_mut()
+        .ok_or_else(|| Status::internal("Unable to build entity"))?;
+
+    for field in proto_entity.fields {
+        let is_array = match entity_object.get(&field.name) {
+            Some(Value::Array(_)) => Some(true),
+            Some(_) if field.name != "type" && field.name != "cid" => Some(false),
+            _ => None,
+        };
+        let field_value = match is_array {
+            None => {
+                return Err(Status::invalid_argument(format!(
+                    "Unknown field {} of entity kind {}",
+                    field.name, proto_entity.kind
+                )))
+            }
+            Some(true) => Value::Array(
+                field
+                    .values
+                    .iter()
+                    .map(|value| Value::String(encode_hex(value)))
+                    .collect(),
+            ),
+            Some(false) => match field.values.as_slice() {
+                [] => continue,
+                [value] => Value::String(encode_hex(value)),
+                _ => {
+                    return Err(Status::invalid_argument(format!(
+                        "Field {} of entity kind {} only holds a single value",
+                        field.name, proto_entity.kind
+                    )))
+                }
+            },
+        };
+        entity_object.insert(field.name, field_value);
+    }
+
+    let web3_entity: FormatWeb3<Entity> = serde_json::from_value(entity_value)
+        .map_err(|err| Status::invalid_argument(err.to_string()))?;
+    Ok(web3_entity.0)
+}
+
+pub struct RlayService {
+    ctx: RpcContext,
+}
+
+/// Record a call to the gRPC `method`, which is an error if it returns an error status.
+async fn observe_grpc_call<T>(
+    method: &str,
+    call: impl Future<Output = Result<T, Status>>,
+) -> Result<T, Status> {
+    let start = Instant::now();
+    let result = call.await;
+    metrics::observe_rpc_call("grpc", method, start.elapsed(), result.is_err());
+    result
+}
+
+fn authorization<T>(request: &Request<T>) -> Option<&str> {
+    request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+}
+
+impl RlayService {
+    fn authorize<T>(&self, request: &Request<T>, rpc_method: &str) -> Result<(), Status> {
+        match self.ctx.auth.authenticate(authorization(request)) {
+            None => Err(Status::unauthenticated("Missing or invalid API key")),
+            Some(permissions) if !permissions.is_allowed(rpc_method) => Err(
+                Status::permission_denied(format!("Not allowed for this API key: {}", rpc_method)),
+            ),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Take a token from the rate limit bucket of the API key of `request`, or of its remote IP if
+    /// it doesn't have a valid one.
+    fn check_rate_limit<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let api_key = authorization(request)
+            .and_then(|authorization| self.ctx.auth.known_api_key(authorization));
+        let client = match (api_key, request.remote_addr()) {
+            (Some(api_key), _) => Client::ApiKey(api_key),
+            (None, Some(remote_addr)) => Client::Ip(remote_addr.ip()),
+            (None, None) => return Ok(()),
+        };
+        if !self.ctx.limits.check_rate_limit(client) {
+            return Err(Status::resource_exhausted("Rate limit exceeded"));
+        }
+        Ok(())
+    }
+
+    /// Wait for one of the `max_in_flight_requests`, which has to be held while handling the
+    /// request.
+    async fn acquire_slot(&self) -> Result<RequestSlot, Status> {
+        self.ctx
+            .limits
+            .acquire_slot()
+            .await
+            .ok_or_else(|| Status::unavailable("Too many requests queued"))
+    }
+
+    async fn backend(&self) -> Result<Backend, Status> {
+        get_backend(&self.ctx.config, &self.ctx.sync_state)
+            .await
+            .map_err(|err| Status::unavailable(err.message))
+    }
+}
+
+#[tonic::async_trait]
+impl Rlay for RlayService {
+    type GetEntitiesStream = mpsc::Receiver<Result<proto::Entity, Status>>;
+    type ListCidsStream = mpsc::Receiver<Result<proto::Cid, Status>>;
+
+    async fn get_entity(
+        &self,
+        request: Request<proto::GetEntityRequest>,
+    ) -> Result<Response<proto::GetEntityResponse>, Status> {
+        observe_grpc_call("GetEntity", async move {
+            self.check_rate_limit(&request)?;
+            self.authorize(&request, "rlay_experimentalGetEntity")?;
+            let _slot = self.acquire_slot().await?;
+
+            let backend = self.backend().await?;
+            let entity = GetEntity::get_entity(&backend, &request.into_inner().cid)
+                .await
+                .map_err(backend_error)?;
+
+            Ok(Response::new(proto::GetEntityResponse {
+                entity: entity.as_ref().map(entity_to_proto).transpose()?,
+            }))
+        })
+        .await
+    }
+
+    async fn get_entities(
+        &self,
+        request: Request<proto::GetEntitiesRequest>,
+    ) -> Result<Response<Self::GetEntitiesStream>, Status> {
+        observe_grpc_call("GetEntities", async move {
+            self.check_rate_limit(&request)?;
+            self.authorize(&request, "rlay_experimentalGetEntities")?;
+            let slot = self.acquire_slot().await?;
+
+            let cids: Vec<String> = request
+                .into_inner()
+                .cids
+                .iter()
+                .map(|cid| encode_hex(cid))
+                .collect();
+            check_entity_count(&self.ctx.config, cids.len())
+                .map_err(|err| Status::invalid_argument(err.message))?;
+            // deduplicated up front, as the backend only deduplicates the CIDs of a single chunk
+            let deduped_cids: Vec<String> = {
+                let mut seen_cids = HashSet::new();
+                cids.into_iter()
+                    .filter(|cid| seen_cids.insert(cid.to_owned()))
+                    .collect()
+            };
+
+            let mut backend = self.backend().await?;
+            let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER_SIZE);
+            tokio::spawn(async move {
+                // the request stays in flight until its response has been streamed completely
+                let _slot = slot;
+                for chunk in deduped_cids.chunks(GET_ENTITIES_CHUNK_SIZE) {
+                    let messages: Vec<_> =
+                        match BackendRpcMethods::get_entities(&mut backend, chunk.to_vec()).await {
+                            Ok(entities) => entities.iter().map(entity_to_proto).collect(),
+                            Err(err) => vec![Err(backend_error(err))],
+                        };
+                    if send_messages(&mut sender, messages).await.is_err() {
+                        return;
+                    }
+                }
+            });
+            Ok(Response::new(receiver))
+        })
+        .await
+    }
+
+    async fn store_entities(
+        &self,
+        request: Request<Streaming<proto::Entity>>,
+    ) -> Result<Response<proto::StoreEntitiesResponse>, Status> {
+        observe_grpc_call("StoreEntities", async move {
+            self.check_rate_limit(&request)?;
+            self.authorize(&request, "rlay_experimentalStoreEntities")?;
+            let _slot = self.acquire_slot().await?;
+
+            let mut proto_entities = request.into_inner();
+            let mut entities = Vec::new();
+            while let Some(proto_entity) = proto_entities.message().await? {
+                entities.push(entity_from_proto(proto_entity)?);
+                // checked while receiving, so that oversized streams aren't buffered completely
+                check_entity_count(&self.ctx.config, entities.len())
+                    .map_err(|err| Status::invalid_argument(err.message))?;
+            }
+
+            let mut backend = self.backend().await?;
+            let cids = BackendRpcMethods::store_entities(&mut backend, &entities, &json!({}))
+                .map_err(backend_error)
+                .await?;
+            self.ctx.subscriptions.publish(&entities);
+
+            Ok(Response::new(proto::StoreEntitiesResponse {
+                cids: cids.iter().map(|cid| cid.to_bytes()).collect(),
+            }))
+        })
+        .await
+    }
+
+    async fn list_cids(
+        &self,
+        request: Request<proto::ListCidsRequest>,
+    ) -> Result<Response<Self::ListCidsStream>, Status> {
+        observe_grpc_call("ListCids", async move {
+            self.check_rate_limit(&request)?;
+            self.authorize(&request, "rlay_experimentalListCids")?;
+            let slot = self.acquire_slot().await?;
+
+            let kind = request.into_inner().kind;
+            let kind = match kind.as_str() {
+                "" => None,
+                kind => {
+                    validate_entity_kind(kind)
+                        .map_err(|err| Status::invalid_argument(err.to_string()))?;
+                    Some(kind)
+                }
+            };
+
+            let mut backend = self.backend().await?;
+            let cids = BackendRpcMethods::list_cids(&mut backend, kind)
+                .map_err(backend_error)
+                .await?;
+
+            // the backends can't list CIDs in pages, but at least their conversion is streamed
+            let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER_SIZE);
+            tokio::spawn(async move {
+                let _slot = slot;
+                let messages = cids
+                    .iter()
+                    .map(|cid| decode_hex(cid).map(|cid| proto::Cid { cid }));
+                let _ = send_messages(&mut sender, messages).await;
+            });
+            Ok(Response::new(receiver))
+        })
+        .await
+    }
+
+    async fn resolve_entity(
+        &self,
+        request: Request<proto::ResolveEntityRequest>,
+    ) -> Result<Response<proto::ResolveEntityResponse>, Status> {
+        observe_grpc_call("ResolveEntity", async move {
+            self.check_rate_limit(&request)?;
+            self.authorize(&request, "rlay_experimentalResolveEntity")?;
+            let _slot = self.acquire_slot().await?;
+
+            let cid = encode_hex(&request.into_inner().cid);
+
+            let mut backend = self.backend().await?;
+            let resolved_entities = BackendRpcMethods::resolve_entity(&mut backend, &cid)
+                .map_err(backend_error)
+                .await?;
+
+            let resolved = resolved_entities
+                .iter()
+                .map(|(cid, entities)| -> Result<_, Status> {
+                    Ok(proto::ResolvedEntities {
+                        cid: decode_hex(cid)?,
+                        entities: entities
+                            .iter()
+                            .map(entity_to_proto)
+                            .collect::<Result<_, _>>()?,
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(Response::new(proto::ResolveEntityResponse { resolved }))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cid::ToCid;
+    use tonic::Code;
+
+    fn field(name: &str, values: Vec<Vec<u8>>) -> proto::Field {
+        proto::Field {
+            name: name.to_owned(),
+            values,
+        }
+    }
+
+    fn roundtrip(entity: Entity) {
+        let proto_entity = entity_to_proto(&entity).unwrap();
+        assert_eq!(entity, entity_from_proto(proto_entity).unwrap());
+    }
+
+    #[test]
+    fn roundtrips_single_valued_fields() {
+        let annotation: Entity = Annotation {
+            property: vec![1, 2],
+            value: vec![3, 4],
+            ..Annotation::default()
+        }
+        .into();
+
+        let proto_entity = entity_to_proto(&annotation).unwrap();
+        assert_eq!("Annotation", proto_entity.kind);
+        assert!(proto_entity
+            .fields
+            .iter()
+            .any(|field| field.name == "property" && field.values == vec![vec![1, 2]]));
+        roundtrip(annotation);
+    }
+
+    #[test]
+    fn roundtrips_array_fields_with_cids() {
+        let annotation_cids: Vec<Vec<u8>> = (0..2u8)
+            .map(|seed| {
+                let annotation: Entity = Annotation {
+                    property: vec![seed],
+                    ..Annotation::default()
+                }
+                .into();
+                annotation.to_cid().unwrap().to_bytes()
+            })
+            .collect();
+        let individual: Entity = Individual {
+            annotations: annotation_cids.clone(),
+            ..Individual::default()
+        }
+        .into();
+
+        let proto_entity = entity_to_proto(&individual).unwrap();
+        assert!(proto_entity
+            .fields
+            .iter()
+            .any(|field| field.name == "annotations" && field.values == annotation_cids));
+        roundtrip(individual);
+    }
+
+    #[test]
+    fn roundtrips_empty_entity() {
+        roundtrip(Individual::default().into());
+    }
+
+    #[test]
+    fn rejects_unknown_kind() {
+        let proto_entity = proto::Entity {
+            kind: "NotAnEntityKind".to_owned(),
+            fields: vec![],
+        };
+
+        let err = entity_from_proto(proto_entity).unwrap_err();
+        assert_eq!(Code::InvalidArgument, err.code());
+    }
+
+    #[test]
+    fn rejects_unknown_field() {
+        let proto_entity = proto::Entity {
+            kind: "Annotation".to_owned(),
+            fields: vec![field("not_a_field", vec![vec![1]])],
+        };
+
+        let err = entity_from_proto(proto_entity).unwrap_err();
+        assert_eq!(Code::InvalidArgument, err.code());
+    }
+
+    #[test]
+    fn rejects_multiple_values_of_single_valued_field() {
+        let proto_entity = proto::Entity {
+            kind: "Annotation".to_owned(),
+            fields: vec![field("property", vec![vec![1], vec![2]])],
+        };
+
+        let err = entity_from_proto(proto_entity).unwrap_err();
+        assert_eq!(Code::InvalidArgument, err.code());
+    }
+
+    #[test]
+    fn reports_invalid_hex_as_internal_error() {
+        let err = decode_hex("0xnothex").unwrap_err();
+        assert_eq!(Code::Internal, err.code());
+    }
+}
@@ -0,0 +1,105 @@
+//! Health checks of the client.
+//!
+//! - `GET /health`: Liveness, which only checks that the RPC is able to respond
+//! - `GET /health/ready`: Readiness, which checks the components that are required for serving
+//!   requests (backend, plugins and the proxy target), and responds with `503` if one of them
+//!   isn't ready
+use futures::prelude::*;
+use hyper::{header, Body, Response, StatusCode};
+use rlay_backend::rpc::BackendRpcMethods;
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+use super::proxy::proxy_rpc_call;
+use super::{get_backend, GenericError, RpcContext};
+use crate::plugins::SharedPluginRegistry;
+
+/// Result of the check of a single component, with details about it if it is ready.
+type CheckResult = Result<Value, String>;
+
+pub async fn http_get_health() -> Result<Response<Body>, GenericError> {
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"status": "healthy"}"#))?;
+    Ok(response)
+}
+
+pub async fn http_get_ready(ctx: RpcContext) -> Result<Response<Body>, GenericError> {
+    let check_timeout = Duration::from_millis(ctx.config.rpc.readiness_timeout_ms);
+    let with_timeout = |check: future::BoxFuture<'static, CheckResult>| {
+        tokio::time::timeout(check_timeout, check)
+            .map(|result| result.unwrap_or_else(|_| Err("Check timed out".to_owned())))
+    };
+
+    let proxy_check = match ctx.config.rpc.proxy_target_network_address.clone() {
+        Some(proxy_target) => with_timeout(check_proxy_target(proxy_target).boxed())
+            .map(Some)
+            .boxed(),
+        None => future::ready(None).boxed(),
+    };
+    let (backend, plugins, proxy_target) = future::join3(
+        with_timeout(check_backend(ctx.clone()).boxed()),
+        with_timeout(check_plugins(ctx.plugins.clone()).boxed()),
+        proxy_check,
+    )
+    .await;
+
+    let mut components = Map::new();
+    let mut is_ready = true;
+    let mut add_component = |name: &str, result: CheckResult| {
+        let component = match result {
+            Ok(mut details) => {
+                details["status"] = Value::String("up".to_owned());
+                details
+            }
+            Err(err) => {
+                is_ready = false;
+                json!({ "status": "down", "error": err })
+            }
+        };
+        components.insert(name.to_owned(), component);
+    };
+    add_component("backend", backend);
+    add_component("plugins", plugins);
+    if let Some(proxy_target) = proxy_target {
+        add_component("proxy_target", proxy_target);
+    }
+
+    let (status, status_name) = if is_ready {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not_ready")
+    };
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({ "status": status_name, "components": components }).to_string(),
+        ))?)
+}
+
+async fn check_backend(ctx: RpcContext) -> CheckResult {
+    let mut backend = get_backend(&ctx.config, &ctx.sync_state)
+        .await
+        .map_err(|err| err.message)?;
+    // Pinged in its own task, so that a backend panicking while connecting is reported as down
+    tokio::spawn(async move { BackendRpcMethods::ping(&mut backend).await })
+        .await
+        .map_err(|_| "Backend failed while pinging".to_owned())?
+        .map_err(|err| err.to_string())?;
+    Ok(json!({}))
+}
+
+/// The plugins are loaded at startup, so this only reports the filters that are loaded.
+async fn check_plugins(plugins: SharedPluginRegistry) -> CheckResult {
+    Ok(json!({ "filters": plugins.current().filter_names() }))
+}
+
+async fn check_proxy_target(proxy_target: String) -> CheckResult {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "net_version", "params": [] });
+    proxy_rpc_call(proxy_target, request)
+        .await
+        .map_err(|err| err.message)?;
+    Ok(json!({}))
+}
@@ -0,0 +1,141 @@
+//! JSON-RPC over a Unix domain socket, serving the same methods as the HTTP RPC.
+//!
+//! Every payload and every response is a single line of JSON. Access to the socket is controlled
+//! by its file permissions, so API keys are not required and the rate limit doesn't apply. Calls
+//! still count against `rpc.limits.max_in_flight_requests`, and connections that send a line
+//! longer than `rpc.limits.max_body_size` are closed.
+use futures::prelude::*;
+use serde_json::Value;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::PathBuf;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+use super::auth::Permissions;
+use super::subscriptions::Connection;
+use super::{jsonrpc, limits, GenericError, RpcContext};
+
+pub async fn run_ipc_rpc(ctx: RpcContext, ipc_path: PathBuf) -> Result<(), GenericError> {
+    // A socket left over by a previous run that hasn't shut down cleanly would prevent binding,
+    // but anything else at the path is not ours to remove
+    match std::fs::symlink_metadata(&ipc_path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(&ipc_path)?,
+        Ok(_) => {
+            return Err(format!(
+                "Unable to listen on {}: The path exists and is not a socket",
+                ipc_path.display()
+            )
+            .into())
+        }
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    let mut listener = UnixListener::bind(&ipc_path)?;
+    // only the user running the client may connect, as calls don't require an API key
+    std::fs::set_permissions(&ipc_path, std::fs::Permissions::from_mode(0o600))?;
+
+    println!("Listening on {}", ipc_path.display());
+
+    let mut shutdown_requested = ctx.shutdown.requested();
+    loop {
+        let accept = listener.accept();
+        futures::pin_mut!(accept);
+        let (stream, _) = match future::select(accept, &mut shutdown_requested).await {
+            future::Either::Left((accepted, _)) => accepted?,
+            future::Either::Right(_) => break,
+        };
+        tokio::spawn(handle_connection(ctx.clone(), stream));
+    }
+    std::fs::remove_file(&ipc_path)?;
+    Ok(())
+}
+
+async fn handle_connection(ctx: RpcContext, stream: UnixStream) {
+    trace!("IPC connection established");
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    // Responses and subscription notifications are sent through a channel, so that calls on the
+    // same connection can be handled concurrently.
+    let (connection, mut receiver) = Connection::new();
+    tokio::spawn(async move {
+        while let Some(message) = receiver.next().await {
+            let line = format!("{}\n", message);
+            if let Err(err) = writer.write_all(line.as_bytes()).await {
+                debug!("Sending to IPC connection failed: {}", err);
+                break;
+            }
+        }
+    });
+
+    let permissions = Permissions::unrestricted();
+    let max_body_size = ctx.limits.max_body_size();
+    let mut reader = BufReader::new(reader);
+    let mut shutdown_requested = ctx.shutdown.requested();
+    loop {
+        let mut payload = Vec::new();
+        let line = {
+            // reading one byte more than allowed tells oversized lines apart from ones at the limit
+            let mut limited_reader = (&mut reader).take(max_body_size as u64 + 1);
+            let next_line = limited_reader.read_until(b'\n', &mut payload);
+            futures::pin_mut!(next_line);
+            // After shutdown has been requested, calls that have already been received are still
+            // answered, but no new ones are accepted
+            match future::select(next_line, &mut shutdown_requested).await {
+                future::Either::Left((line, _)) => line,
+                future::Either::Right(_) => break,
+            }
+        };
+        match line {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(err) => {
+                debug!("Receiving from IPC connection failed: {}", err);
+                break;
+            }
+        }
+        // the connection is closed if the client doesn't keep up with receiving its messages
+        if connection.is_closed() {
+            break;
+        }
+        if payload.last() == Some(&b'\n') {
+            payload.pop();
+        } else if payload.len() > max_body_size {
+            // the rest of the line can't be told apart from the next payload, so the connection
+            // can't be used any further
+            connection.send(jsonrpc::error_response(
+                Value::Null,
+                limits::payload_too_large_error(max_body_size),
+            ));
+            break;
+        }
+        if payload.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        // calls count against `max_in_flight_requests` like HTTP requests, and waiting for a slot
+        // stops reading further calls of the connection in the meantime
+        let slot = match ctx.limits.acquire_slot().await {
+            Some(slot) => slot,
+            None => {
+                connection.send(jsonrpc::error_response(
+                    Value::Null,
+                    limits::overloaded_error(),
+                ));
+                continue;
+            }
+        };
+        let ctx = ctx.clone();
+        let connection = connection.clone();
+        let permissions = permissions.clone();
+        tokio::spawn(async move {
+            let _slot = slot;
+            if let Some(response) =
+                jsonrpc::handle_payload(&ctx, Some(&connection), &permissions, &payload).await
+            {
+                connection.send(response);
+            }
+        });
+    }
+    ctx.subscriptions.unsubscribe_connection(&connection);
+    trace!("IPC connection closed");
+}
@@ -0,0 +1,184 @@
+//! Handling of JSON-RPC 2.0 payloads, independent of the transport they have been received on.
+use serde_json::Value;
+use std::time::Instant;
+
+use super::auth::{method_not_allowed_error, Permissions};
+use super::metrics;
+use super::proxy::proxy_rpc_call;
+use super::subscriptions::Connection;
+use super::{call_method, JsonRpcResult, RpcContext};
+
+/// A single call contained in a JSON-RPC payload.
+struct Call {
+    /// `None` if the call is a notification, which doesn't receive a response.
+    id: Option<Value>,
+    method: String,
+    params: Vec<Value>,
+    /// The call as it has been received, for proxying it to the upstream RPC.
+    raw: Value,
+}
+
+impl Call {
+    /// Validate a request object, returning the error response for it if it's invalid.
+    fn from_value(value: Value) -> Result<Self, Value> {
+        let object = match value.as_object() {
+            Some(object) => object,
+            None => {
+                return Err(error_response(
+                    Value::Null,
+                    invalid_request("Not an object"),
+                ))
+            }
+        };
+
+        let id = match object.get("id") {
+            None => None,
+            Some(id) if id.is_null() || id.is_string() || id.is_number() => Some(id.clone()),
+            Some(_) => {
+                return Err(error_response(
+                    Value::Null,
+                    invalid_request("'id' must be a string, number or null"),
+                ))
+            }
+        };
+        let fail =
+            |err: jsonrpc_core::Error| error_response(id.clone().unwrap_or(Value::Null), err);
+
+        match object.get("jsonrpc") {
+            None => {}
+            Some(Value::String(version)) if version == "2.0" => {}
+            Some(_) => return Err(fail(invalid_request("'jsonrpc' must be \"2.0\""))),
+        }
+        let method = match object.get("method") {
+            Some(Value::String(method)) => method.to_owned(),
+            _ => return Err(fail(invalid_request("'method' must be a string"))),
+        };
+        let params = match object.get("params") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(params)) => params.to_owned(),
+            Some(_) => {
+                return Err(fail(jsonrpc_core::Error::invalid_params(
+                    "Only positional (array) params are supported",
+                )))
+            }
+        };
+
+        Ok(Self {
+            id,
+            method,
+            params,
+            raw: value,
+        })
+    }
+}
+
+fn invalid_request(message: &str) -> jsonrpc_core::Error {
+    let mut err = jsonrpc_core::Error::invalid_request();
+    err.message = format!("Invalid request: {}", message);
+    err
+}
+
+pub fn error_response(id: Value, err: jsonrpc_core::Error) -> Value {
+    json!({ "id": id, "jsonrpc": "2.0", "error": err })
+}
+
+fn response(id: Value, result: JsonRpcResult<Value>) -> Value {
+    match result {
+        Ok(result) => json!({ "id": id, "jsonrpc": "2.0", "result": result }),
+        Err(err) => error_response(id, err),
+    }
+}
+
+/// Handle a JSON-RPC payload, which may either be a single call or a batch of calls.
+///
+/// `connection` is the connection the payload has been received on, if it supports pushing
+/// messages to the client, and `permissions` are the ones of the API key it has been sent with.
+/// Returns `None` if nothing should be sent back, which is the case if the payload only consisted
+/// of notifications.
+pub async fn handle_payload(
+    ctx: &RpcContext,
+    connection: Option<&Connection>,
+    permissions: &Permissions,
+    payload: &[u8],
+) -> Option<Value> {
+    let _call = ctx.shutdown.track_call();
+    let payload: Value = match serde_json::from_slice(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            let mut parse_err = jsonrpc_core::Error::parse_error();
+            parse_err.message = format!("Parse error: {}", err);
+            return Some(error_response(Value::Null, parse_err));
+        }
+    };
+
+    match payload {
+        Value::Array(calls) => {
+            if calls.is_empty() {
+                return Some(error_response(Value::Null, invalid_request("Empty batch")));
+            }
+            let mut responses = Vec::new();
+            for call in calls {
+                if let Some(response) = handle_call(ctx, connection, permissions, call).await {
+                    responses.push(response);
+                }
+            }
+            if responses.is_empty() {
+                None
+            } else {
+                Some(Value::Array(responses))
+            }
+        }
+        call => handle_call(ctx, connection, permissions, call).await,
+    }
+}
+
+async fn handle_call(
+    ctx: &RpcContext,
+    connection: Option<&Connection>,
+    permissions: &Permissions,
+    call: Value,
+) -> Option<Value> {
+    let call = match Call::from_value(call) {
+        Ok(call) => call,
+        Err(err_response) => return Some(err_response),
+    };
+    if !permissions.is_allowed(&call.method) {
+        let err = method_not_allowed_error(&call.method);
+        return call.id.map(|id| error_response(id, err));
+    }
+
+    let start = Instant::now();
+    let internal_result = call_method(ctx, connection, &call.method, call.params).await;
+    let result = match internal_result {
+        Some(result) => {
+            metrics::observe_rpc_call("jsonrpc", &call.method, start.elapsed(), result.is_err());
+            result
+        }
+        None => match ctx.config.rpc.proxy_target_network_address.clone() {
+            None => {
+                let mut err = jsonrpc_core::Error::method_not_found();
+                err.message = format!("Method not found: {}", call.method);
+                Err(err)
+            }
+            Some(proxy_target) => {
+                // The upstream RPC responds with a complete response object (or nothing for
+                // notifications), so it's passed through as-is.
+                let proxy_result = proxy_rpc_call(proxy_target, call.raw).await;
+                // proxied methods are recorded together, as they are not known in advance
+                metrics::observe_rpc_call(
+                    "jsonrpc",
+                    "proxied",
+                    start.elapsed(),
+                    proxy_result.is_err(),
+                );
+                return match (call.id, proxy_result) {
+                    (None, _) => None,
+                    (Some(_), Ok(proxy_response)) => Some(proxy_response),
+                    (Some(id), Err(err)) => Some(error_response(id, err)),
+                };
+            }
+        },
+    };
+
+    call.id.map(|id| response(id, result))
+}
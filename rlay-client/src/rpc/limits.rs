@@ -0,0 +1,266 @@
+//! Limits on the size, number and rate of RPC requests (see `[rpc.limits]`).
+use futures::prelude::*;
+use hyper::{header, Body, Response, StatusCode};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::{GenericError, JsonRpcResult};
+use crate::config::rpc::{LimitsConfig, RateLimitConfig};
+use crate::config::Config;
+
+/// Number of clients to track before the rate limit buckets of idle clients are first dropped.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// The client a request is accounted to for rate limiting.
+pub enum Client<'a> {
+    ApiKey(&'a str),
+    Ip(IpAddr),
+}
+
+#[derive(Clone)]
+pub struct Limits {
+    max_body_size: usize,
+    in_flight: Option<Arc<InFlightLimit>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+impl Limits {
+    pub fn from_config(limits_config: &LimitsConfig) -> Self {
+        Self {
+            max_body_size: limits_config.max_body_size,
+            in_flight: limits_config.max_in_flight_requests.map(|max_in_flight| {
+                Arc::new(InFlightLimit::new(
+                    max_in_flight,
+                    limits_config.max_queued_requests,
+                ))
+            }),
+            rate_limiter: limits_config
+                .rate_limit
+                .as_ref()
+                .map(|rate_limit| Arc::new(RateLimiter::new(rate_limit))),
+        }
+    }
+
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+
+    /// Read a request body, returning `None` if it is larger than `max_body_size`.
+    pub async fn read_body(&self, mut body: Body) -> Result<Option<Vec<u8>>, GenericError> {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            if bytes.len() + chunk.len() > self.max_body_size {
+                return Ok(None);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(Some(bytes))
+    }
+
+    /// Wait until the request may be handled.
+    ///
+    /// Returns `None` if the request has to be rejected because too many requests are already
+    /// waiting. Otherwise the returned slot has to be held while handling the request.
+    pub async fn acquire_slot(&self) -> Option<RequestSlot> {
+        match self.in_flight.as_ref() {
+            Some(in_flight) => in_flight.acquire().await.map(|permit| RequestSlot {
+                _permit: Some(permit),
+            }),
+            None => Some(RequestSlot { _permit: None }),
+        }
+    }
+
+    /// Take a token from the rate limit bucket of `client`, returning `false` if it has none left.
+    pub fn check_rate_limit(&self, client: Client) -> bool {
+        match self.rate_limiter.as_ref() {
+            Some(rate_limiter) => rate_limiter.check(client),
+            None => true,
+        }
+    }
+}
+
+/// Slot of a request among the `max_in_flight_requests`, which is released when dropped.
+pub struct RequestSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+struct InFlightLimit {
+    semaphore: Arc<Semaphore>,
+    queued: AtomicUsize,
+    max_queued: usize,
+}
+
+/// Counts a request as queued for as long as it is alive.
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for QueuedGuard<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl InFlightLimit {
+    fn new(max_in_flight: usize, max_queued: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_in_flight)),
+            queued: AtomicUsize::new(0),
+            max_queued,
+        }
+    }
+
+    async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        // the guard also releases the queue slot if the client goes away while waiting
+        let _queued = QueuedGuard(&self.queued);
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            return None;
+        }
+        Some(self.semaphore.clone().acquire_owned().await)
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+struct Buckets {
+    by_client: HashMap<String, Bucket>,
+    /// Number of tracked clients at which the buckets of idle clients are dropped next.
+    ///
+    /// Doubles the number of clients that are still active after dropping, so that the clients
+    /// are only iterated over once per as many new clients (instead of on every request once
+    /// there are too many active clients to drop any).
+    evict_at: usize,
+}
+
+/// Token bucket rate limiter, with one bucket per client.
+struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    fn new(rate_limit_config: &RateLimitConfig) -> Self {
+        Self {
+            requests_per_second: rate_limit_config.requests_per_second,
+            burst: rate_limit_config
+                .burst
+                .map(f64::from)
+                .unwrap_or(rate_limit_config.requests_per_second)
+                .max(1.0),
+            buckets: Mutex::new(Buckets {
+                by_client: HashMap::new(),
+                evict_at: MAX_TRACKED_CLIENTS,
+            }),
+        }
+    }
+
+    fn check(&self, client: Client) -> bool {
+        let client_key = match client {
+            Client::ApiKey(key) => format!("key:{}", key),
+            Client::Ip(ip) => format!("ip:{}", ip),
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.by_client.len() >= buckets.evict_at {
+            let (requests_per_second, burst) = (self.requests_per_second, self.burst);
+            // buckets that would have been refilled completely are the same as new ones
+            buckets.by_client.retain(|_, bucket| {
+                let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+                bucket.tokens + elapsed * requests_per_second < burst
+            });
+            buckets.evict_at = (buckets.by_client.len() * 2).max(MAX_TRACKED_CLIENTS);
+        }
+
+        let bucket = buckets.by_client.entry(client_key).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.requests_per_second).min(self.burst);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Check that a call doesn't exceed `rpc.limits.max_entities_per_call` with its `count` entities.
+pub fn check_entity_count(config: &Config, count: usize) -> JsonRpcResult<()> {
+    let max_entities = config.rpc.limits.max_entities_per_call;
+    if count > max_entities {
+        return Err(jsonrpc_core::Error::invalid_params(format!(
+            "At most {} entities may be passed per call, got {}",
+            max_entities, count
+        )));
+    }
+    Ok(())
+}
+
+fn error_response(status: StatusCode, message: &str) -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "error": message }).to_string()))?)
+}
+
+pub fn payload_too_large_response() -> Result<Response<Body>, GenericError> {
+    error_response(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large")
+}
+
+pub fn overloaded_response() -> Result<Response<Body>, GenericError> {
+    let mut response = error_response(StatusCode::SERVICE_UNAVAILABLE, "Too many requests queued")?;
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, header::HeaderValue::from_static("1"));
+    Ok(response)
+}
+
+pub fn rate_limited_response() -> Result<Response<Body>, GenericError> {
+    let mut response = error_response(StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded")?;
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, header::HeaderValue::from_static("1"));
+    Ok(response)
+}
+
+/// Error of a JSON-RPC call that has been rejected because of the rate limit.
+pub fn rate_limited_error() -> jsonrpc_core::Error {
+    jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::ServerError(-32005),
+        message: "Rate limit exceeded".to_owned(),
+        data: None,
+    }
+}
+
+/// Error of a JSON-RPC call that has been rejected because too many requests are queued.
+pub fn overloaded_error() -> jsonrpc_core::Error {
+    jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::ServerError(-32006),
+        message: "Too many requests queued".to_owned(),
+        data: None,
+    }
+}
+
+/// Error of a JSON-RPC payload that is larger than `max_body_size`.
+pub fn payload_too_large_error(max_body_size: usize) -> jsonrpc_core::Error {
+    jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::InvalidRequest,
+        message: format!("Payload larger than {} bytes", max_body_size),
+        data: None,
+    }
+}
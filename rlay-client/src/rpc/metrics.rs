@@ -0,0 +1,98 @@
+//! Prometheus metrics of the RPC, served at `GET /metrics` together with the metrics of the
+//! backends.
+//!
+//! Calls are recorded per API (`jsonrpc`, `rest`, `graphql` or `grpc`) and method. JSON-RPC calls
+//! are recorded the same way for all transports (HTTP, WebSocket and IPC).
+use futures::prelude::*;
+use hyper::{header, Body, Response, StatusCode};
+use once_cell::sync::Lazy;
+use prometheus::{Encoder, HistogramVec, IntCounterVec, TextEncoder};
+use std::time::{Duration, Instant};
+
+use super::GenericError;
+
+static RPC_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    prometheus::register_int_counter_vec!(
+        "rlay_rpc_requests_total",
+        "Number of RPC calls",
+        &["api", "method"]
+    )
+    .unwrap()
+});
+
+static RPC_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    prometheus::register_int_counter_vec!(
+        "rlay_rpc_errors_total",
+        "Number of RPC calls that resulted in an error",
+        &["api", "method"]
+    )
+    .unwrap()
+});
+
+static RPC_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    prometheus::register_histogram_vec!(
+        "rlay_rpc_request_duration_seconds",
+        "Duration of RPC calls",
+        &["api", "method"],
+        prometheus::exponential_buckets(0.001, 2.0, 16).unwrap()
+    )
+    .unwrap()
+});
+
+static FILTER_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    prometheus::register_histogram_vec!(
+        "rlay_filter_duration_seconds",
+        "Duration of the execution of filter plugins",
+        &["filter"],
+        prometheus::exponential_buckets(0.0001, 2.0, 16).unwrap()
+    )
+    .unwrap()
+});
+
+/// Record a call to `method` of `api`.
+///
+/// `method` should only be one of the methods implemented by the client (or a fixed placeholder
+/// like `"proxied"`), as every method results in its own time series.
+pub fn observe_rpc_call(api: &str, method: &str, duration: Duration, is_error: bool) {
+    RPC_REQUESTS.with_label_values(&[api, method]).inc();
+    if is_error {
+        RPC_ERRORS.with_label_values(&[api, method]).inc();
+    }
+    RPC_DURATION
+        .with_label_values(&[api, method])
+        .observe(duration.as_secs_f64());
+}
+
+/// Record a call to `method` of `api` that is answered with a HTTP response, which is an error if
+/// it has a 4xx or 5xx status.
+pub async fn observe_http_call(
+    api: &str,
+    method: &str,
+    call: impl Future<Output = Result<Response<Body>, GenericError>>,
+) -> Result<Response<Body>, GenericError> {
+    let start = Instant::now();
+    let response = call.await;
+    let is_error = match response.as_ref() {
+        Ok(response) => response.status().is_client_error() || response.status().is_server_error(),
+        Err(_) => true,
+    };
+    observe_rpc_call(api, method, start.elapsed(), is_error);
+    response
+}
+
+/// Record the execution of the filter plugin `filter`.
+pub fn observe_filter_duration(filter: &str, duration: Duration) {
+    FILTER_DURATION
+        .with_label_values(&[filter])
+        .observe(duration.as_secs_f64());
+}
+
+pub async fn http_get_metrics() -> Result<Response<Body>, GenericError> {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder.encode(&prometheus::gather(), &mut buffer)?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))?)
+}
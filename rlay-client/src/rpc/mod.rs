@@ -1,7 +1,26 @@
+mod auth;
+mod cors;
+mod discover;
+mod graphql;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod health;
+#[cfg(unix)]
+mod ipc;
+mod jsonrpc;
+mod limits;
+mod metrics;
 mod proxy;
+mod rest;
+mod shutdown;
+mod subscriptions;
+mod tls;
+mod ws;
 
 use cid::ToCid;
 use futures::prelude::*;
+use hyper::server::accept;
+use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
 use rlay_backend::rpc::*;
@@ -9,22 +28,47 @@ use rlay_ontology::prelude::*;
 use rlay_plugin_interface::{FilterContext, RlayFilter};
 use rustc_hex::ToHex;
 use serde_json::{Map, Value};
-use std::error::Error;
-use std::net::ToSocketAddrs;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::runtime::Runtime;
+use tokio_rustls::server::TlsStream;
 use url::Url;
 
-use self::proxy::proxy_rpc_call;
+use self::auth::{Auth, Permissions};
+use self::cors::Cors;
+use self::limits::{Client, Limits};
+use self::shutdown::Shutdown;
+use self::subscriptions::{Connection, NewEntitiesFilter, Subscriptions};
 use crate::backend::{Backend, SyncState};
 use crate::config::Config;
-use crate::plugins::PluginRegistry;
+use crate::plugins::{PluginRegistry, SharedPluginRegistry};
 
 const NETWORK_VERSION: &'static str = "0.3.3";
 const CLIENT_VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
+/// Paths that are served without an API key and aren't subject to `[rpc.limits]`, so that they
+/// can be used by monitoring.
+const UNAUTHENTICATED_PATHS: &[&str] = &["/health", "/health/ready", "/metrics"];
+
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
 type JsonRpcResult<T> = std::result::Result<T, jsonrpc_core::Error>;
 
+/// State shared between all transports of the RPC.
+#[derive(Clone)]
+pub struct RpcContext {
+    pub config: Config,
+    pub sync_state: SyncState,
+    pub subscriptions: Subscriptions,
+    pub auth: Auth,
+    pub cors: Cors,
+    pub limits: Limits,
+    pub shutdown: Shutdown,
+    pub plugins: SharedPluginRegistry,
+}
+
 pub fn start_rpc(full_config: &Config) {
     let config = full_config.rpc.clone();
     if config.disabled {
@@ -61,125 +105,365 @@ fn failure_into_jsonrpc_err(err: ::failure::Error) -> jsonrpc_core::Error {
 }
 
 async fn run_rpc(full_config: &Config) -> Result<(), GenericError> {
-    let addr = full_config
+    let network_url = full_config
         .rpc
         .network_address
         .parse::<Url>()
-        .expect("Unable to parse rpc.network_address")
-        .to_socket_addrs()
-        .unwrap()
-        .next()
-        .unwrap();
+        .expect("Unable to parse rpc.network_address");
+    let addr = network_url.to_socket_addrs().unwrap().next().unwrap();
+
+    let data_path = Path::new(full_config.data_path.as_ref().unwrap());
+    let sync_state = SyncState::new(full_config.get_backend_config().unwrap(), data_path).await;
+    let shutdown = Shutdown::on_signals();
+    let plugins = SharedPluginRegistry::from_dir(&full_config.plugins_path)
+        .map_err(|err| format!("Unable to load plugins: {}", err))?;
+    if let Err(err) = plugins.watch() {
+        warn!(
+            "Not reloading plugins on changes to {}: {}",
+            full_config.plugins_path, err
+        );
+    }
+    let ctx = RpcContext {
+        config: full_config.clone(),
+        sync_state: sync_state.clone(),
+        subscriptions: Subscriptions::default(),
+        auth: Auth::from_config(full_config.rpc.auth.as_ref()).expect("Invalid rpc.auth config"),
+        cors: Cors::from_config(&full_config.rpc),
+        limits: Limits::from_config(&full_config.rpc.limits),
+        shutdown: shutdown.clone(),
+        plugins,
+    };
+
+    // WebSocket RPC
+    let ws_server = match full_config.rpc.ws_network_address.clone() {
+        Some(ws_network_address) => ws::run_ws_rpc(ctx.clone(), ws_network_address).boxed(),
+        None => future::ok(()).boxed(),
+    };
 
-    let full_config = full_config.clone();
-    let sync_state = SyncState::new(full_config.get_backend_config().unwrap()).await;
-
-    let new_service = make_service_fn(move |_| {
-        let full_config = full_config.clone();
-        let sync_state = sync_state.clone();
-        async {
-            Ok::<_, GenericError>(service_fn(move |req| {
-                match (req.method(), req.uri().path()) {
-                    (&Method::GET, "/health") => http_get_health().boxed(),
-                    _ => handle_jsonrpc(full_config.clone(), sync_state.clone(), req).boxed(),
+    // IPC RPC
+    let ipc_server = match full_config.rpc.ipc_path.as_ref() {
+        #[cfg(unix)]
+        Some(ipc_path) => ipc::run_ipc_rpc(ctx.clone(), data_path.join(ipc_path)).boxed(),
+        #[cfg(not(unix))]
+        Some(_) => {
+            warn!("rpc.ipc_path is only supported on Unix. Not starting IPC RPC server.");
+            future::ok(()).boxed()
+        }
+        None => future::ok(()).boxed(),
+    };
+
+    // gRPC API
+    let grpc_server = match full_config.rpc.grpc_network_address.clone() {
+        #[cfg(feature = "grpc")]
+        Some(grpc_network_address) => grpc::run_grpc(ctx.clone(), grpc_network_address).boxed(),
+        #[cfg(not(feature = "grpc"))]
+        Some(_) => {
+            warn!("rpc.grpc_network_address requires the grpc feature. Not starting gRPC server.");
+            future::ok(()).boxed()
+        }
+        None => future::ok(()).boxed(),
+    };
+
+    let server = match network_url.scheme() {
+        "https" => {
+            let acceptor = tls::tls_acceptor(&full_config.rpc)?;
+            let listener = TcpListener::bind(&addr).await?;
+            let new_service = make_service_fn(move |conn: &TlsStream<TcpStream>| {
+                let ctx = ctx.clone();
+                let remote_ip = conn.get_ref().0.peer_addr().ok().map(|addr| addr.ip());
+                async move {
+                    Ok::<_, GenericError>(service_fn(move |req| {
+                        handle_request(ctx.clone(), remote_ip, req)
+                    }))
                 }
-            }))
+            });
+            let incoming = tls::incoming(listener, acceptor, shutdown.requested());
+            Server::builder(accept::from_stream(incoming))
+                .serve(new_service)
+                .with_graceful_shutdown(shutdown.requested())
+                .map_err(GenericError::from)
+                .boxed()
         }
-    });
+        _ => {
+            let new_service = make_service_fn(move |conn: &AddrStream| {
+                let ctx = ctx.clone();
+                let remote_ip = Some(conn.remote_addr().ip());
+                async move {
+                    Ok::<_, GenericError>(service_fn(move |req| {
+                        handle_request(ctx.clone(), remote_ip, req)
+                    }))
+                }
+            });
+            Server::bind(&addr)
+                .serve(new_service)
+                .with_graceful_shutdown(shutdown.requested())
+                .map_err(GenericError::from)
+                .boxed()
+        }
+    };
 
-    let server = Server::bind(&addr).serve(new_service);
+    println!("Listening on {}://{}", network_url.scheme(), addr);
 
-    println!("Listening on http://{}", addr);
+    let serve = async {
+        // The servers only return once shutdown has been requested and they have stopped
+        // accepting connections, and the HTTP servers have finished their in-flight requests
+        future::try_join4(server, ws_server, ipc_server, grpc_server).await?;
+        shutdown.drained().await;
+        Ok::<_, GenericError>(())
+    };
+    let shutdown_timeout = Duration::from_secs(full_config.rpc.shutdown_timeout_secs);
+    let deadline = shutdown
+        .requested()
+        .then(|_| tokio::time::delay_for(shutdown_timeout));
+    match future::select(serve.boxed(), deadline.boxed()).await {
+        future::Either::Left((result, _)) => result?,
+        future::Either::Right(_) => warn!(
+            "In-flight requests haven't finished within {} seconds. Shutting down anyway.",
+            full_config.rpc.shutdown_timeout_secs
+        ),
+    }
 
-    server.await?;
+    sync_state.close().await.map_err(|err| err.compat())?;
 
     Ok(())
 }
 
-async fn http_get_health() -> Result<Response<Body>, GenericError> {
-    let response = Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "application/json")
-        .body(Body::from(r#"{"status": "healthy"}"#))?;
+/// Handle a HTTP request from `remote_ip`.
+async fn handle_request(
+    ctx: RpcContext,
+    remote_ip: Option<IpAddr>,
+    req: Request<Body>,
+) -> Result<Response<Body>, GenericError> {
+    let allowed_origin = ctx.cors.allowed_origin(req.headers());
+    // Preflight requests never carry an API key, so they are answered before authentication
+    let mut response = if req.method() == Method::OPTIONS {
+        ctx.cors.preflight_response(allowed_origin.is_some())?
+    } else {
+        limit_request(&ctx, remote_ip, req).await?
+    };
+    ctx.cors.add_headers(allowed_origin, &mut response);
     Ok(response)
 }
 
+/// Apply the rate limit and `max_in_flight_requests` of `[rpc.limits]` to a request, before
+/// dispatching it.
+async fn limit_request(
+    ctx: &RpcContext,
+    remote_ip: Option<IpAddr>,
+    req: Request<Body>,
+) -> Result<Response<Body>, GenericError> {
+    // Only the GET endpoints are exempt, as other methods on their paths are handled as JSON-RPC
+    if req.method() == Method::GET && UNAUTHENTICATED_PATHS.contains(&req.uri().path()) {
+        return route_request(ctx, req).await;
+    }
+
+    // Requests with a valid API key are accounted to it, so that clients behind the same proxy
+    // don't share their rate limit
+    let api_key = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|authorization| ctx.auth.known_api_key(authorization));
+    let client = match (api_key, remote_ip) {
+        (Some(api_key), _) => Some(Client::ApiKey(api_key)),
+        (None, Some(remote_ip)) => Some(Client::Ip(remote_ip)),
+        (None, None) => None,
+    };
+    if let Some(client) = client {
+        if !ctx.limits.check_rate_limit(client) {
+            return limits::rate_limited_response();
+        }
+    }
+
+    let _slot = match ctx.limits.acquire_slot().await {
+        Some(slot) => slot,
+        None => return limits::overloaded_response(),
+    };
+    route_request(ctx, req).await
+}
+
+/// Dispatch a HTTP request to the handler for its path.
+fn route_request(
+    ctx: &RpcContext,
+    req: Request<Body>,
+) -> future::BoxFuture<'static, Result<Response<Body>, GenericError>> {
+    if req.method() == Method::GET {
+        match req.uri().path() {
+            "/health" => return health::http_get_health().boxed(),
+            "/health/ready" => return health::http_get_ready(ctx.clone()).boxed(),
+            "/metrics" => return metrics::http_get_metrics().boxed(),
+            _ => {}
+        }
+    }
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let permissions = match ctx.auth.authenticate(authorization) {
+        Some(permissions) => permissions,
+        None => return auth::unauthorized_response().boxed(),
+    };
+
+    match req.uri().path() {
+        "/graphql" => {
+            let method = graphql::method_name(req.method());
+            let call = graphql::handle_graphql(ctx.clone(), permissions, req);
+            metrics::observe_http_call("graphql", method, call).boxed()
+        }
+        path if rest::is_rest_path(path) => {
+            let method = rest::method_name(req.method(), path);
+            let call = rest::handle_rest(ctx.clone(), permissions, req);
+            metrics::observe_http_call("rest", method, call).boxed()
+        }
+        _ => handle_jsonrpc(ctx.clone(), permissions, req).boxed(),
+    }
+}
+
 pub fn run_rpc_with_tokio(full_config: &Config) -> Result<(), GenericError> {
     let mut rt = Runtime::new().unwrap();
     rt.block_on(run_rpc(full_config))
 }
 
 async fn handle_jsonrpc(
-    full_config: Config,
-    sync_state: SyncState,
+    ctx: RpcContext,
+    permissions: Arc<Permissions>,
     req: Request<Body>,
 ) -> Result<Response<Body>, GenericError> {
-    let config = full_config.clone();
-    let body: Vec<u8> = hyper::body::to_bytes(req).await?.to_vec();
-    let body_value: Value = serde_json::from_slice(&body).unwrap();
+    let body = match ctx.limits.read_body(req.into_body()).await? {
+        Some(body) => body,
+        None => return limits::payload_too_large_response(),
+    };
 
-    let id = body_value.as_object().unwrap()["id"].clone();
-    let method = body_value.as_object().unwrap()["method"].as_str().unwrap();
-    let params = body_value.as_object().unwrap()["params"]
-        .as_array()
-        .unwrap();
+    let response = match jsonrpc::handle_payload(&ctx, None, &permissions, &body).await {
+        Some(json) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_string(&json)?))?,
+        // Only notifications have been received, so there is nothing to respond with.
+        None => Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())?,
+    };
+    Ok(response)
+}
 
-    let internal_result = match method {
-        "rlay_version" => Some(rpc_rlay_version(full_config).await?),
-        "rlay_experimentalStoreEntity" => Some(
-            rpc_rlay_experimental_store_entity(full_config, sync_state, params.to_owned()).await?,
-        ),
-        "rlay_experimentalStoreEntities" => Some(
-            rpc_rlay_experimental_store_entities(full_config, sync_state, params.to_owned())
-                .await?,
-        ),
-        "rlay_experimentalGetEntity" => Some(
-            rpc_rlay_experimental_get_entity(full_config, sync_state, params.to_owned()).await?,
-        ),
-        "rlay_experimentalGetEntities" => Some(
-            rpc_rlay_experimental_get_entities(full_config, sync_state, params.to_owned()).await?,
-        ),
-        "rlay_experimentalResolveEntity" => Some(
-            rpc_rlay_experimental_resolve_entity(full_config, sync_state, params.to_owned())
-                .await?,
-        ),
-        "rlay_experimentalResolveEntities" => Some(
-            rpc_rlay_experimental_resolve_entities(full_config, sync_state, params.to_owned())
-                .await?,
-        ),
-        "rlay_experimentalNeo4jQuery" => Some(
-            rpc_rlay_experimental_neo4j_query(full_config, sync_state, params.to_owned()).await?,
-        ),
-        "rlay_experimentalListCids" => {
-            Some(rpc_rlay_experimental_list_cids(full_config, sync_state, params.to_owned()).await?)
+/// Call the RPC method `method` that is implemented by the client.
+///
+/// `connection` is the connection the call has been received on, if it supports pushing
+/// messages to the client. Returns `None` if there is no such method, in which case the call may
+/// be proxied.
+async fn call_method(
+    ctx: &RpcContext,
+    connection: Option<&Connection>,
+    method: &str,
+    params: Vec<Value>,
+) -> Option<JsonRpcResult<Value>> {
+    let full_config = ctx.config.clone();
+    let sync_state = ctx.sync_state.clone();
+    let result = match method {
+        "rlay_version" => rpc_rlay_version(full_config).await,
+        "rlay_experimentalStoreEntity" => {
+            rpc_rlay_experimental_store_entity(full_config, sync_state, &ctx.subscriptions, params)
+                .await
+        }
+        "rlay_experimentalStoreEntities" => {
+            rpc_rlay_experimental_store_entities(
+                full_config,
+                sync_state,
+                &ctx.subscriptions,
+                params,
+            )
+            .await
+        }
+        "rlay_experimentalGetEntity" => {
+            rpc_rlay_experimental_get_entity(full_config, sync_state, params).await
+        }
+        "rlay_experimentalGetEntities" => {
+            rpc_rlay_experimental_get_entities(full_config, sync_state, params).await
+        }
+        "rlay_experimentalResolveEntity" => {
+            rpc_rlay_experimental_resolve_entity(
+                full_config,
+                sync_state,
+                ctx.plugins.current(),
+                params,
+            )
+            .await
         }
-        "rlay_experimentalGetEntityCid" => {
-            Some(rpc_rlay_experimental_get_entity_cid(params.to_owned()).await?)
+        "rlay_experimentalResolveEntities" => {
+            rpc_rlay_experimental_resolve_entities(
+                full_config,
+                sync_state,
+                ctx.plugins.current(),
+                params,
+            )
+            .await
         }
-        _ => None,
+        "rlay_experimentalNeo4jQuery" => {
+            rpc_rlay_experimental_neo4j_query(
+                full_config,
+                sync_state,
+                ctx.plugins.current(),
+                params,
+            )
+            .await
+        }
+        "rlay_experimentalListCids" => {
+            rpc_rlay_experimental_list_cids(full_config, sync_state, params).await
+        }
+        "rlay_experimentalGetEntityCid" => rpc_rlay_experimental_get_entity_cid(params).await,
+        "rlay_subscribe" => rpc_rlay_subscribe(&ctx.subscriptions, connection, params).await,
+        "rlay_unsubscribe" => rpc_rlay_unsubscribe(&ctx.subscriptions, connection, params).await,
+        "rlay_reloadPlugins" => rpc_rlay_reload_plugins(&ctx.plugins).await,
+        "rpc.discover" => discover::rpc_discover(full_config, sync_state).await,
+        _ => return None,
     };
+    Some(result)
+}
 
-    let json = match internal_result {
-        Some(internal_res) => {
-            let json = json!({ "id": id, "jsonrpc": "2.0", "result": internal_res });
-            json
-        }
-        None => match config.rpc.proxy_target_network_address {
-            None => {
-                let mut err = jsonrpc_core::Error::internal_error();
-                err.message = format!("Method not found: {}", method);
-                Result::Err(err)?
-            }
-            Some(proxy_target) => proxy_rpc_call(proxy_target, body_value).await?,
-        },
+/// Get the mandatory parameter at position `pos`.
+fn mandatory_param<'a>(
+    params_array: &'a [Value],
+    pos: usize,
+    name: &str,
+) -> JsonRpcResult<&'a Value> {
+    params_array.get(pos).ok_or_else(|| {
+        jsonrpc_core::Error::invalid_params(format!("Mandatory parameter '{}' missing", name))
+    })
+}
+
+fn string_param(params_array: &[Value], pos: usize, name: &str) -> JsonRpcResult<String> {
+    mandatory_param(params_array, pos, name)?
+        .as_str()
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| {
+            jsonrpc_core::Error::invalid_params(format!("Parameter '{}' must be a string", name))
+        })
+}
+
+fn string_array_param(
+    params_array: &[Value],
+    pos: usize,
+    name: &str,
+) -> JsonRpcResult<Vec<String>> {
+    let invalid = || {
+        jsonrpc_core::Error::invalid_params(format!(
+            "Parameter '{}' must be an array of strings",
+            name
+        ))
     };
+    mandatory_param(params_array, pos, name)?
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|value| value.as_str().map(ToOwned::to_owned).ok_or_else(invalid))
+        .collect()
+}
 
-    let json_str = serde_json::to_string(&json)?;
-    let response = Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "application/json")
-        .body(Body::from(json_str))?;
-    Ok(response)
+fn entity_from_value(entity_object: &Value) -> JsonRpcResult<Entity> {
+    let web3_entity: FormatWeb3<Entity> = serde_json::from_value(entity_object.clone())
+        .map_err(|err| jsonrpc_core::Error::invalid_params(err.to_string()))?;
+    Ok(web3_entity.0)
 }
 
 /// `rlay_version` RPC call.
@@ -196,16 +480,10 @@ async fn rpc_rlay_version(_config: Config) -> JsonRpcResult<Value> {
 async fn rpc_rlay_experimental_store_entity(
     config: Config,
     sync_state: SyncState,
+    subscriptions: &Subscriptions,
     params_array: Vec<Value>,
 ) -> JsonRpcResult<Value> {
-    let entity_object = params_array
-        .get(0)
-        .ok_or(jsonrpc_core::Error::invalid_params(
-            "Mandatory parameter 'entity' missing",
-        ))?;
-    let web3_entity: FormatWeb3<Entity> = serde_json::from_value(entity_object.clone())
-        .map_err(|err| jsonrpc_core::Error::invalid_params(err.description()))?;
-    let entity: Entity = web3_entity.0;
+    let entity = entity_from_value(mandatory_param(&params_array, 0, "entity")?)?;
 
     let options_object = extract_options_object(&params_array, 1);
     let mut backend = get_backend(&config, &sync_state).await?;
@@ -216,8 +494,8 @@ async fn rpc_rlay_experimental_store_entity(
             let cid: String = format!("0x{}", raw_cid.to_bytes().to_hex());
             serde_json::to_value(cid).unwrap()
         })
-        .await
-        .unwrap();
+        .await?;
+    subscriptions.publish(&[entity]);
 
     Ok(cid)
 }
@@ -225,26 +503,20 @@ async fn rpc_rlay_experimental_store_entity(
 async fn rpc_rlay_experimental_store_entities(
     config: Config,
     sync_state: SyncState,
+    subscriptions: &Subscriptions,
     params_array: Vec<Value>,
 ) -> JsonRpcResult<Value> {
-    let entity_objects = params_array
-        .get(0)
-        .ok_or(jsonrpc_core::Error::invalid_params(
-            "Mandatory parameter 'entities' missing",
-        ))
-        .unwrap()
+    let entity_objects = mandatory_param(&params_array, 0, "entities")?
         .as_array()
-        .unwrap();
+        .ok_or_else(|| {
+            jsonrpc_core::Error::invalid_params("Parameter 'entities' must be an array")
+        })?;
+    limits::check_entity_count(&config, entity_objects.len())?;
 
     let entities: Vec<Entity> = entity_objects
         .iter()
-        .map(|entity_object| {
-            let web3_entities: FormatWeb3<Entity> = serde_json::from_value(entity_object.clone())
-                .map_err(|err| jsonrpc_core::Error::invalid_params(err.description()))
-                .unwrap();
-            return web3_entities.0;
-        })
-        .collect();
+        .map(entity_from_value)
+        .collect::<JsonRpcResult<_>>()?;
 
     let options_object = extract_options_object(&params_array, 1);
     let mut backend = get_backend(&config, &sync_state).await?;
@@ -261,6 +533,7 @@ async fn rpc_rlay_experimental_store_entities(
                 .collect();
         })
         .await?;
+    subscriptions.publish(&entities);
 
     Ok(cids)
 }
@@ -270,7 +543,7 @@ async fn rpc_rlay_experimental_get_entity(
     sync_state: SyncState,
     params_array: Vec<Value>,
 ) -> JsonRpcResult<Value> {
-    let cid = params_array.get(0).unwrap().as_str().unwrap().to_owned();
+    let cid = string_param(&params_array, 0, "cid")?;
 
     let mut backend = get_backend(&config, &sync_state).await?;
 
@@ -280,8 +553,7 @@ async fn rpc_rlay_experimental_get_entity(
             debug!("retrieved {:?}", entity.is_some());
             serde_json::to_value(entity.map(|n| FormatWeb3(n))).unwrap()
         })
-        .await
-        .unwrap();
+        .await?;
 
     Ok(entity)
 }
@@ -291,14 +563,8 @@ async fn rpc_rlay_experimental_get_entities(
     sync_state: SyncState,
     params_array: Vec<Value>,
 ) -> JsonRpcResult<Value> {
-    let cid_array = params_array.get(0).unwrap().as_array().unwrap().to_owned();
-
-    let cids: Vec<String> = cid_array
-        .iter()
-        .map(|cid_value| {
-            return cid_value.as_str().unwrap().to_owned();
-        })
-        .collect();
+    let cids = string_array_param(&params_array, 0, "cids")?;
+    limits::check_entity_count(&config, cids.len())?;
 
     let mut backend = get_backend(&config, &sync_state).await?;
 
@@ -310,8 +576,7 @@ async fn rpc_rlay_experimental_get_entities(
                 .map(|raw_entity| serde_json::to_value(FormatWeb3(raw_entity)).unwrap())
                 .collect();
         })
-        .await
-        .unwrap();
+        .await?;
 
     Ok(result)
 }
@@ -324,21 +589,22 @@ struct FilterArgument {
 }
 
 impl FilterArgument {
-    pub fn from_options_object(options_object: Option<&Value>) -> Vec<Self> {
+    pub fn from_options_object(options_object: Option<&Value>) -> JsonRpcResult<Vec<Self>> {
         options_object
             .and_then(|n| n.as_object())
             .and_then(|n| n.get("filters"))
-            .and_then(|n| {
-                n.as_array().and_then(|filters_arr| {
-                    Some(
-                        filters_arr
-                            .into_iter()
-                            .map(|n| serde_json::from_value(n.clone()).unwrap())
-                            .collect::<Vec<_>>(),
-                    )
-                })
+            .and_then(|n| n.as_array())
+            .map(|filters_arr| {
+                filters_arr
+                    .iter()
+                    .map(|n| {
+                        serde_json::from_value(n.clone()).map_err(|err| {
+                            jsonrpc_core::Error::invalid_params(format!("Invalid filter: {}", err))
+                        })
+                    })
+                    .collect::<JsonRpcResult<Vec<_>>>()
             })
-            .unwrap_or_else(Vec::new)
+            .unwrap_or_else(|| Ok(Vec::new()))
     }
 }
 
@@ -369,9 +635,11 @@ async fn filter_entities(
             params,
         };
 
+        let start = Instant::now();
         let filter_values = filter
             .filter_entities(filter_ctx, filtered_entities.clone())
             .await;
+        metrics::observe_filter_duration(filter.filter_name(), start.elapsed());
         filtered_entities = filtered_entities
             .into_iter()
             .zip(filter_values.iter())
@@ -386,21 +654,19 @@ async fn filter_entities(
 async fn rpc_rlay_experimental_resolve_entity(
     config: Config,
     sync_state: SyncState,
+    filter_registry: Arc<PluginRegistry>,
     params_array: Vec<Value>,
 ) -> JsonRpcResult<Value> {
-    let filter_registry = PluginRegistry::from_dir(config.clone().plugins_path);
-
-    let cid = params_array.get(0).unwrap().as_str().unwrap().to_owned();
+    let cid = string_param(&params_array, 0, "cid")?;
     let default_options = json!({});
     let options_object = params_array.get(1).or_else(|| Some(&default_options));
-    let activated_filters_arg = FilterArgument::from_options_object(options_object);
+    let activated_filters_arg = FilterArgument::from_options_object(options_object)?;
 
     let mut backend = get_backend(&config, &sync_state).await?;
 
     let resolved_entities = BackendRpcMethods::resolve_entity(&mut backend, &cid)
         .map_err(failure_into_jsonrpc_err)
-        .await
-        .unwrap();
+        .await?;
 
     let mut serde_map: Map<String, Value> = Map::new();
     for (cid, raw_entities) in resolved_entities {
@@ -428,28 +694,20 @@ async fn rpc_rlay_experimental_resolve_entity(
 async fn rpc_rlay_experimental_resolve_entities(
     config: Config,
     sync_state: SyncState,
+    filter_registry: Arc<PluginRegistry>,
     params_array: Vec<Value>,
 ) -> JsonRpcResult<Value> {
-    let filter_registry = PluginRegistry::from_dir(config.clone().plugins_path);
-
-    let cid_array = params_array.get(0).unwrap().as_array().unwrap().to_owned();
+    let cids = string_array_param(&params_array, 0, "cids")?;
+    limits::check_entity_count(&config, cids.len())?;
     let default_options = json!({});
     let options_object = params_array.get(1).or_else(|| Some(&default_options));
-    let activated_filters_arg = FilterArgument::from_options_object(options_object);
-
-    let cids: Vec<String> = cid_array
-        .iter()
-        .map(|cid_value| {
-            return cid_value.as_str().unwrap().to_owned();
-        })
-        .collect();
+    let activated_filters_arg = FilterArgument::from_options_object(options_object)?;
 
     let mut backend = get_backend(&config, &sync_state).await?;
 
     let resolved_entities = BackendRpcMethods::resolve_entities(&mut backend, cids)
         .map_err(failure_into_jsonrpc_err)
-        .await
-        .unwrap();
+        .await?;
 
     let mut serde_map: Map<String, Value> = Map::new();
     for (cid, raw_entities) in resolved_entities {
@@ -477,15 +735,14 @@ async fn rpc_rlay_experimental_resolve_entities(
 async fn rpc_rlay_experimental_neo4j_query(
     config: Config,
     sync_state: SyncState,
+    filter_registry: Arc<PluginRegistry>,
     params_array: Vec<Value>,
 ) -> JsonRpcResult<Value> {
-    let filter_registry = PluginRegistry::from_dir(config.clone().plugins_path);
-
-    let query = params_array.get(0).unwrap().as_str().unwrap().to_owned();
+    let query = string_param(&params_array, 0, "query")?;
 
     let default_options = json!({});
     let options_object = params_array.get(1).or_else(|| Some(&default_options));
-    let activated_filters_arg = FilterArgument::from_options_object(options_object);
+    let activated_filters_arg = FilterArgument::from_options_object(options_object)?;
 
     let config = config.clone();
     let sync_state = sync_state.clone();
@@ -494,14 +751,12 @@ async fn rpc_rlay_experimental_neo4j_query(
 
     let cids: Vec<String> = BackendRpcMethods::neo4j_query(&mut backend, &query)
         .map_err(failure_into_jsonrpc_err)
-        .await
-        .unwrap();
+        .await?;
 
     let entities = backend
         .get_entities(cids)
         .map_err(failure_into_jsonrpc_err)
-        .await
-        .unwrap();
+        .await?;
 
     let filtered_entities = filter_entities(
         backend.clone(),
@@ -525,26 +780,89 @@ async fn rpc_rlay_experimental_list_cids(
     sync_state: SyncState,
     params_array: Vec<Value>,
 ) -> JsonRpcResult<Value> {
-    let entity_kind: Option<String> = params_array.get(0).unwrap().as_str().map(|n| n.to_owned());
+    let entity_kind: Option<String> = match params_array.get(0) {
+        None | Some(Value::Null) => None,
+        Some(_) => Some(string_param(&params_array, 0, "entityKind")?),
+    };
 
     let mut backend = get_backend(&config, &sync_state).await?;
 
     let cids: Vec<String> =
         BackendRpcMethods::list_cids(&mut backend, entity_kind.as_ref().map(|n| &**n))
             .map_err(failure_into_jsonrpc_err)
-            .await
-            .unwrap();
+            .await?;
 
     Ok(serde_json::to_value(cids).unwrap())
 }
 
 async fn rpc_rlay_experimental_get_entity_cid(params_array: Vec<Value>) -> JsonRpcResult<Value> {
-    let entity_object = params_array.get(0).unwrap();
-    let web3_entity: FormatWeb3<Entity> = serde_json::from_value(entity_object.clone())
-        .map_err(|err| jsonrpc_core::Error::invalid_params(err.description()))
-        .unwrap();
-    let entity: Entity = web3_entity.0;
-    let cid: String = format!("0x{}", entity.to_cid().unwrap().to_bytes().to_hex());
+    let entity = entity_from_value(mandatory_param(&params_array, 0, "entity")?)?;
+    let raw_cid = entity
+        .to_cid()
+        .map_err(|_| jsonrpc_core::Error::invalid_params("Unable to calculate CID of entity"))?;
+    let cid: String = format!("0x{}", raw_cid.to_bytes().to_hex());
 
     Ok(serde_json::to_value(cid).unwrap())
 }
+
+fn push_connection(connection: Option<&Connection>) -> JsonRpcResult<&Connection> {
+    connection.ok_or_else(|| {
+        let mut err = jsonrpc_core::Error::method_not_found();
+        err.message = "Subscriptions are not supported on this transport".to_owned();
+        err
+    })
+}
+
+/// `rlay_subscribe` RPC call.
+///
+/// Subscribe to entities as they are stored via `rlay_experimentalStoreEntity` and
+/// `rlay_experimentalStoreEntities`, which are pushed as `rlay_subscription` notifications.
+/// The only supported subscription is `newEntities`, with the options `kind` and `referencing`.
+async fn rpc_rlay_subscribe(
+    subscriptions: &Subscriptions,
+    connection: Option<&Connection>,
+    params_array: Vec<Value>,
+) -> JsonRpcResult<Value> {
+    let connection = push_connection(connection)?;
+
+    let subscription_type = string_param(&params_array, 0, "subscriptionType")?;
+    if subscription_type != "newEntities" {
+        return Err(jsonrpc_core::Error::invalid_params(format!(
+            "Unknown subscription type: {}",
+            subscription_type
+        )));
+    }
+    let filter = NewEntitiesFilter::from_options_object(params_array.get(1))?;
+
+    let subscription_id = subscriptions.subscribe(connection, filter);
+    Ok(serde_json::to_value(subscription_id).unwrap())
+}
+
+/// `rlay_unsubscribe` RPC call.
+///
+/// Cancel a subscription of the same connection, returning whether it existed.
+async fn rpc_rlay_unsubscribe(
+    subscriptions: &Subscriptions,
+    connection: Option<&Connection>,
+    params_array: Vec<Value>,
+) -> JsonRpcResult<Value> {
+    let connection = push_connection(connection)?;
+
+    let subscription_id = string_param(&params_array, 0, "subscriptionId")?;
+    Ok(Value::Bool(
+        subscriptions.unsubscribe(connection, &subscription_id),
+    ))
+}
+
+/// `rlay_reloadPlugins` RPC call.
+///
+/// Load the plugins of the plugins directory again, returning the names of the loaded filters.
+async fn rpc_rlay_reload_plugins(plugins: &SharedPluginRegistry) -> JsonRpcResult<Value> {
+    let plugins = plugins.clone();
+    let filter_registry = tokio::task::spawn_blocking(move || plugins.reload())
+        .await
+        .map_err(|_| failure_into_jsonrpc_err(format_err!("Reloading plugins failed")))?
+        .map_err(failure_into_jsonrpc_err)?;
+
+    Ok(serde_json::to_value(filter_registry.filter_names()).unwrap())
+}
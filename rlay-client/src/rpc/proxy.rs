@@ -1,7 +1,7 @@
 use hyper::{header, Body, Client, Request};
 use serde_json::Value;
 
-use super::JsonRpcResult;
+use super::{failure_into_jsonrpc_err, JsonRpcResult};
 
 pub async fn proxy_rpc_call(target_url: String, request_body: Value) -> JsonRpcResult<Value> {
     let client = Client::new();
@@ -12,9 +12,15 @@ pub async fn proxy_rpc_call(target_url: String, request_body: Value) -> JsonRpcR
         .body(Body::from(request_body.to_string()))
         .expect("request builder");
 
-    let res = client.request(req).await.unwrap();
-    let body = hyper::body::to_bytes(res).await.unwrap();
-    let value: Value = serde_json::from_slice(&body).unwrap();
+    let res = client
+        .request(req)
+        .await
+        .map_err(|err| failure_into_jsonrpc_err(err.into()))?;
+    let body = hyper::body::to_bytes(res)
+        .await
+        .map_err(|err| failure_into_jsonrpc_err(err.into()))?;
+    let value: Value =
+        serde_json::from_slice(&body).map_err(|err| failure_into_jsonrpc_err(err.into()))?;
 
     Ok(value)
 }
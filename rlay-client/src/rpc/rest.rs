@@ -0,0 +1,263 @@
+//! Resource-oriented HTTP API, backed by the same calls as the JSON-RPC methods.
+//!
+//! - `GET /entities/{cid}`: Retrieve an entity
+//! - `GET /entities/{cid}/resolve`: Resolve an entity (see `rlay_experimentalResolveEntity`)
+//! - `GET /entities?kind={kind}`: List the CIDs of all entities (of a kind)
+//! - `POST /entities`: Store an entity (or an array of entities)
+//! - `GET /cid?entity={entity}` or `POST /cid`: Calculate the CID of an entity
+//!
+//! Every resource requires the API key to be allowed to call the JSON-RPC method it corresponds
+//! to.
+//!
+//! As CIDs are content addresses, responses that are determined by a CID alone never change and
+//! are marked as immutable, so that they can be cached by any HTTP cache.
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::auth::Permissions;
+use super::limits;
+use super::{
+    rpc_rlay_experimental_get_entity, rpc_rlay_experimental_get_entity_cid,
+    rpc_rlay_experimental_list_cids, rpc_rlay_experimental_resolve_entity,
+    rpc_rlay_experimental_store_entities, rpc_rlay_experimental_store_entity, GenericError,
+    JsonRpcResult, RpcContext,
+};
+
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Whether the request path belongs to the REST API.
+pub fn is_rest_path(path: &str) -> bool {
+    path == "/entities" || path.starts_with("/entities/") || path == "/cid"
+}
+
+/// Name of the resource and HTTP method of a request, under which it is recorded in the metrics.
+pub fn method_name(method: &Method, path: &str) -> &'static str {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        (&Method::GET, ["entities"]) => "GET /entities",
+        (&Method::POST, ["entities"]) => "POST /entities",
+        (&Method::GET, ["entities", _]) => "GET /entities/{cid}",
+        (&Method::GET, ["entities", _, "resolve"]) => "GET /entities/{cid}/resolve",
+        (&Method::GET, ["cid"]) => "GET /cid",
+        (&Method::POST, ["cid"]) => "POST /cid",
+        _ => "other",
+    }
+}
+
+pub async fn handle_rest(
+    ctx: RpcContext,
+    permissions: Arc<Permissions>,
+    req: Request<Body>,
+) -> Result<Response<Body>, GenericError> {
+    let path = req.uri().path().to_owned();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let query = req.uri().query().unwrap_or("").to_owned();
+
+    match (req.method().clone(), segments.as_slice()) {
+        (Method::GET, ["entities"]) => {
+            if !permissions.is_allowed("rlay_experimentalListCids") {
+                return forbidden_response();
+            }
+            let kind = query_param(&query, "kind").map_or(Value::Null, Value::String);
+            let result = rpc_rlay_experimental_list_cids(
+                ctx.config.clone(),
+                ctx.sync_state.clone(),
+                vec![kind],
+            )
+            .await;
+            json_response(result, StatusCode::OK, None)
+        }
+        (Method::POST, ["entities"]) => {
+            let body = match ctx.limits.read_body(req.into_body()).await? {
+                Some(body) => body,
+                None => return limits::payload_too_large_response(),
+            };
+            let entity_value: Value = match serde_json::from_slice(&body) {
+                Ok(entity_value) => entity_value,
+                Err(err) => return error_response(StatusCode::BAD_REQUEST, &err.to_string()),
+            };
+            let rpc_method = match entity_value {
+                Value::Array(_) => "rlay_experimentalStoreEntities",
+                _ => "rlay_experimentalStoreEntity",
+            };
+            if !permissions.is_allowed(rpc_method) {
+                return forbidden_response();
+            }
+            let result = match entity_value {
+                Value::Array(_) => {
+                    rpc_rlay_experimental_store_entities(
+                        ctx.config.clone(),
+                        ctx.sync_state.clone(),
+                        &ctx.subscriptions,
+                        vec![entity_value],
+                    )
+                    .await
+                }
+                _ => {
+                    rpc_rlay_experimental_store_entity(
+                        ctx.config.clone(),
+                        ctx.sync_state.clone(),
+                        &ctx.subscriptions,
+                        vec![entity_value],
+                    )
+                    .await
+                }
+            };
+            json_response(result, StatusCode::CREATED, None)
+        }
+        (Method::GET, ["entities", cid]) => {
+            if !permissions.is_allowed("rlay_experimentalGetEntity") {
+                return forbidden_response();
+            }
+            // entities are immutable, so a client that has the entity has its current version
+            if is_not_modified(&req, cid, false) {
+                return not_modified_response(cid);
+            }
+            let result = rpc_rlay_experimental_get_entity(
+                ctx.config.clone(),
+                ctx.sync_state.clone(),
+                vec![Value::String(cid.to_string())],
+            )
+            .await;
+            match result {
+                Ok(Value::Null) => error_response(StatusCode::NOT_FOUND, "Entity not found"),
+                Ok(_) if is_not_modified(&req, cid, true) => not_modified_response(cid),
+                result => json_response(result, StatusCode::OK, Some(*cid)),
+            }
+        }
+        (Method::GET, ["entities", cid, "resolve"]) => {
+            if !permissions.is_allowed("rlay_experimentalResolveEntity") {
+                return forbidden_response();
+            }
+            let result = rpc_rlay_experimental_resolve_entity(
+                ctx.config.clone(),
+                ctx.sync_state.clone(),
+                ctx.plugins.current(),
+                vec![Value::String(cid.to_string())],
+            )
+            .await;
+            json_response(result, StatusCode::OK, None)
+        }
+        (Method::GET, ["cid"]) => {
+            if !permissions.is_allowed("rlay_experimentalGetEntityCid") {
+                return forbidden_response();
+            }
+            let entity_value: Value = match query_param(&query, "entity")
+                .map(|entity| serde_json::from_str(&entity))
+            {
+                Some(Ok(entity_value)) => entity_value,
+                Some(Err(err)) => return error_response(StatusCode::BAD_REQUEST, &err.to_string()),
+                None => {
+                    return error_response(
+                        StatusCode::BAD_REQUEST,
+                        "Missing query parameter 'entity'",
+                    )
+                }
+            };
+            let result = rpc_rlay_experimental_get_entity_cid(vec![entity_value]).await;
+            // The CID only depends on the query, so the response never changes
+            let mut response = json_response(result, StatusCode::OK, None)?;
+            if response.status() == StatusCode::OK {
+                response.headers_mut().insert(
+                    header::CACHE_CONTROL,
+                    header::HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+                );
+            }
+            Ok(response)
+        }
+        (Method::POST, ["cid"]) => {
+            if !permissions.is_allowed("rlay_experimentalGetEntityCid") {
+                return forbidden_response();
+            }
+            let body = match ctx.limits.read_body(req.into_body()).await? {
+                Some(body) => body,
+                None => return limits::payload_too_large_response(),
+            };
+            let entity_value: Value = match serde_json::from_slice(&body) {
+                Ok(entity_value) => entity_value,
+                Err(err) => return error_response(StatusCode::BAD_REQUEST, &err.to_string()),
+            };
+            let result = rpc_rlay_experimental_get_entity_cid(vec![entity_value]).await;
+            json_response(result, StatusCode::OK, None)
+        }
+        (_, ["entities"])
+        | (_, ["entities", _])
+        | (_, ["entities", _, "resolve"])
+        | (_, ["cid"]) => error_response(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"),
+        _ => error_response(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn etag(cid: &str) -> String {
+    format!("\"{}\"", cid)
+}
+
+/// Whether the client already has the current version of the resource identified by `cid`.
+///
+/// `If-None-Match: *` only matches if the resource `exists`.
+fn is_not_modified(req: &Request<Body>, cid: &str, exists: bool) -> bool {
+    let etag = etag(cid);
+    req.headers()
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim() == etag || (exists && value.trim() == "*"))
+}
+
+fn not_modified_response(cid: &str) -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(StatusCode::NOT_MODIFIED)
+        .header(header::ETAG, etag(cid))
+        .header(header::CACHE_CONTROL, IMMUTABLE_CACHE_CONTROL)
+        .body(Body::empty())?)
+}
+
+/// Build the response for the `result` of a RPC method.
+///
+/// If `immutable_cid` is provided, the response is marked as immutable with the CID as its ETag.
+fn json_response(
+    result: JsonRpcResult<Value>,
+    status: StatusCode,
+    immutable_cid: Option<&str>,
+) -> Result<Response<Body>, GenericError> {
+    let value = match result {
+        Ok(value) => value,
+        Err(err) => {
+            let status = match err.code {
+                jsonrpc_core::ErrorCode::InvalidParams
+                | jsonrpc_core::ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            return error_response(status, &err.message);
+        }
+    };
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(cid) = immutable_cid {
+        response = response
+            .header(header::ETAG, etag(cid))
+            .header(header::CACHE_CONTROL, IMMUTABLE_CACHE_CONTROL);
+    }
+    Ok(response.body(Body::from(serde_json::to_string(&value)?))?)
+}
+
+fn forbidden_response() -> Result<Response<Body>, GenericError> {
+    error_response(StatusCode::FORBIDDEN, "Not allowed for this API key")
+}
+
+fn error_response(status: StatusCode, message: &str) -> Result<Response<Body>, GenericError> {
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "error": message }).to_string()))?)
+}
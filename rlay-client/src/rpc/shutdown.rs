@@ -0,0 +1,89 @@
+//! Graceful shutdown of the RPC on SIGTERM or SIGINT.
+//!
+//! Once a signal has been received, all transports stop accepting connections (and calls on
+//! existing WebSocket and IPC connections), and in-flight JSON-RPC calls are given
+//! `rpc.shutdown_timeout_secs` to finish, before pending writes of the backend are flushed and its
+//! connections are closed.
+use futures::future::{self, Shared};
+use futures::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Future that resolves once shutdown has been requested.
+pub type ShutdownSignal = Shared<future::BoxFuture<'static, ()>>;
+
+#[derive(Clone)]
+pub struct Shutdown {
+    signal: ShutdownSignal,
+    in_flight: Arc<InFlightCalls>,
+}
+
+struct InFlightCalls {
+    count: AtomicUsize,
+    drained: Notify,
+}
+
+impl Shutdown {
+    /// Request shutdown on SIGTERM or SIGINT (Ctrl+C).
+    pub fn on_signals() -> Self {
+        Self {
+            signal: wait_for_signal().boxed().shared(),
+            in_flight: Arc::new(InFlightCalls {
+                count: AtomicUsize::new(0),
+                drained: Notify::new(),
+            }),
+        }
+    }
+
+    pub fn requested(&self) -> ShutdownSignal {
+        self.signal.clone()
+    }
+
+    /// Count a call as in-flight for as long as the returned guard is alive.
+    pub fn track_call(&self) -> CallGuard {
+        self.in_flight.count.fetch_add(1, Ordering::SeqCst);
+        CallGuard {
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    /// Wait until there are no more in-flight calls.
+    pub async fn drained(&self) {
+        while self.in_flight.count.load(Ordering::SeqCst) > 0 {
+            // `Notify` keeps a notification that happens before waiting, so none can be missed
+            self.in_flight.drained.notified().await;
+        }
+    }
+}
+
+/// Guard of an in-flight call, see `Shutdown::track_call`.
+pub struct CallGuard {
+    in_flight: Arc<InFlightCalls>,
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        if self.in_flight.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.in_flight.drained.notify();
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM");
+    let interrupt = tokio::signal::ctrl_c().map(|_| ()).boxed();
+    future::select(terminate.recv().boxed(), interrupt).await;
+    println!("Shutting down");
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Unable to listen for Ctrl+C");
+    println!("Shutting down");
+}
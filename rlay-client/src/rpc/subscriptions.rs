@@ -0,0 +1,187 @@
+//! Subscriptions to entities stored via the RPC, which are pushed to clients on connections that
+//! support it (e.g. WebSocket).
+use futures::channel::mpsc;
+use rlay_backend::relationships::entity_relationships;
+use rlay_backend::rpc::validate_entity_kind;
+use rlay_ontology::prelude::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::JsonRpcResult;
+
+/// Number of messages that may be waiting to be sent on a connection before it is closed.
+const CONNECTION_BUFFER_SIZE: usize = 256;
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A client connection that messages can be pushed to outside of responses.
+#[derive(Clone)]
+pub struct Connection {
+    id: u64,
+    sender: Arc<Mutex<mpsc::Sender<Value>>>,
+}
+
+impl Connection {
+    /// Create a connection, whose messages have to be sent to the client from the returned
+    /// receiver.
+    pub fn new() -> (Self, mpsc::Receiver<Value>) {
+        let (sender, receiver) = mpsc::channel(CONNECTION_BUFFER_SIZE);
+        let connection = Self {
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::SeqCst),
+            sender: Arc::new(Mutex::new(sender)),
+        };
+        (connection, receiver)
+    }
+
+    /// Send `message` to the client.
+    ///
+    /// The connection may have been closed in the meantime, in which case the message is dropped.
+    /// A client that doesn't keep up with its messages (e.g. notifications of a subscription to
+    /// all entities) is disconnected, instead of buffering them without bound.
+    pub fn send(&self, message: Value) {
+        let mut sender = self.sender.lock().unwrap();
+        if let Err(err) = sender.try_send(message) {
+            if err.is_full() {
+                debug!("Closing connection {}: Too many pending messages", self.id);
+                sender.close_channel();
+            }
+        }
+    }
+
+    /// Whether the connection has been closed, after which messages are dropped.
+    pub fn is_closed(&self) -> bool {
+        self.sender.lock().unwrap().is_closed()
+    }
+}
+
+/// Options of a `newEntities` subscription, restricting which entities are pushed.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct NewEntitiesFilter {
+    /// Only push entities of this kind.
+    kind: Option<String>,
+    /// Only push entities that reference this CID in one of their fields.
+    referencing: Option<String>,
+}
+
+impl NewEntitiesFilter {
+    pub fn from_options_object(options_object: Option<&Value>) -> JsonRpcResult<Self> {
+        let filter: Self = match options_object {
+            None | Some(Value::Null) => Self::default(),
+            Some(options_object) => serde_json::from_value(options_object.clone())
+                .map_err(|err| jsonrpc_core::Error::invalid_params(err.to_string()))?,
+        };
+        if let Some(kind) = filter.kind.as_ref() {
+            validate_entity_kind(kind)
+                .map_err(|err| jsonrpc_core::Error::invalid_params(err.to_string()))?;
+        }
+        Ok(filter)
+    }
+
+    fn matches(&self, entity: &Entity) -> bool {
+        if let Some(kind) = self.kind.as_ref() {
+            let kind_name: &str = entity.kind().into();
+            if kind_name != kind {
+                return false;
+            }
+        }
+        if let Some(referencing) = self.referencing.as_ref() {
+            return entity_relationships(entity)
+                .iter()
+                .any(|(_, cid)| cid.eq_ignore_ascii_case(referencing));
+        }
+        true
+    }
+}
+
+struct Subscription {
+    connection: Connection,
+    filter: NewEntitiesFilter,
+}
+
+#[derive(Default)]
+struct SubscriptionsInner {
+    next_id: u64,
+    subscriptions: HashMap<String, Subscription>,
+}
+
+/// All active subscriptions, shared between all connections.
+#[derive(Clone, Default)]
+pub struct Subscriptions {
+    inner: Arc<Mutex<SubscriptionsInner>>,
+}
+
+impl Subscriptions {
+    /// Subscribe `connection` to newly stored entities matching `filter`, returning the ID of
+    /// the subscription.
+    pub fn subscribe(&self, connection: &Connection, filter: NewEntitiesFilter) -> String {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = format!("0x{:x}", inner.next_id);
+        inner.subscriptions.insert(
+            id.clone(),
+            Subscription {
+                connection: connection.clone(),
+                filter,
+            },
+        );
+        id
+    }
+
+    /// Remove the subscription `id` of `connection`, returning whether it existed.
+    pub fn unsubscribe(&self, connection: &Connection, id: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let is_own = inner
+            .subscriptions
+            .get(id)
+            .map(|subscription| subscription.connection.id == connection.id)
+            .unwrap_or(false);
+        if is_own {
+            inner.subscriptions.remove(id);
+        }
+        is_own
+    }
+
+    /// Remove all subscriptions of `connection`, e.g. because it has been closed.
+    pub fn unsubscribe_connection(&self, connection: &Connection) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .subscriptions
+            .retain(|_, subscription| subscription.connection.id != connection.id);
+    }
+
+    /// Push `entities`, which have just been stored, to all matching subscriptions.
+    pub fn publish(&self, entities: &[Entity]) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.subscriptions.is_empty() {
+            return;
+        }
+
+        let web3_entities: Vec<Value> = entities
+            .iter()
+            .map(|entity| serde_json::to_value(FormatWeb3(entity)).unwrap())
+            .collect();
+        inner.subscriptions.retain(|id, subscription| {
+            for (entity, web3_entity) in entities.iter().zip(web3_entities.iter()) {
+                if !subscription.filter.matches(entity) {
+                    continue;
+                }
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "rlay_subscription",
+                    "params": {
+                        "subscription": id,
+                        "result": web3_entity,
+                    },
+                });
+                subscription.connection.send(notification);
+                // The connection has been closed, so the subscription is dropped.
+                if subscription.connection.is_closed() {
+                    return false;
+                }
+            }
+            true
+        });
+    }
+}
@@ -0,0 +1,121 @@
+//! TLS termination for the HTTP RPC, used if `rpc.network_address` is a `https://` URL.
+use futures::channel::mpsc;
+use futures::prelude::*;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use tokio_rustls::rustls::{
+    AllowAnyAuthenticatedClient, Certificate, NoClientAuth, PrivateKey, RootCertStore, ServerConfig,
+};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use super::shutdown::ShutdownSignal;
+use super::GenericError;
+use crate::config::RpcConfig;
+
+/// Time a client has to complete the TLS handshake before the connection is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Pause after a failed accept, as the error (e.g. too many open files) is likely to persist.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+fn open(path: &str) -> Result<BufReader<File>, GenericError> {
+    let file = File::open(path).map_err(|err| format!("Unable to open {}: {}", path, err))?;
+    Ok(BufReader::new(file))
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, GenericError> {
+    let certs =
+        certs(&mut open(path)?).map_err(|_| format!("Unable to parse certificates in {}", path))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", path).into());
+    }
+    Ok(certs)
+}
+
+/// Load the first private key in `path`, which may either be a PKCS8 or a RSA key.
+fn load_private_key(path: &str) -> Result<PrivateKey, GenericError> {
+    let invalid = || format!("Unable to parse private key in {}", path);
+    let mut keys = pkcs8_private_keys(&mut open(path)?).map_err(|_| invalid())?;
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut open(path)?).map_err(|_| invalid())?;
+    }
+    match keys.into_iter().next() {
+        Some(key) => Ok(key),
+        None => Err(format!("No private key found in {}", path).into()),
+    }
+}
+
+/// Build the TLS acceptor from `rpc.tls_cert_path` and `rpc.tls_key_path`.
+///
+/// If `rpc.tls_client_ca_path` is set, clients have to present a certificate signed by one of the
+/// CAs in it.
+pub fn tls_acceptor(rpc_config: &RpcConfig) -> Result<TlsAcceptor, GenericError> {
+    let cert_path = rpc_config
+        .tls_cert_path
+        .as_ref()
+        .ok_or("rpc.tls_cert_path is required for https:// network addresses")?;
+    let key_path = rpc_config
+        .tls_key_path
+        .as_ref()
+        .ok_or("rpc.tls_key_path is required for https:// network addresses")?;
+
+    let client_cert_verifier = match rpc_config.tls_client_ca_path.as_ref() {
+        Some(client_ca_path) => {
+            let mut client_roots = RootCertStore::empty();
+            client_roots
+                .add_pem_file(&mut open(client_ca_path)?)
+                .map_err(|_| format!("Unable to parse certificates in {}", client_ca_path))?;
+            AllowAnyAuthenticatedClient::new(client_roots)
+        }
+        None => NoClientAuth::new(),
+    };
+
+    let mut config = ServerConfig::new(client_cert_verifier);
+    config.set_single_cert(load_certs(cert_path)?, load_private_key(key_path)?)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Accept connections on `listener`, yielding them once the TLS handshake has completed.
+///
+/// Handshakes are performed concurrently, so that slow clients don't hold up others, and failed
+/// or timed out handshakes are only logged instead of shutting down the server. Errors while
+/// accepting (e.g. running out of file descriptors) are logged and retried after a short pause.
+/// The listener is closed once `shutdown` resolves or the returned stream has been dropped.
+pub fn incoming(
+    mut listener: TcpListener,
+    acceptor: TlsAcceptor,
+    mut shutdown: ShutdownSignal,
+) -> impl Stream<Item = Result<TlsStream<TcpStream>, io::Error>> {
+    let (sender, receiver) = mpsc::unbounded();
+    tokio::spawn(async move {
+        while !sender.is_closed() {
+            let accept = listener.accept();
+            futures::pin_mut!(accept);
+            let (stream, peer_addr) = match future::select(accept, &mut shutdown).await {
+                future::Either::Left((Ok(accepted), _)) => accepted,
+                future::Either::Left((Err(err), _)) => {
+                    warn!("Unable to accept connection: {}", err);
+                    tokio::time::delay_for(ACCEPT_ERROR_BACKOFF).await;
+                    continue;
+                }
+                future::Either::Right(_) => break,
+            };
+            let acceptor = acceptor.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(tls_stream)) => {
+                        let _ = sender.unbounded_send(Ok(tls_stream));
+                    }
+                    Ok(Err(err)) => debug!("TLS handshake with {} failed: {}", peer_addr, err),
+                    Err(_) => debug!("TLS handshake with {} timed out", peer_addr),
+                }
+            });
+        }
+    });
+    receiver
+}
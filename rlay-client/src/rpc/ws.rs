@@ -0,0 +1,159 @@
+//! JSON-RPC over WebSocket, serving the same methods as the HTTP RPC.
+use futures::prelude::*;
+use serde_json::Value;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+use url::Url;
+
+use super::auth::Permissions;
+use super::limits::{self, Client};
+use super::subscriptions::Connection;
+use super::{jsonrpc, GenericError, RpcContext};
+
+pub async fn run_ws_rpc(ctx: RpcContext, ws_network_address: String) -> Result<(), GenericError> {
+    let addr = ws_network_address
+        .parse::<Url>()
+        .expect("Unable to parse rpc.ws_network_address")
+        .to_socket_addrs()?
+        .next()
+        .expect("Unable to resolve rpc.ws_network_address");
+
+    let mut listener = TcpListener::bind(&addr).await?;
+
+    println!("Listening on ws://{}", addr);
+
+    let mut shutdown_requested = ctx.shutdown.requested();
+    loop {
+        let accept = listener.accept();
+        futures::pin_mut!(accept);
+        let (stream, peer_addr) = match future::select(accept, &mut shutdown_requested).await {
+            future::Either::Left((accepted, _)) => accepted?,
+            future::Either::Right(_) => return Ok(()),
+        };
+        tokio::spawn(handle_connection(ctx.clone(), stream, peer_addr));
+    }
+}
+
+async fn handle_connection(ctx: RpcContext, stream: TcpStream, peer_addr: SocketAddr) {
+    // The API key is checked during the handshake, and applies to all calls on the connection
+    let mut permissions: Option<Arc<Permissions>> = None;
+    let mut api_key: Option<String> = None;
+    let authenticate = |request: &Request, response: Response| {
+        let authorization = request
+            .headers()
+            .get(hyper::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        permissions = ctx.auth.authenticate(authorization);
+        api_key = authorization
+            .and_then(|authorization| ctx.auth.known_api_key(authorization))
+            .map(ToOwned::to_owned);
+        match permissions {
+            Some(_) => Ok(response),
+            None => Err(unauthorized_response()),
+        }
+    };
+    let ws_config = WebSocketConfig {
+        max_message_size: Some(ctx.limits.max_body_size()),
+        max_frame_size: Some(ctx.limits.max_body_size()),
+        ..WebSocketConfig::default()
+    };
+    let ws_stream = match tokio_tungstenite::accept_hdr_async_with_config(
+        stream,
+        authenticate,
+        Some(ws_config),
+    )
+    .await
+    {
+        Ok(ws_stream) => ws_stream,
+        Err(err) => {
+            debug!("WebSocket handshake with {} failed: {}", peer_addr, err);
+            return;
+        }
+    };
+    let permissions = permissions.expect("WebSocket handshake succeeded without authentication");
+    trace!("WebSocket connection from {} established", peer_addr);
+    let (ws_sink, mut ws_stream) = ws_stream.split();
+
+    // Responses and subscription notifications are sent through a channel, so that calls on the
+    // same connection can be handled concurrently.
+    let (connection, receiver) = Connection::new();
+    tokio::spawn(
+        receiver
+            .map(|message| Ok(Message::Text(message.to_string())))
+            .forward(ws_sink)
+            .map_err(move |err| debug!("Sending to {} failed: {}", peer_addr, err))
+            .map(|_| ()),
+    );
+
+    let mut shutdown_requested = ctx.shutdown.requested();
+    loop {
+        // After shutdown has been requested, calls that have already been received are still
+        // answered, but no new ones are accepted
+        let message = match future::select(ws_stream.next(), &mut shutdown_requested).await {
+            future::Either::Left((Some(message), _)) => message,
+            future::Either::Left((None, _)) | future::Either::Right(_) => break,
+        };
+        // the connection is closed if the client doesn't keep up with receiving its messages
+        if connection.is_closed() {
+            break;
+        }
+        let payload = match message {
+            Ok(Message::Text(text)) => text.into_bytes(),
+            Ok(Message::Binary(data)) => data,
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue,
+            Err(err) => {
+                debug!("Receiving from {} failed: {}", peer_addr, err);
+                break;
+            }
+        };
+
+        let client = match api_key.as_ref() {
+            Some(api_key) => Client::ApiKey(api_key),
+            None => Client::Ip(peer_addr.ip()),
+        };
+        if !ctx.limits.check_rate_limit(client) {
+            connection.send(jsonrpc::error_response(
+                Value::Null,
+                limits::rate_limited_error(),
+            ));
+            continue;
+        }
+
+        // calls count against `max_in_flight_requests` like HTTP requests, and waiting for a slot
+        // stops reading further calls of the connection in the meantime
+        let slot = match ctx.limits.acquire_slot().await {
+            Some(slot) => slot,
+            None => {
+                connection.send(jsonrpc::error_response(
+                    Value::Null,
+                    limits::overloaded_error(),
+                ));
+                continue;
+            }
+        };
+        let ctx = ctx.clone();
+        let connection = connection.clone();
+        let permissions = permissions.clone();
+        tokio::spawn(async move {
+            let _slot = slot;
+            if let Some(response) =
+                jsonrpc::handle_payload(&ctx, Some(&connection), &permissions, &payload).await
+            {
+                connection.send(response);
+            }
+        });
+    }
+    ctx.subscriptions.unsubscribe_connection(&connection);
+    trace!("WebSocket connection from {} closed", peer_addr);
+}
+
+fn unauthorized_response() -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some("Missing or invalid API key".to_owned()));
+    *response.status_mut() = hyper::StatusCode::UNAUTHORIZED;
+    response
+}
@@ -2,16 +2,21 @@
 extern crate serde_json;
 
 use assert_cmd::prelude::*;
+use futures::prelude::*;
 use hyper::Client;
 use rand::Rng;
 use rlay_jsonrpc_client::RlayClient;
-use rlay_ontology::ontology::Annotation;
+use rlay_ontology::ontology::{Annotation, Individual};
+use rlay_ontology::prelude::*;
+use rustc_hex::FromHex;
 use serde_json::Value;
 use std::path::Path;
 use std::process::Command;
 use tempfile::NamedTempFile;
 use testcontainers::*;
 use tokio::runtime::Runtime;
+use tokio_rustls::rustls;
+use tokio_tungstenite::tungstenite::Message;
 
 fn neo4j_container() -> images::generic::GenericImage {
     images::generic::GenericImage::new("neo4j:3.4.8")
@@ -27,17 +32,99 @@ fn set_neo4j_port(path: &Path, port: u32) {
     std::fs::write(path, new_contents).unwrap();
 }
 
+/// Sets a random port for the HTTP RPC, and the port following it for the WebSocket RPC.
 fn set_rpc_port(path: &Path) -> u32 {
     let mut rng = rand::thread_rng();
-    let port = rng.gen_range(35000, 36000);
+    let port = rng.gen_range(17500, 18000) * 2;
 
     let contents = std::fs::read_to_string(path).unwrap();
-    let new_contents = contents.replace("<RPC_PORT>", &port.to_string());
+    let new_contents = contents
+        .replace("<RPC_PORT>", &port.to_string())
+        .replace("<WS_RPC_PORT>", &(port + 1).to_string());
     std::fs::write(path, new_contents).unwrap();
 
     port
 }
 
+/// A running client, which is killed when dropped, so that failing tests don't leak it.
+struct ClientProcess {
+    child: std::process::Child,
+    rpc_port: u32,
+    scheme: &'static str,
+    has_websocket_rpc: bool,
+}
+
+impl ClientProcess {
+    fn url(&self) -> String {
+        format!("{}://127.0.0.1:{}", self.scheme, self.rpc_port)
+    }
+
+    /// Whether the client responds on `/health` (for HTTPS accepts connections), and the
+    /// WebSocket RPC accepts connections if it is configured.
+    fn is_up(&self) -> bool {
+        use std::io::{Read, Write};
+
+        if self.has_websocket_rpc
+            && std::net::TcpStream::connect(("127.0.0.1", self.rpc_port as u16 + 1)).is_err()
+        {
+            return false;
+        }
+        let mut stream = match std::net::TcpStream::connect(("127.0.0.1", self.rpc_port as u16)) {
+            Ok(stream) => stream,
+            Err(_) => return false,
+        };
+        if self.scheme == "https" {
+            return true;
+        }
+        let _ = stream.set_read_timeout(Some(std::time::Duration::from_secs(1)));
+        let mut response = String::new();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: 127.0.0.1\r\nConnection: close\r\n\r\n")
+            .and_then(|_| stream.read_to_string(&mut response))
+            .is_ok()
+            && response.starts_with("HTTP/1.1 200")
+    }
+}
+
+impl Drop for ClientProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Start the client with the config at `path` on a random RPC port, and wait until it is up.
+fn spawn_client(path: &Path) -> ClientProcess {
+    let config = std::fs::read_to_string(path).unwrap();
+    let rpc_port = set_rpc_port(path);
+    let scheme = if config.contains("https://127.0.0.1") {
+        "https"
+    } else {
+        "http"
+    };
+    let mut client_process = ClientProcess {
+        child: Command::cargo_bin("rlay-client")
+            .unwrap()
+            .args(&["client", "--config", path.to_str().unwrap()])
+            .spawn()
+            .unwrap(),
+        rpc_port,
+        scheme,
+        has_websocket_rpc: config.contains("<WS_RPC_PORT>"),
+    };
+
+    for _ in 0..300 {
+        if let Some(exit_status) = client_process.child.try_wait().unwrap() {
+            panic!("Client exited during startup with {}", exit_status);
+        }
+        if client_process.is_up() {
+            return client_process;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    panic!("Client didn't start up within 30 seconds");
+}
+
 #[test]
 fn get_health() {
     let _ = env_logger::try_init();
@@ -53,17 +140,9 @@ fn get_health() {
     let node = docker.run(neo4j_container());
 
     set_neo4j_port(config_file.path(), node.get_host_port(7474).unwrap().into());
-    let rpc_port = set_rpc_port(config_file.path());
-    let mut child_client = Command::cargo_bin("rlay-client")
-        .unwrap()
-        .args(&["client", "--config", config_file.path().to_str().unwrap()])
-        .spawn()
-        .unwrap();
-
-    // HACK: wait for client to start up
-    std::thread::sleep(std::time::Duration::new(3, 0));
+    let client_process = spawn_client(config_file.path());
 
-    let base_url = format!("http://127.0.0.1:{}", rpc_port);
+    let base_url = client_process.url();
     let rpc_result_value = rt.block_on(async {
         let client = Client::new();
         let res = client
@@ -79,8 +158,6 @@ fn get_health() {
     let expected_value = json!({"status": "healthy"});
 
     assert_eq!(expected_value, rpc_result_value);
-
-    child_client.kill().unwrap();
 }
 
 #[test]
@@ -98,25 +175,1208 @@ fn store_and_get_roundtrip() {
     let node = docker.run(neo4j_container());
 
     set_neo4j_port(config_file.path(), node.get_host_port(7474).unwrap().into());
-    let rpc_port = set_rpc_port(config_file.path());
-    let mut child_client = Command::cargo_bin("rlay-client")
-        .unwrap()
-        .args(&["client", "--config", config_file.path().to_str().unwrap()])
-        .spawn()
+    let client_process = spawn_client(config_file.path());
+
+    let client = RlayClient::new(&client_process.url());
+
+    let cid = rt
+        .block_on(client.store_entity(Annotation::default()))
         .unwrap();
+    println!("RETURNED CID: {}", cid);
+
+    let retrieved_entity = rt.block_on(client.get_entity(cid)).unwrap();
+
+    assert_eq!(Some(Annotation::default().into()), retrieved_entity);
+}
+
+#[test]
+fn store_and_get_roundtrip_memory() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+
+    let mut rt = Runtime::new().unwrap();
 
-    // HACK: wait for client to start up
-    std::thread::sleep(std::time::Duration::new(3, 0));
+    let client_process = spawn_client(config_file.path());
 
-    let client = RlayClient::new(&format!("http://127.0.0.1:{}", rpc_port));
+    let client = RlayClient::new(&client_process.url());
 
     let cid = rt
         .block_on(client.store_entity(Annotation::default()))
         .unwrap();
-    println!("RETURNED CID: {}", cid);
 
     let retrieved_entity = rt.block_on(client.get_entity(cid)).unwrap();
 
     assert_eq!(Some(Annotation::default().into()), retrieved_entity);
-    child_client.kill().unwrap();
+}
+
+fn post_jsonrpc(rt: &mut Runtime, url: &str, body: &str) -> (hyper::StatusCode, Option<Value>) {
+    rt.block_on(async {
+        let client = Client::new();
+        let req = hyper::Request::builder()
+            .method("POST")
+            .uri(url)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(hyper::Body::from(body.to_owned()))
+            .unwrap();
+        let res = client.request(req).await.unwrap();
+        let status = res.status();
+
+        let body: Vec<u8> = hyper::body::to_bytes(res).await.unwrap().to_vec();
+        let value = if body.is_empty() {
+            None
+        } else {
+            Some(serde_json::from_slice(&body).unwrap())
+        };
+        (status, value)
+    })
+}
+
+#[test]
+fn jsonrpc_batches_notifications_and_errors() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let url = client_process.url();
+
+    let (_, parse_error) = post_jsonrpc(&mut rt, &url, "{not json");
+    assert_eq!(
+        Some(&json!(-32700)),
+        parse_error.unwrap().pointer("/error/code")
+    );
+
+    let (_, empty_batch) = post_jsonrpc(&mut rt, &url, "[]");
+    assert_eq!(
+        Some(&json!(-32600)),
+        empty_batch.unwrap().pointer("/error/code")
+    );
+
+    let (_, unknown_method) = post_jsonrpc(
+        &mut rt,
+        &url,
+        r#"{"jsonrpc": "2.0", "id": 7, "method": "rlay_doesNotExist", "params": []}"#,
+    );
+    let unknown_method = unknown_method.unwrap();
+    assert_eq!(Some(&json!(7)), unknown_method.get("id"));
+    assert_eq!(Some(&json!(-32601)), unknown_method.pointer("/error/code"));
+
+    let (_, missing_params) = post_jsonrpc(
+        &mut rt,
+        &url,
+        r#"{"jsonrpc": "2.0", "id": "a", "method": "rlay_experimentalGetEntity"}"#,
+    );
+    let missing_params = missing_params.unwrap();
+    assert_eq!(Some(&json!("a")), missing_params.get("id"));
+    assert_eq!(Some(&json!(-32602)), missing_params.pointer("/error/code"));
+
+    let (status, notification) = post_jsonrpc(
+        &mut rt,
+        &url,
+        r#"{"jsonrpc": "2.0", "method": "rlay_version", "params": []}"#,
+    );
+    assert_eq!(hyper::StatusCode::NO_CONTENT, status);
+    assert_eq!(None, notification);
+
+    let (_, batch) = post_jsonrpc(
+        &mut rt,
+        &url,
+        r#"[
+            {"jsonrpc": "2.0", "id": 1, "method": "rlay_version", "params": []},
+            {"jsonrpc": "2.0", "method": "rlay_version", "params": []},
+            {"jsonrpc": "2.0", "id": 2, "method": "rlay_experimentalGetEntity", "params": [1]},
+            42
+        ]"#,
+    );
+    let batch = batch.unwrap();
+    let batch = batch.as_array().unwrap();
+    assert_eq!(3, batch.len());
+    assert_eq!(Some(&json!(1)), batch[0].get("id"));
+    assert!(batch[0].get("result").is_some());
+    assert_eq!(Some(&json!(2)), batch[1].get("id"));
+    assert_eq!(Some(&json!(-32602)), batch[1].pointer("/error/code"));
+    assert_eq!(Some(&Value::Null), batch[2].get("id"));
+    assert_eq!(Some(&json!(-32600)), batch[2].pointer("/error/code"));
+}
+
+#[test]
+fn jsonrpc_over_websocket() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let ws_url = format!("ws://127.0.0.1:{}", client_process.rpc_port + 1);
+    let responses: Vec<Value> = rt.block_on(async {
+        let (mut ws_stream, _) = tokio_tungstenite::connect_async(ws_url.as_str())
+            .await
+            .unwrap();
+
+        let store_request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "rlay_experimentalStoreEntity",
+            "params": [FormatWeb3::<Entity>(Annotation::default().into())],
+        });
+        ws_stream
+            .send(Message::Text(store_request.to_string()))
+            .await
+            .unwrap();
+        let store_response = ws_stream.next().await.unwrap().unwrap();
+        let store_response: Value =
+            serde_json::from_str(store_response.to_text().unwrap()).unwrap();
+
+        let get_request = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "rlay_experimentalGetEntity",
+            "params": [store_response["result"]],
+        });
+        ws_stream
+            .send(Message::Text(get_request.to_string()))
+            .await
+            .unwrap();
+        let get_response = ws_stream.next().await.unwrap().unwrap();
+        let get_response: Value = serde_json::from_str(get_response.to_text().unwrap()).unwrap();
+
+        vec![store_response, get_response]
+    });
+
+    assert_eq!(Some(&json!(1)), responses[0].get("id"));
+    assert_eq!(Some(&json!(2)), responses[1].get("id"));
+    let retrieved_entity: FormatWeb3<Entity> =
+        serde_json::from_value(responses[1]["result"].clone()).unwrap();
+    let expected_entity: Entity = Annotation::default().into();
+    assert_eq!(expected_entity, retrieved_entity.0);
+}
+
+#[test]
+fn subscribe_to_new_entities_over_websocket() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let client = RlayClient::new(&client_process.url());
+    let ws_url = format!("ws://127.0.0.1:{}", client_process.rpc_port + 1);
+    let (subscription_id, notification) = rt.block_on(async {
+        let (mut ws_stream, _) = tokio_tungstenite::connect_async(ws_url.as_str())
+            .await
+            .unwrap();
+
+        let subscribe_request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "rlay_subscribe",
+            "params": ["newEntities", {"kind": "Annotation"}],
+        });
+        ws_stream
+            .send(Message::Text(subscribe_request.to_string()))
+            .await
+            .unwrap();
+        let subscribe_response = ws_stream.next().await.unwrap().unwrap();
+        let subscribe_response: Value =
+            serde_json::from_str(subscribe_response.to_text().unwrap()).unwrap();
+
+        // Not matched by the subscription
+        client.store_entity(Individual::default()).await.unwrap();
+        client.store_entity(Annotation::default()).await.unwrap();
+
+        let notification = ws_stream.next().await.unwrap().unwrap();
+        let notification: Value = serde_json::from_str(notification.to_text().unwrap()).unwrap();
+
+        (subscribe_response["result"].clone(), notification)
+    });
+
+    assert_eq!(
+        Some(&json!("rlay_subscription")),
+        notification.get("method")
+    );
+    assert_eq!(
+        Some(&subscription_id),
+        notification.pointer("/params/subscription")
+    );
+    let pushed_entity: FormatWeb3<Entity> =
+        serde_json::from_value(notification["params"]["result"].clone()).unwrap();
+    let expected_entity: Entity = Annotation::default().into();
+    assert_eq!(expected_entity, pushed_entity.0);
+}
+
+#[cfg(unix)]
+#[test]
+fn jsonrpc_over_ipc() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let ipc_path = data_dir.path().join("rlay.ipc");
+    let contents = std::fs::read_to_string(config_file.path()).unwrap();
+    let new_contents = contents.replace(
+        "[rpc]\n",
+        &format!("[rpc]\nipc_path = \"{}\"\n", ipc_path.display()),
+    ) + "\n[rpc.limits]\nmax_body_size = 1024\n";
+    std::fs::write(config_file.path(), new_contents).unwrap();
+
+    let _client_process = spawn_client(config_file.path());
+    // the IPC socket is bound after the HTTP RPC has started
+    for _ in 0..50 {
+        if ipc_path.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    let mut stream = UnixStream::connect(&ipc_path).unwrap();
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "rlay_experimentalGetEntityCid",
+        "params": [FormatWeb3::<Entity>(Annotation::default().into())],
+    });
+    stream
+        .write_all(format!("{}\n", request).as_bytes())
+        .unwrap();
+
+    let mut response_line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut response_line)
+        .unwrap();
+    let response: Value = serde_json::from_str(&response_line).unwrap();
+
+    assert_eq!(Some(&json!(1)), response.get("id"));
+    assert_eq!(
+        Some(&json!(
+            "0x019580031b2088868a58d3aac6d2558a29b3b8cacf3c9788364f57a3470158283121a15dcae0"
+        )),
+        response.get("result")
+    );
+
+    let oversized_line = format!("\"{}\"\n", "a".repeat(2000));
+    stream.write_all(oversized_line.as_bytes()).unwrap();
+    let mut reader = BufReader::new(&stream);
+    let mut response_line = String::new();
+    reader.read_line(&mut response_line).unwrap();
+    let response: Value = serde_json::from_str(&response_line).unwrap();
+    assert_eq!(Some(&json!(-32600)), response.pointer("/error/code"));
+    // the connection is closed after an oversized line
+    response_line.clear();
+    assert_eq!(0, reader.read_line(&mut response_line).unwrap());
+}
+
+#[test]
+fn rest_store_and_get_roundtrip() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let base_url = client_process.url();
+    let entity = FormatWeb3::<Entity>(Annotation::default().into());
+    rt.block_on(async {
+        let client = Client::new();
+
+        let req = hyper::Request::builder()
+            .method("POST")
+            .uri(format!("{}/entities", base_url))
+            .body(hyper::Body::from(serde_json::to_string(&entity).unwrap()))
+            .unwrap();
+        let res = client.request(req).await.unwrap();
+        assert_eq!(hyper::StatusCode::CREATED, res.status());
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        let cid: String = serde_json::from_slice(&body).unwrap();
+
+        let res = client
+            .get(format!("{}/entities/{}", base_url, cid).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(hyper::StatusCode::OK, res.status());
+        let etag = res.headers()[hyper::header::ETAG].clone();
+        assert_eq!(format!("\"{}\"", cid), etag.to_str().unwrap());
+        assert!(res.headers()[hyper::header::CACHE_CONTROL]
+            .to_str()
+            .unwrap()
+            .contains("immutable"));
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        let retrieved_entity: FormatWeb3<Entity> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(entity.0, retrieved_entity.0);
+
+        let req = hyper::Request::builder()
+            .uri(format!("{}/entities/{}", base_url, cid))
+            .header(hyper::header::IF_NONE_MATCH, etag)
+            .body(hyper::Body::empty())
+            .unwrap();
+        let res = client.request(req).await.unwrap();
+        assert_eq!(hyper::StatusCode::NOT_MODIFIED, res.status());
+
+        let res = client
+            .get(
+                format!("{}/entities?kind=Annotation", base_url)
+                    .parse()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        let cids: Vec<String> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vec![cid.clone()], cids);
+
+        let unknown_cid =
+            "0x019580031b20ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+        let res = client
+            .get(
+                format!("{}/entities/{}", base_url, unknown_cid)
+                    .parse()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(hyper::StatusCode::NOT_FOUND, res.status());
+
+        let req = hyper::Request::builder()
+            .uri(format!("{}/entities/{}", base_url, unknown_cid))
+            .header(hyper::header::IF_NONE_MATCH, "*")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let res = client.request(req).await.unwrap();
+        assert_eq!(hyper::StatusCode::NOT_FOUND, res.status());
+    });
+}
+
+#[test]
+fn graphql_resolves_nested_entities() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let rpc_url = client_process.url();
+    rt.block_on(async {
+        let rlay_client = RlayClient::new(&rpc_url);
+        let ann_cid = rlay_client
+            .store_entity(Annotation::default())
+            .await
+            .unwrap();
+        let ind_cid = rlay_client
+            .store_entity(Individual {
+                annotations: vec![ann_cid[2..].from_hex().unwrap()],
+                ..Individual::default()
+            })
+            .await
+            .unwrap();
+
+        let query = format!(
+            r#"{{ entity(cid: "{}") {{ ... on Individual {{ cid annotations {{ ... on Annotation {{ cid }} }} }} }} }}"#,
+            ind_cid
+        );
+        let req = hyper::Request::builder()
+            .method("POST")
+            .uri(format!("{}/graphql", rpc_url))
+            .body(hyper::Body::from(json!({ "query": query }).to_string()))
+            .unwrap();
+        let res = Client::new().request(req).await.unwrap();
+        assert_eq!(hyper::StatusCode::OK, res.status());
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        let response: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(
+            json!({
+                "data": {
+                    "entity": {
+                        "cid": ind_cid,
+                        "annotations": [{ "cid": ann_cid }],
+                    }
+                }
+            }),
+            response
+        );
+    });
+}
+
+#[test]
+fn graphql_rejects_queries_resolving_too_many_entities() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+    let mut config = std::fs::read_to_string(config_file.path()).unwrap();
+    config.push_str("\n[rpc.limits]\nmax_entities_per_call = 2\n");
+    std::fs::write(config_file.path(), config).unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let rpc_url = client_process.url();
+    rt.block_on(async {
+        let rlay_client = RlayClient::new(&rpc_url);
+        let ann_cid = rlay_client
+            .store_entity(Annotation::default())
+            .await
+            .unwrap();
+        // every reference is resolved on its own, even if it references the same entity
+        let ind_cid = rlay_client
+            .store_entity(Individual {
+                annotations: vec![ann_cid[2..].from_hex().unwrap(); 3],
+                ..Individual::default()
+            })
+            .await
+            .unwrap();
+
+        let query = format!(
+            r#"{{ entity(cid: "{}") {{ ... on Individual {{ cid annotations {{ ... on Annotation {{ cid }} }} }} }} }}"#,
+            ind_cid
+        );
+        let req = hyper::Request::builder()
+            .method("POST")
+            .uri(format!("{}/graphql", rpc_url))
+            .body(hyper::Body::from(json!({ "query": query }).to_string()))
+            .unwrap();
+        let res = Client::new().request(req).await.unwrap();
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        let response: Value = serde_json::from_slice(&body).unwrap();
+
+        let message = response["errors"][0]["message"].as_str().unwrap();
+        assert!(
+            message.contains("more than 2 entities"),
+            "unexpected error: {}",
+            message
+        );
+    });
+}
+
+#[test]
+fn rpc_discover_describes_methods() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let url = client_process.url();
+    let (_, response) = post_jsonrpc(
+        &mut rt,
+        &url,
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "rpc.discover", "params": []}"#,
+    );
+    let document = response.unwrap()["result"].clone();
+
+    let is_supported = |method_name: &str| {
+        document["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|method| method["name"] == method_name)
+            .map(|method| method["x-supported"].clone())
+    };
+    assert_eq!(
+        Some(json!(true)),
+        is_supported("rlay_experimentalGetEntity")
+    );
+    // The memory backend doesn't support Cypher queries
+    assert_eq!(
+        Some(json!(false)),
+        is_supported("rlay_experimentalNeo4jQuery")
+    );
+    assert_eq!(
+        Some(&json!("Annotation")),
+        document.pointer("/components/schemas/Annotation/properties/type/const")
+    );
+}
+
+#[test]
+fn api_keys_restrict_methods_by_role() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+    let mut config = std::fs::read_to_string(config_file.path()).unwrap();
+    config.push_str(
+        r#"
+[rpc.auth]
+[[rpc.auth.keys]]
+key = "reader-key"
+role = "read-only"
+[[rpc.auth.keys]]
+key = "writer-key"
+role = "writer"
+"#,
+    );
+    std::fs::write(config_file.path(), config).unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let url = client_process.url();
+    let store_call = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "rlay_experimentalStoreEntity",
+        "params": [FormatWeb3::<Entity>(Annotation::default().into())],
+    })
+    .to_string();
+    let call_with_key = |rt: &mut Runtime, key: Option<&str>| {
+        rt.block_on(async {
+            let mut req = hyper::Request::builder()
+                .method("POST")
+                .uri(&url)
+                .header(hyper::header::CONTENT_TYPE, "application/json");
+            if let Some(key) = key {
+                req = req.header(hyper::header::AUTHORIZATION, format!("Bearer {}", key));
+            }
+            let req = req.body(hyper::Body::from(store_call.clone())).unwrap();
+            let res = Client::new().request(req).await.unwrap();
+            let status = res.status();
+            let body = hyper::body::to_bytes(res).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).ok())
+        })
+    };
+
+    let (status, _) = call_with_key(&mut rt, None);
+    assert_eq!(hyper::StatusCode::UNAUTHORIZED, status);
+    let (status, _) = call_with_key(&mut rt, Some("unknown-key"));
+    assert_eq!(hyper::StatusCode::UNAUTHORIZED, status);
+
+    let (_, reader_response) = call_with_key(&mut rt, Some("reader-key"));
+    assert_eq!(
+        Some(&json!(-32001)),
+        reader_response.unwrap().pointer("/error/code")
+    );
+
+    let (_, writer_response) = call_with_key(&mut rt, Some("writer-key"));
+    assert!(writer_response.unwrap().get("result").is_some());
+}
+
+#[test]
+fn cors_preflight_and_allowed_origins() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+    let config = std::fs::read_to_string(config_file.path()).unwrap();
+    let config = config.replacen(
+        "[rpc]",
+        "[rpc]\ncors_allowed_origins = [\"https://dashboard.example.com\"]",
+        1,
+    );
+    std::fs::write(config_file.path(), config).unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let url = client_process.url();
+    let request_with_origin = |rt: &mut Runtime, method: &str, origin: &str| {
+        rt.block_on(async {
+            let req = hyper::Request::builder()
+                .method(method)
+                .uri(&url)
+                .header(hyper::header::ORIGIN, origin)
+                .header(hyper::header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .body(hyper::Body::from(
+                    r#"{"jsonrpc":"2.0","id":1,"method":"rlay_version","params":[]}"#,
+                ))
+                .unwrap();
+            Client::new().request(req).await.unwrap()
+        })
+    };
+
+    let preflight = request_with_origin(&mut rt, "OPTIONS", "https://dashboard.example.com");
+    assert_eq!(hyper::StatusCode::NO_CONTENT, preflight.status());
+    let preflight_headers = preflight.headers();
+    assert_eq!(
+        "https://dashboard.example.com",
+        preflight_headers[hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN]
+    );
+    assert!(
+        preflight_headers[hyper::header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("Authorization")
+    );
+
+    let call = request_with_origin(&mut rt, "POST", "https://dashboard.example.com");
+    assert_eq!(hyper::StatusCode::OK, call.status());
+    assert_eq!(
+        "https://dashboard.example.com",
+        call.headers()[hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN]
+    );
+
+    let other_origin = request_with_origin(&mut rt, "OPTIONS", "https://other.example.com");
+    assert!(other_origin
+        .headers()
+        .get(hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .is_none());
+}
+
+#[test]
+fn limits_reject_excessive_requests() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+    let mut config = std::fs::read_to_string(config_file.path()).unwrap();
+    config.push_str(
+        r#"
+[rpc.limits]
+max_body_size = 4096
+max_entities_per_call = 2
+[rpc.limits.rate_limit]
+requests_per_second = 0.1
+burst = 3
+"#,
+    );
+    std::fs::write(config_file.path(), config).unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let url = client_process.url();
+
+    let oversized_call = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "rlay_experimentalGetEntities",
+        "params": [vec!["0x00"; 2000]],
+    })
+    .to_string();
+    let (status, _) = post_jsonrpc(&mut rt, &url, &oversized_call);
+    assert_eq!(hyper::StatusCode::PAYLOAD_TOO_LARGE, status);
+
+    let too_many_entities_call = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "rlay_experimentalGetEntities",
+        "params": [["0x00", "0x01", "0x02"]],
+    })
+    .to_string();
+    let (status, response) = post_jsonrpc(&mut rt, &url, &too_many_entities_call);
+    assert_eq!(hyper::StatusCode::OK, status);
+    assert_eq!(
+        Some(&json!(-32602)),
+        response.unwrap().pointer("/error/code")
+    );
+
+    // the burst has been used up by the previous requests
+    let version_call = r#"{"jsonrpc":"2.0","id":1,"method":"rlay_version","params":[]}"#;
+    let (status, _) = post_jsonrpc(&mut rt, &url, version_call);
+    assert_eq!(hyper::StatusCode::OK, status);
+    let (status, _) = post_jsonrpc(&mut rt, &url, version_call);
+    assert_eq!(hyper::StatusCode::TOO_MANY_REQUESTS, status);
+
+    // only GET requests to the health endpoints are exempt from the rate limit
+    let health_url = format!("{}/health", url);
+    let (status, _) = post_jsonrpc(&mut rt, &health_url, version_call);
+    assert_eq!(hyper::StatusCode::TOO_MANY_REQUESTS, status);
+    let status = rt.block_on(async {
+        let res = Client::new()
+            .get(health_url.parse().unwrap())
+            .await
+            .unwrap();
+        res.status()
+    });
+    assert_eq!(hyper::StatusCode::OK, status);
+}
+
+#[test]
+fn metrics_count_rpc_calls() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let base_url = client_process.url();
+    let (status, _) = post_jsonrpc(
+        &mut rt,
+        &base_url,
+        r#"{"jsonrpc":"2.0","id":1,"method":"rlay_version","params":[]}"#,
+    );
+    assert_eq!(hyper::StatusCode::OK, status);
+
+    let metrics = rt.block_on(async {
+        let client = Client::new();
+        let res = client
+            .get(format!("{}/entities/0x00", base_url).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(hyper::StatusCode::NOT_FOUND, res.status());
+
+        let res = client
+            .get(format!("{}/metrics", base_url).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(hyper::StatusCode::OK, res.status());
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    });
+
+    assert!(metrics.contains(r#"rlay_rpc_requests_total{api="jsonrpc",method="rlay_version"} 1"#));
+    assert!(metrics.contains(r#"rlay_rpc_errors_total{api="rest",method="GET /entities/{cid}"} 1"#));
+    assert!(metrics.contains("rlay_rpc_request_duration_seconds_bucket"));
+    assert!(metrics.contains(
+        r#"rlay_backend_query_duration_seconds_count{backend="memory",query="get_entities"} 1"#
+    ));
+}
+
+#[test]
+fn readiness_reports_unreachable_proxy_target() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    let plugins_dir = tempfile::tempdir().unwrap();
+    let config_template =
+        std::fs::read_to_string("./tests/rlay.config.memory.toml.test_template").unwrap();
+    // nothing listens on port 1, so the proxy target is unreachable
+    let config = format!(
+        "plugins_path = {:?}\n{}",
+        plugins_dir.path().to_str().unwrap(),
+        config_template.replacen(
+            "[rpc]",
+            "[rpc]\nproxy_target_network_address = \"http://127.0.0.1:1\"",
+            1
+        )
+    );
+    std::fs::write(config_file.path(), config).unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let base_url = client_process.url();
+    let (status, readiness) = rt.block_on(async {
+        let res = Client::new()
+            .get(format!("{}/health/ready", base_url).parse().unwrap())
+            .await
+            .unwrap();
+        let status = res.status();
+        let body = hyper::body::to_bytes(res).await.unwrap();
+        (status, serde_json::from_slice::<Value>(&body).unwrap())
+    });
+
+    assert_eq!(hyper::StatusCode::SERVICE_UNAVAILABLE, status);
+    assert_eq!(json!("not_ready"), readiness["status"]);
+    assert_eq!(json!("up"), readiness["components"]["backend"]["status"]);
+    assert_eq!(json!("up"), readiness["components"]["plugins"]["status"]);
+    assert_eq!(
+        json!("down"),
+        readiness["components"]["proxy_target"]["status"]
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn shuts_down_gracefully_on_sigterm() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    std::fs::copy(
+        "./tests/rlay.config.memory.toml.test_template",
+        config_file.path(),
+    )
+    .unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let mut client_process = spawn_client(config_file.path());
+
+    let url = client_process.url();
+    let (status, _) = post_jsonrpc(
+        &mut rt,
+        &url,
+        r#"{"jsonrpc":"2.0","id":1,"method":"rlay_version","params":[]}"#,
+    );
+    assert_eq!(hyper::StatusCode::OK, status);
+
+    let kill_status = Command::new("kill")
+        .args(&["-TERM", &client_process.child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(kill_status.success());
+
+    let mut exit_status = None;
+    for _ in 0..50 {
+        exit_status = client_process.child.try_wait().unwrap();
+        if exit_status.is_some() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    match exit_status {
+        Some(exit_status) => assert!(exit_status.success()),
+        None => panic!("Client didn't shut down within 5 seconds of SIGTERM"),
+    }
+}
+
+#[test]
+fn reload_plugins_with_missing_plugins_dir() {
+    let _ = env_logger::try_init();
+    let config_file = NamedTempFile::new().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let missing_plugins_dir = data_dir.path().join("plugins");
+    let config_template =
+        std::fs::read_to_string("./tests/rlay.config.memory.toml.test_template").unwrap();
+    let config = format!(
+        "plugins_path = {:?}\n{}",
+        missing_plugins_dir.to_str().unwrap(),
+        config_template
+    );
+    std::fs::write(config_file.path(), config).unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let url = client_process.url();
+    let (_, resolved) = post_jsonrpc(
+        &mut rt,
+        &url,
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "rlay_experimentalResolveEntities", "params": [[]]}"#,
+    );
+    assert_eq!(json!({}), resolved.unwrap()["result"]);
+
+    std::fs::create_dir(&missing_plugins_dir).unwrap();
+    let (_, reloaded) = post_jsonrpc(
+        &mut rt,
+        &url,
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "rlay_reloadPlugins", "params": []}"#,
+    );
+    assert_eq!(json!([]), reloaded.unwrap()["result"]);
+}
+
+/// Build the example filter plugin, returning the path of its library.
+fn build_example_plugin() -> std::path::PathBuf {
+    let status = Command::new(env!("CARGO"))
+        .args(&["build", "-p", "example-filter-plugin"])
+        .status()
+        .unwrap();
+    assert!(status.success(), "Building example-filter-plugin failed");
+
+    // the test binary is located in `target/<profile>/deps`
+    let profile_dir = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(Path::parent)
+        .unwrap()
+        .to_owned();
+    profile_dir.join(format!(
+        "{}example_filter_plugin{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    ))
+}
+
+/// Install a plugin atomically, by copying it next to the plugins directory and moving it in.
+fn install_plugin(plugin_path: &Path, plugins_dir: &Path) {
+    let file_name = plugin_path.file_name().unwrap();
+    let staging_path = plugins_dir.with_file_name(file_name);
+    std::fs::copy(plugin_path, &staging_path).unwrap();
+    std::fs::rename(&staging_path, plugins_dir.join(file_name)).unwrap();
+}
+
+#[test]
+fn reload_plugins_skips_invalid_files_and_replaces_changed_plugins() {
+    let _ = env_logger::try_init();
+    let plugin_path = build_example_plugin();
+    let config_file = NamedTempFile::new().unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let plugins_dir = data_dir.path().join("plugins");
+    std::fs::create_dir(&plugins_dir).unwrap();
+    std::fs::write(plugins_dir.join("README.txt"), "not a plugin").unwrap();
+    let config_template =
+        std::fs::read_to_string("./tests/rlay.config.memory.toml.test_template").unwrap();
+    let config = format!(
+        "plugins_path = {:?}\n{}",
+        plugins_dir.to_str().unwrap(),
+        config_template
+    );
+    std::fs::write(config_file.path(), config).unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let url = client_process.url();
+    let reload_call =
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "rlay_reloadPlugins", "params": []}"#;
+    let (_, reloaded) = post_jsonrpc(&mut rt, &url, reload_call);
+    assert_eq!(json!([]), reloaded.unwrap()["result"]);
+
+    // moving a plugin into the directory reloads the plugins
+    install_plugin(&plugin_path, &plugins_dir);
+    assert_eq!(
+        Some(json!(["whitelist"])),
+        wait_for_filters(&mut rt, &url, json!(["whitelist"]))
+    );
+
+    // replacing the plugin while it is loaded
+    install_plugin(&plugin_path, &plugins_dir);
+    let (_, reloaded) = post_jsonrpc(&mut rt, &url, reload_call);
+    assert_eq!(json!(["whitelist"]), reloaded.unwrap()["result"]);
+
+    let annotation = FormatWeb3::<Entity>(Annotation::default().into());
+    let (_, stored) = post_jsonrpc(
+        &mut rt,
+        &url,
+        &json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "rlay_experimentalStoreEntity",
+            "params": [annotation],
+        })
+        .to_string(),
+    );
+    let cid = stored.unwrap()["result"].as_str().unwrap().to_owned();
+    let (_, filtered) = post_jsonrpc(
+        &mut rt,
+        &url,
+        &json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "rlay_experimentalResolveEntity",
+            "params": [cid, { "filters": [{ "filter": "whitelist", "params": { "whitelist": [] } }] }],
+        })
+        .to_string(),
+    );
+    assert_eq!(json!([]), filtered.unwrap()["result"][&cid]);
+
+    // removing the plugin from the directory unloads it
+    let plugin_file_name = plugin_path.file_name().unwrap();
+    std::fs::remove_file(plugins_dir.join(plugin_file_name)).unwrap();
+    assert_eq!(Some(json!([])), wait_for_filters(&mut rt, &url, json!([])));
+}
+
+/// Poll `/health/ready` until the loaded filters are `expected`, returning the last loaded filters.
+fn wait_for_filters(rt: &mut Runtime, url: &str, expected: Value) -> Option<Value> {
+    let mut filters = None;
+    for _ in 0..50 {
+        let ready = rt.block_on(async {
+            let res = Client::new()
+                .get(format!("{}/health/ready", url).parse().unwrap())
+                .await
+                .unwrap();
+            let body = hyper::body::to_bytes(res).await.unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()
+        });
+        filters = ready.pointer("/components/plugins/filters").cloned();
+        if filters.as_ref() == Some(&expected) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    filters
+}
+
+/// Certificates and keys for a server on `localhost` and a client, both signed by the same CA.
+struct TestCertificates {
+    dir: tempfile::TempDir,
+    ca_der: Vec<u8>,
+    client_cert_der: Vec<u8>,
+    client_key_der: Vec<u8>,
+}
+
+impl TestCertificates {
+    fn generate() -> Self {
+        let mut ca_params = rcgen::CertificateParams::new(vec![]);
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+        let server = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let client = rcgen::generate_simple_self_signed(vec!["client".to_owned()]).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+        std::fs::write(
+            dir.path().join("server.pem"),
+            server.serialize_pem_with_signer(&ca).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("server.key"),
+            server.serialize_private_key_pem(),
+        )
+        .unwrap();
+
+        Self {
+            ca_der: ca.serialize_der().unwrap(),
+            client_cert_der: client.serialize_der_with_signer(&ca).unwrap(),
+            client_key_der: client.serialize_private_key_der(),
+            dir,
+        }
+    }
+
+    fn path(&self, file_name: &str) -> String {
+        self.dir.path().join(file_name).to_str().unwrap().to_owned()
+    }
+
+    fn client_config(&self, with_client_cert: bool) -> rustls::ClientConfig {
+        let mut config = rustls::ClientConfig::new();
+        config
+            .root_store
+            .add(&rustls::Certificate(self.ca_der.clone()))
+            .unwrap();
+        if with_client_cert {
+            config
+                .set_single_client_cert(
+                    vec![rustls::Certificate(self.client_cert_der.clone())],
+                    rustls::PrivateKey(self.client_key_der.clone()),
+                )
+                .unwrap();
+        }
+        config
+    }
+}
+
+/// POST a JSON-RPC call via HTTPS, returning the raw HTTP response.
+fn post_jsonrpc_tls(
+    rt: &mut Runtime,
+    port: u32,
+    client_config: rustls::ClientConfig,
+    body: &str,
+) -> std::io::Result<String> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    rt.block_on(async {
+        let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client_config));
+        let stream = tokio::net::TcpStream::connect(("127.0.0.1", port as u16)).await?;
+        let domain = tokio_rustls::webpki::DNSNameRef::try_from_ascii_str("localhost").unwrap();
+        let mut tls_stream = connector.connect(domain, stream).await?;
+
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        tls_stream.write_all(request.as_bytes()).await?;
+        let mut response = String::new();
+        tls_stream.read_to_string(&mut response).await?;
+        Ok(response)
+    })
+}
+
+#[test]
+fn jsonrpc_over_https_with_client_certificates() {
+    let _ = env_logger::try_init();
+    let certs = TestCertificates::generate();
+    let config_file = NamedTempFile::new().unwrap();
+    let config_template =
+        std::fs::read_to_string("./tests/rlay.config.memory.toml.test_template").unwrap();
+    let config = config_template
+        .replace(
+            "http://127.0.0.1:<RPC_PORT>",
+            "https://127.0.0.1:<RPC_PORT>",
+        )
+        .replace(
+            "[rpc]\n",
+            &format!(
+                "[rpc]\ntls_cert_path = {:?}\ntls_key_path = {:?}\ntls_client_ca_path = {:?}\n",
+                certs.path("server.pem"),
+                certs.path("server.key"),
+                certs.path("ca.pem"),
+            ),
+        );
+    std::fs::write(config_file.path(), config).unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+
+    let client_process = spawn_client(config_file.path());
+
+    let call = r#"{"jsonrpc":"2.0","id":1,"method":"rlay_version","params":[]}"#;
+    let response = post_jsonrpc_tls(
+        &mut rt,
+        client_process.rpc_port,
+        certs.client_config(true),
+        call,
+    )
+    .unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "unexpected response: {}",
+        response
+    );
+    assert!(response.contains(r#""id":1"#));
+
+    // without a client certificate, the server rejects the connection during the handshake
+    let rejected = post_jsonrpc_tls(
+        &mut rt,
+        client_process.rpc_port,
+        certs.client_config(false),
+        call,
+    );
+    assert!(
+        rejected
+            .map(|response| !response.starts_with("HTTP/1.1 200"))
+            .unwrap_or(true),
+        "call without client certificate succeeded"
+    );
 }